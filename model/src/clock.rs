@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io,
+    path::Path,
+    process::Command,
+    sync::{Arc, PoisonError, RwLock},
+    time::SystemTime,
+};
+
+use once_cell::sync::Lazy;
+
+use crate::{resource::ClockKind, tai::TaiTime};
+
+/// PTP version advertised in node clocks.
+const PTP_VERSION: &str = "IEEE1588-2008";
+
+/// Grandmaster identity advertised before one has been seen.
+const UNKNOWN_GMID: &str = "00-00-00-00-00-00-00-00";
+
+/// Largest offset from the master, in nanoseconds, at which a PTP clock is
+/// considered locked.
+const LOCK_THRESHOLD_NS: i64 = 1000;
+
+/// Default path of the ptp4l management socket.
+pub const DEFAULT_PTP4L_SOCKET: &str = "/var/run/ptp4l";
+
+// Source of `TaiTime::now`, or the system clock if none is set
+static CLOCK_SOURCE: Lazy<RwLock<Option<Arc<dyn ClockSource>>>> = Lazy::new(RwLock::default);
+
+/// A timebase for resource versions and activation times.
+pub trait ClockSource: fmt::Debug + Send + Sync {
+    /// The current time in the TAI timebase.
+    fn now(&self) -> TaiTime;
+
+    /// How the clock is described in the `clocks` of a node.
+    fn kind(&self) -> ClockKind;
+
+    /// Update the status described by `kind`. This may block.
+    fn refresh(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Use a clock source for every subsequent `TaiTime::now`.
+///
+/// The clock source is global to the process rather than held by a model or
+/// node, so that resource versions from every model share one timebase. The
+/// last source set replaces any earlier one.
+pub fn set_clock_source(source: Arc<dyn ClockSource>) {
+    *CLOCK_SOURCE.write().unwrap_or_else(PoisonError::into_inner) = Some(source);
+}
+
+pub(crate) fn now() -> TaiTime {
+    match &*CLOCK_SOURCE.read().unwrap_or_else(PoisonError::into_inner) {
+        Some(source) => source.now(),
+        None => SystemClock.now(),
+    }
+}
+
+/// The system clock, converted from UTC using the leap second table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> TaiTime {
+        TaiTime::from(SystemTime::now())
+    }
+
+    fn kind(&self) -> ClockKind {
+        ClockKind::Internal
+    }
+}
+
+/// A clock synchronised by linuxptp.
+///
+/// Time is read from a PTP hardware clock (PHC) when one is given, which
+/// ptp4l keeps in the TAI timebase, and otherwise from the system clock as
+/// kept by phc2sys. Lock status and grandmaster identity are queried with
+/// `pmc` over the ptp4l management socket.
+#[derive(Debug)]
+pub struct PtpClock {
+    phc: Option<File>,
+    socket: String,
+    status: RwLock<ClockKind>,
+}
+
+impl PtpClock {
+    /// A clock whose status is read from the ptp4l management socket at
+    /// `socket`, such as [`DEFAULT_PTP4L_SOCKET`].
+    #[must_use]
+    pub fn new<S: Into<String>>(socket: S) -> Self {
+        PtpClock {
+            phc: None,
+            socket: socket.into(),
+            status: RwLock::new(ClockKind::Ptp {
+                traceable: false,
+                version: PTP_VERSION.to_owned(),
+                gmid: UNKNOWN_GMID.to_owned(),
+                locked: false,
+            }),
+        }
+    }
+
+    /// Read time from a PHC device, such as `/dev/ptp0`.
+    pub fn phc<P: AsRef<Path>>(mut self, device: P) -> io::Result<Self> {
+        self.phc = Some(File::open(device)?);
+        Ok(self)
+    }
+
+    fn query(&self) -> io::Result<ClockKind> {
+        let output = Command::new("pmc")
+            .args(["-u", "-b", "0", "-s", &self.socket])
+            .args(["GET TIME_STATUS_NP", "GET TIME_PROPERTIES_DATA_SET"])
+            .output()?;
+
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("pmc exited with {}", output.status),
+            ));
+        }
+
+        parse_pmc(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+impl ClockSource for PtpClock {
+    fn now(&self) -> TaiTime {
+        match &self.phc {
+            Some(phc) => read_phc(phc).unwrap_or_else(|_| SystemClock.now()),
+            None => SystemClock.now(),
+        }
+    }
+
+    fn kind(&self) -> ClockKind {
+        self.status
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn refresh(&self) -> io::Result<()> {
+        let status = self.query();
+
+        let mut current = self.status.write().unwrap_or_else(PoisonError::into_inner);
+        match status {
+            Ok(status) => *current = status,
+            Err(err) => {
+                // Keep the last grandmaster but no longer claim to be locked
+                if let ClockKind::Ptp { locked, .. } = &mut *current {
+                    *locked = false;
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_phc(phc: &File) -> io::Result<TaiTime> {
+    use std::os::unix::io::AsRawFd;
+
+    // Dynamic POSIX clock of an open PHC device, as FD_TO_CLOCKID in the kernel
+    let clock_id = ((!phc.as_raw_fd()) << 3) | 3;
+
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    // SAFETY: the kernel only writes to the timespec passed by reference
+    if unsafe { libc::clock_gettime(clock_id as libc::clockid_t, &mut time) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(TaiTime::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_phc(_phc: &File) -> io::Result<TaiTime> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "PHC devices are only supported on Linux",
+    ))
+}
+
+// Clock status from the TIME_STATUS_NP and TIME_PROPERTIES_DATA_SET
+// responses printed by pmc
+fn parse_pmc(output: &str) -> io::Result<ClockKind> {
+    let fields: HashMap<&str, &str> = output
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            Some((words.next()?, words.next()?))
+        })
+        .collect();
+
+    let invalid = |field: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("pmc response is missing {}", field),
+        )
+    };
+
+    let gm_present = *fields
+        .get("gmPresent")
+        .ok_or_else(|| invalid("gmPresent"))?
+        == "true";
+    let offset: i64 = fields
+        .get("master_offset")
+        .and_then(|offset| offset.parse().ok())
+        .ok_or_else(|| invalid("master_offset"))?;
+    let gmid = fields
+        .get("gmIdentity")
+        .and_then(|identity| format_gmid(identity))
+        .ok_or_else(|| invalid("gmIdentity"))?;
+
+    Ok(ClockKind::Ptp {
+        traceable: fields.get("timeTraceable") == Some(&"1"),
+        version: PTP_VERSION.to_owned(),
+        gmid,
+        locked: gm_present && offset.abs() <= LOCK_THRESHOLD_NS,
+    })
+}
+
+// Convert a clock identity from the "001122.fffe.334455" form used by
+// linuxptp to the "00-11-22-ff-fe-33-44-55" form used by IS-04
+fn format_gmid(identity: &str) -> Option<String> {
+    let hex: String = identity.chars().filter(|c| *c != '.').collect();
+    if hex.len() != 16 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let octets: Vec<&str> = (0..16).step_by(2).map(|i| &hex[i..i + 2]).collect();
+
+    Some(octets.join("-").to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Output of `pmc -u -b 0 'GET TIME_STATUS_NP' 'GET TIME_PROPERTIES_DATA_SET'`
+    // from a slave of a traceable grandmaster
+    const LOCKED: &str = "\
+sending: GET TIME_STATUS_NP
+\t507c6f.fffe.1f3a2b-0 seq 0 RESPONSE MANAGEMENT TIME_STATUS_NP
+\t\tmaster_offset              -18
+\t\tingress_time               1608547311464003456
+\t\tcumulativeScaledRateOffset +0.000000000
+\t\tscaledLastGmPhaseChange    0
+\t\tgmTimeBaseIndicator        0
+\t\tlastGmPhaseChange          0x0000'0000000000000000.0000
+\t\tgmPresent                  true
+\t\tgmIdentity                 001B21.FFFE.4C5D6E
+sending: GET TIME_PROPERTIES_DATA_SET
+\t507c6f.fffe.1f3a2b-0 seq 1 RESPONSE MANAGEMENT TIME_PROPERTIES_DATA_SET
+\t\tcurrentUtcOffset      37
+\t\tleap61                0
+\t\tleap59                0
+\t\tcurrentUtcOffsetValid 1
+\t\tptpTimescale          1
+\t\ttimeTraceable         1
+\t\tfrequencyTraceable    1
+\t\ttimeSource            0x20
+";
+
+    // Output from a free-running ptp4l that has not seen a grandmaster
+    const FREE_RUNNING: &str = "\
+sending: GET TIME_STATUS_NP
+\t507c6f.fffe.1f3a2b-0 seq 0 RESPONSE MANAGEMENT TIME_STATUS_NP
+\t\tmaster_offset              0
+\t\tingress_time               0
+\t\tcumulativeScaledRateOffset +0.000000000
+\t\tscaledLastGmPhaseChange    0
+\t\tgmTimeBaseIndicator        0
+\t\tlastGmPhaseChange          0x0000'0000000000000000.0000
+\t\tgmPresent                  false
+\t\tgmIdentity                 507c6f.fffe.1f3a2b
+sending: GET TIME_PROPERTIES_DATA_SET
+\t507c6f.fffe.1f3a2b-0 seq 1 RESPONSE MANAGEMENT TIME_PROPERTIES_DATA_SET
+\t\tcurrentUtcOffset      37
+\t\tleap61                0
+\t\tleap59                0
+\t\tcurrentUtcOffsetValid 0
+\t\tptpTimescale          1
+\t\ttimeTraceable         0
+\t\tfrequencyTraceable    0
+\t\ttimeSource            0xa0
+";
+
+    fn ptp(traceable: bool, gmid: &str, locked: bool) -> ClockKind {
+        ClockKind::Ptp {
+            traceable,
+            version: PTP_VERSION.to_owned(),
+            gmid: gmid.to_owned(),
+            locked,
+        }
+    }
+
+    #[test]
+    fn parse_pmc_locked() {
+        assert_eq!(
+            parse_pmc(LOCKED).unwrap(),
+            ptp(true, "00-1b-21-ff-fe-4c-5d-6e", true)
+        );
+    }
+
+    #[test]
+    fn parse_pmc_free_running() {
+        assert_eq!(
+            parse_pmc(FREE_RUNNING).unwrap(),
+            ptp(false, "50-7c-6f-ff-fe-1f-3a-2b", false)
+        );
+    }
+
+    #[test]
+    fn parse_pmc_offset_beyond_threshold() {
+        let output = LOCKED.replace("-18", "-1001");
+
+        assert_eq!(
+            parse_pmc(&output).unwrap(),
+            ptp(true, "00-1b-21-ff-fe-4c-5d-6e", false)
+        );
+    }
+
+    #[test]
+    fn parse_pmc_missing_fields() {
+        for field in &["gmPresent", "master_offset", "gmIdentity"] {
+            let output: String = LOCKED
+                .lines()
+                .filter(|line| !line.contains(field))
+                .map(|line| format!("{}\n", line))
+                .collect();
+
+            let err = parse_pmc(&output).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains(field), "{}", err);
+        }
+    }
+
+    #[test]
+    fn parse_pmc_no_response() {
+        // pmc prints only what it sent when ptp4l does not answer
+        let output = "sending: GET TIME_STATUS_NP\nsending: GET TIME_PROPERTIES_DATA_SET\n";
+
+        assert!(parse_pmc(output).is_err());
+    }
+
+    #[test]
+    fn format_gmid_valid() {
+        assert_eq!(
+            format_gmid("001122.fffe.334455").as_deref(),
+            Some("00-11-22-ff-fe-33-44-55")
+        );
+        assert_eq!(
+            format_gmid("AABBCC.FFFE.DDEEFF").as_deref(),
+            Some("aa-bb-cc-ff-fe-dd-ee-ff")
+        );
+    }
+
+    #[test]
+    fn format_gmid_invalid() {
+        for identity in &[
+            "",
+            "001122.fffe.33445",
+            "001122.fffe.33445566",
+            "001122.fffe.33445g",
+            "00-11-22-ff-fe-33-44-55",
+        ] {
+            assert_eq!(format_gmid(identity), None, "{:?}", identity);
+        }
+    }
+}
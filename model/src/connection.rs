@@ -0,0 +1,93 @@
+use std::{fmt, net::IpAddr};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivationMode {
+    ActivateImmediate,
+    ActivateScheduledAbsolute,
+    ActivateScheduledRelative,
+}
+
+impl fmt::Display for ActivationMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActivationMode::ActivateImmediate => write!(f, "activate_immediate"),
+            ActivationMode::ActivateScheduledAbsolute => write!(f, "activate_scheduled_absolute"),
+            ActivationMode::ActivateScheduledRelative => write!(f, "activate_scheduled_relative"),
+        }
+    }
+}
+
+/// Activation of staged parameters. Times use the IS-05 "secs:nanos" TAI format.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Activation {
+    pub mode: Option<ActivationMode>,
+    pub requested_time: Option<String>,
+    pub activation_time: Option<String>,
+}
+
+/// IS-05 RTP transport parameters for one leg of a sender.
+///
+/// A sender using SMPTE 2022-7 redundancy has two legs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RtpSenderTransportParams {
+    pub source_ip: Option<IpAddr>,
+    pub destination_ip: Option<IpAddr>,
+    pub source_port: Option<u16>,
+    pub destination_port: Option<u16>,
+    pub rtp_enabled: bool,
+}
+
+/// IS-05 RTP transport parameters for one leg of a receiver.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RtpReceiverTransportParams {
+    pub source_ip: Option<IpAddr>,
+    pub multicast_ip: Option<IpAddr>,
+    pub interface_ip: Option<IpAddr>,
+    pub destination_port: Option<u16>,
+    pub rtp_enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SenderConnection {
+    pub receiver_id: Option<Uuid>,
+    pub master_enable: bool,
+    pub activation: Activation,
+    pub transport_params: Vec<RtpSenderTransportParams>,
+}
+
+impl SenderConnection {
+    /// Whether the connection is enabled and has at least one leg sending RTP.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.master_enable && self.transport_params.iter().any(|leg| leg.rtp_enabled)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiverConnection {
+    pub sender_id: Option<Uuid>,
+    pub master_enable: bool,
+    pub activation: Activation,
+    /// SDP transport file the transport parameters were staged from.
+    pub transport_file: Option<String>,
+    pub transport_params: Vec<RtpReceiverTransportParams>,
+}
+
+impl Default for ReceiverConnection {
+    fn default() -> Self {
+        Self {
+            sender_id: None,
+            master_enable: false,
+            activation: Activation::default(),
+            transport_file: None,
+            transport_params: vec![RtpReceiverTransportParams {
+                rtp_enabled: true,
+                ..RtpReceiverTransportParams::default()
+            }],
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::{resource::ResourceType, version::APIVersion};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A string is not an API version of the form `v<major>.<minor>`.
+    InvalidVersion(String),
+    /// A resource cannot be rendered at an API version.
+    UnsupportedVersion(APIVersion),
+    /// A resource uses a format, transport or device type URN which an API
+    /// version does not allow.
+    UnsupportedUrn(String, APIVersion),
+    /// No resource of a type has the id.
+    NotFound(ResourceType, Uuid),
+    /// The model has no node to represent itself.
+    MissingSelf,
+    /// The resource of a type with the id, which another resource belongs to,
+    /// is not in the model.
+    MissingParent(ResourceType, Uuid),
+    /// A source or flow would be its own ancestor through its parents.
+    ParentCycle(ResourceType, Uuid),
+    /// A video or audio flow has no essence parameters to describe it.
+    MissingEssence(Uuid),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidVersion(version) => write!(f, "Invalid API version \"{}\"", version),
+            Error::UnsupportedVersion(api) => write!(f, "Unsupported API version {}", api),
+            Error::UnsupportedUrn(urn, api) => write!(f, "{} is not allowed in {}", urn, api),
+            Error::NotFound(type_, id) => write!(f, "No {} with id {}", type_, id),
+            Error::MissingSelf => write!(f, "Missing self resource"),
+            Error::MissingParent(type_, id) => write!(f, "Parent {} {} does not exist", type_, id),
+            Error::ParentCycle(type_, id) => {
+                write!(f, "Parents of {} {} form a cycle", type_, id)
+            }
+            Error::MissingEssence(id) => write!(f, "Flow {} is missing essence parameters", id),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
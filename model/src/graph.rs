@@ -0,0 +1,201 @@
+// Traversal of the parent relationships between sources and between flows
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use uuid::Uuid;
+
+// Every ancestor of a resource, nearest first
+//
+// Parents which are not in the map are included but not followed, as they may
+// belong to resources held elsewhere.
+pub(crate) fn ancestors<T, F>(resources: &HashMap<Uuid, T>, id: &Uuid, parents_of: F) -> Vec<Uuid>
+where
+    F: Fn(&T) -> &[Uuid],
+{
+    let mut found = Vec::new();
+    let mut seen = HashSet::new();
+    seen.insert(*id);
+
+    let mut queue: VecDeque<Uuid> = VecDeque::new();
+    queue.push_back(*id);
+
+    while let Some(current) = queue.pop_front() {
+        let parents = match resources.get(&current) {
+            Some(resource) => parents_of(resource),
+            None => continue,
+        };
+
+        for parent in parents {
+            if seen.insert(*parent) {
+                found.push(*parent);
+                queue.push_back(*parent);
+            }
+        }
+    }
+
+    found
+}
+
+// Children of each resource which has any
+fn children<T, F>(resources: &HashMap<Uuid, T>, parents_of: F) -> HashMap<Uuid, Vec<Uuid>>
+where
+    F: Fn(&T) -> &[Uuid],
+{
+    let mut children: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for (child, resource) in resources {
+        for parent in parents_of(resource) {
+            children.entry(*parent).or_default().push(*child);
+        }
+    }
+
+    children
+}
+
+// Every descendant of any of the resources, nearest first
+pub(crate) fn descendants<T, F>(
+    resources: &HashMap<Uuid, T>,
+    ids: &[Uuid],
+    parents_of: F,
+) -> Vec<Uuid>
+where
+    F: Fn(&T) -> &[Uuid],
+{
+    let children = children(resources, parents_of);
+
+    let mut found = Vec::new();
+    let mut seen: HashSet<Uuid> = ids.iter().copied().collect();
+    let mut queue: VecDeque<Uuid> = ids.iter().copied().collect();
+
+    while let Some(current) = queue.pop_front() {
+        for child in children.get(&current).map_or(&[][..], Vec::as_slice) {
+            if seen.insert(*child) {
+                found.push(*child);
+                queue.push_back(*child);
+            }
+        }
+    }
+
+    found
+}
+
+// A resource which is its own ancestor, if any
+//
+// Resources are removed from the graph once none of their parents remain, so
+// those left over are on or below a cycle.
+pub(crate) fn find_cycle<T, F>(resources: &HashMap<Uuid, T>, parents_of: F) -> Option<Uuid>
+where
+    F: Fn(&T) -> &[Uuid],
+{
+    let children = children(resources, &parents_of);
+
+    let mut remaining: HashMap<Uuid, usize> = resources
+        .iter()
+        .map(|(id, resource)| {
+            let parents = parents_of(resource)
+                .iter()
+                .filter(|parent| resources.contains_key(parent))
+                .count();
+            (*id, parents)
+        })
+        .collect();
+
+    let mut queue: VecDeque<Uuid> = remaining
+        .iter()
+        .filter(|(_, parents)| **parents == 0)
+        .map(|(id, _)| *id)
+        .collect();
+
+    while let Some(current) = queue.pop_front() {
+        remaining.remove(&current);
+
+        for child in children.get(&current).map_or(&[][..], Vec::as_slice) {
+            if let Some(parents) = remaining.get_mut(child) {
+                *parents -= 1;
+                if *parents == 0 {
+                    queue.push_back(*child);
+                }
+            }
+        }
+    }
+
+    remaining.into_keys().find(|id| {
+        let parents = resources
+            .get(id)
+            .map_or(&[][..], |resource| parents_of(resource));
+        creates_cycle(resources, id, parents, &parents_of)
+    })
+}
+
+// Whether giving a resource the parents would make it its own ancestor
+pub(crate) fn creates_cycle<T, F>(
+    resources: &HashMap<Uuid, T>,
+    id: &Uuid,
+    parents: &[Uuid],
+    parents_of: F,
+) -> bool
+where
+    F: Fn(&T) -> &[Uuid],
+{
+    if parents.contains(id) {
+        return true;
+    }
+
+    parents
+        .iter()
+        .any(|parent| ancestors(resources, parent, &parents_of).contains(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Resources given as their parents, with ids 1 to n
+    fn graph(parents: &[&[u128]]) -> HashMap<Uuid, Vec<Uuid>> {
+        parents
+            .iter()
+            .enumerate()
+            .map(|(i, parents)| {
+                let parents = parents.iter().map(|p| Uuid::from_u128(*p)).collect();
+                (Uuid::from_u128(i as u128 + 1), parents)
+            })
+            .collect()
+    }
+
+    fn parents(parents: &Vec<Uuid>) -> &[Uuid] {
+        parents
+    }
+
+    fn ids(ids: &[u128]) -> Vec<Uuid> {
+        ids.iter().map(|id| Uuid::from_u128(*id)).collect()
+    }
+
+    #[test]
+    fn descendants_of_several() {
+        // 1 -> 2 -> 4, 3 -> 4 -> 5
+        let resources = graph(&[&[], &[1], &[], &[2, 3], &[4]]);
+
+        assert_eq!(
+            descendants(&resources, &ids(&[1]), parents),
+            ids(&[2, 4, 5])
+        );
+
+        let mut found = descendants(&resources, &ids(&[2, 3]), parents);
+        found.sort();
+        assert_eq!(found, ids(&[4, 5]));
+    }
+
+    #[test]
+    fn cycles() {
+        // Parents outside the map are not followed
+        let resources = graph(&[&[9], &[1], &[1, 2]]);
+        assert_eq!(find_cycle(&resources, parents), None);
+
+        // 5 is below the cycle of 2, 3 and 4 but not part of it
+        let resources = graph(&[&[], &[1, 4], &[2], &[3], &[4]]);
+        let cycle = find_cycle(&resources, parents).unwrap();
+        assert!(ids(&[2, 3, 4]).contains(&cycle));
+
+        let resources = graph(&[&[], &[2]]);
+        assert_eq!(find_cycle(&resources, parents), Some(Uuid::from_u128(2)));
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::Bound,
+};
+
+use uuid::Uuid;
+
+use crate::tai::TaiTime;
+
+/// Timestamp a resource is ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Created,
+    Updated,
+}
+
+/// Creation and update times of the resources of one type.
+///
+/// Timestamps are strictly increasing within an index, so a timestamp
+/// identifies a single resource and can be used as a paging cursor.
+#[derive(Debug, Default)]
+pub struct TimeIndex {
+    times: HashMap<Uuid, (TaiTime, TaiTime)>,
+    created: BTreeSet<(TaiTime, Uuid)>,
+    updated: BTreeSet<(TaiTime, Uuid)>,
+    latest: Option<TaiTime>,
+}
+
+impl TimeIndex {
+    // Record a resource as inserted or updated now
+    pub(crate) fn touch(&mut self, id: Uuid) {
+        let now = self.next_timestamp();
+
+        let created = match self.times.get(&id) {
+            Some(&(created, updated)) => {
+                self.updated.remove(&(updated, id));
+                created
+            }
+            None => {
+                self.created.insert((now, id));
+                now
+            }
+        };
+
+        self.times.insert(id, (created, now));
+        self.updated.insert((now, id));
+    }
+
+    pub(crate) fn remove(&mut self, id: &Uuid) {
+        if let Some((created, updated)) = self.times.remove(id) {
+            self.created.remove(&(created, *id));
+            self.updated.remove(&(updated, *id));
+        }
+    }
+
+    fn next_timestamp(&mut self) -> TaiTime {
+        let now = TaiTime::next_version();
+
+        self.latest = Some(now);
+        now
+    }
+
+    #[must_use]
+    pub fn created(&self, id: &Uuid) -> Option<TaiTime> {
+        self.times.get(id).map(|(created, _)| *created)
+    }
+
+    #[must_use]
+    pub fn updated(&self, id: &Uuid) -> Option<TaiTime> {
+        self.times.get(id).map(|(_, updated)| *updated)
+    }
+
+    /// Most recent timestamp handed out by the index.
+    #[must_use]
+    pub fn latest(&self) -> Option<TaiTime> {
+        self.latest
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// Resources with a timestamp after `since` and up to and including
+    /// `until`, oldest first.
+    pub fn range(
+        &self,
+        order: Order,
+        since: Option<TaiTime>,
+        until: Option<TaiTime>,
+    ) -> impl DoubleEndedIterator<Item = (TaiTime, Uuid)> + '_ {
+        let set = match order {
+            Order::Created => &self.created,
+            Order::Updated => &self.updated,
+        };
+
+        let last = Uuid::from_u128(u128::MAX);
+        let lower = match since {
+            Some(since) => Bound::Excluded((since, last)),
+            None => Bound::Unbounded,
+        };
+        let upper = match until {
+            Some(until) => Bound::Included((until, last)),
+            None => Bound::Unbounded,
+        };
+
+        // An empty range would otherwise panic
+        let empty = matches!((since, until), (Some(since), Some(until)) if since >= until);
+        let range = if empty {
+            set.range((
+                Bound::Unbounded,
+                Bound::Excluded((TaiTime::new(0, 0), Uuid::nil())),
+            ))
+        } else {
+            set.range((lower, upper))
+        };
+
+        range.copied()
+    }
+}
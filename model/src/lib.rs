@@ -1,14 +1,60 @@
+pub mod clock;
+pub mod connection;
+pub mod error;
+mod graph;
+pub mod index;
+pub mod persist;
 pub mod resource;
+pub mod rql;
+pub mod sdp;
 pub mod tai;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 pub mod version;
 
-use std::collections::HashMap;
+pub use error::{Error, Result};
 
-use resource::{Device, Flow, Node, Receiver, ResourceBundle, Sender, Source};
-use tokio::sync::{RwLock, RwLockReadGuard};
+use std::collections::{HashMap, HashSet};
+
+use index::TimeIndex;
+use resource::{
+    Device, Flow, Node, Receiver, Resource, ResourceBundle, ResourceType, Sender, Source,
+};
+use serde_json::Value;
+use tai::TaiTime;
+use tokio::sync::{broadcast, RwLock, RwLockReadGuard};
 use uuid::Uuid;
+use version::APIVersion;
+
+/// Number of change events buffered for each model subscriber.
+const EVENT_CAPACITY: usize = 1024;
+
+/// Change to a resource in the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelEvent {
+    /// The resource was inserted or updated.
+    Changed(ResourceType, Uuid),
+    /// The resource was removed.
+    Removed(ResourceType, Uuid),
+}
+
+impl ModelEvent {
+    #[must_use]
+    pub fn resource_type(&self) -> ResourceType {
+        match self {
+            ModelEvent::Changed(type_, _) | ModelEvent::Removed(type_, _) => *type_,
+        }
+    }
+
+    #[must_use]
+    pub fn id(&self) -> Uuid {
+        match self {
+            ModelEvent::Changed(_, id) | ModelEvent::Removed(_, id) => *id,
+        }
+    }
+}
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Model {
     // IS-04 resources
     nodes: RwLock<HashMap<Uuid, Node>>,
@@ -17,6 +63,98 @@ pub struct Model {
     flows: RwLock<HashMap<Uuid, Flow>>,
     senders: RwLock<HashMap<Uuid, Sender>>,
     receivers: RwLock<HashMap<Uuid, Receiver>>,
+
+    // Creation and update times of each resource type
+    node_times: RwLock<TimeIndex>,
+    device_times: RwLock<TimeIndex>,
+    source_times: RwLock<TimeIndex>,
+    flow_times: RwLock<TimeIndex>,
+    sender_times: RwLock<TimeIndex>,
+    receiver_times: RwLock<TimeIndex>,
+
+    events: broadcast::Sender<ModelEvent>,
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Model::from_maps(
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        )
+    }
+}
+
+// Index every resource in a map as created now
+fn index_all<T>(resources: &HashMap<Uuid, T>) -> RwLock<TimeIndex> {
+    let mut index = TimeIndex::default();
+    for id in resources.keys() {
+        index.touch(*id);
+    }
+
+    RwLock::new(index)
+}
+
+// Render every resource in a map
+fn render_map<R: Resource>(
+    resources: &HashMap<Uuid, R>,
+    api: &APIVersion,
+) -> Result<HashMap<Uuid, Value>> {
+    resources
+        .iter()
+        .map(|(id, resource)| Ok((*id, resource.render(api)?)))
+        .collect()
+}
+
+// Render a resource in a map, if it exists
+fn render_one<R: Resource>(
+    resources: &HashMap<Uuid, R>,
+    api: &APIVersion,
+    id: &Uuid,
+) -> Result<Value> {
+    resources
+        .get(id)
+        .ok_or(Error::NotFound(R::RESOURCE_TYPE, *id))?
+        .render(api)
+}
+
+// Remove the resources matching a predicate, returning their ids
+fn remove_where<T, F>(resources: &mut HashMap<Uuid, T>, f: F) -> Vec<Uuid>
+where
+    F: Fn(&T) -> bool,
+{
+    let removed: Vec<Uuid> = resources
+        .iter()
+        .filter(|(_, resource)| f(resource))
+        .map(|(id, _)| *id)
+        .collect();
+
+    for id in &removed {
+        resources.remove(id);
+    }
+
+    removed
+}
+
+fn source_parents(source: &Source) -> &[Uuid] {
+    &source.parents
+}
+
+fn flow_parents(flow: &Flow) -> &[Uuid] {
+    &flow.parents
+}
+
+// Whether a source would be its own ancestor
+fn source_cycle(sources: &HashMap<Uuid, Source>, source: &Source) -> bool {
+    graph::creates_cycle(sources, &source.core.id, &source.parents, source_parents)
+}
+
+// Whether a flow would be its own ancestor
+fn flow_cycle(flows: &HashMap<Uuid, Flow>, flow: &Flow) -> bool {
+    graph::creates_cycle(flows, &flow.core.id, &flow.parents, flow_parents)
 }
 
 impl Model {
@@ -25,8 +163,9 @@ impl Model {
         Model::default()
     }
 
-    #[must_use]
-    pub fn from_resources(resource_bundle: ResourceBundle) -> Self {
+    /// Create a model holding a bundle of resources, failing if the parents
+    /// of a source or flow make it its own ancestor.
+    pub fn from_resources(resource_bundle: ResourceBundle) -> Result<Self> {
         // Fold each resource vec into a hashmap
         let nodes = resource_bundle
             .nodes
@@ -80,13 +219,40 @@ impl Model {
                     map
                 });
 
+        if let Some(id) = graph::find_cycle(&sources, source_parents) {
+            return Err(Error::ParentCycle(ResourceType::Source, id));
+        }
+        if let Some(id) = graph::find_cycle(&flows, flow_parents) {
+            return Err(Error::ParentCycle(ResourceType::Flow, id));
+        }
+
+        Ok(Model::from_maps(
+            nodes, devices, sources, flows, senders, receivers,
+        ))
+    }
+
+    fn from_maps(
+        nodes: HashMap<Uuid, Node>,
+        devices: HashMap<Uuid, Device>,
+        sources: HashMap<Uuid, Source>,
+        flows: HashMap<Uuid, Flow>,
+        senders: HashMap<Uuid, Sender>,
+        receivers: HashMap<Uuid, Receiver>,
+    ) -> Self {
         Self {
+            node_times: index_all(&nodes),
+            device_times: index_all(&devices),
+            source_times: index_all(&sources),
+            flow_times: index_all(&flows),
+            sender_times: index_all(&senders),
+            receiver_times: index_all(&receivers),
             nodes: RwLock::new(nodes),
             devices: RwLock::new(devices),
             sources: RwLock::new(sources),
             flows: RwLock::new(flows),
             senders: RwLock::new(senders),
             receivers: RwLock::new(receivers),
+            events: broadcast::channel(EVENT_CAPACITY).0,
         }
     }
 
@@ -120,36 +286,484 @@ impl Model {
         self.flows.read().await
     }
 
-    pub async fn insert_node(&self, node: Node) -> Option<()> {
+    /// Creation and update times of the resources of a type.
+    ///
+    /// Take the resource map before the index when holding both.
+    pub async fn index(&self, type_: ResourceType) -> RwLockReadGuard<'_, TimeIndex> {
+        self.times(type_).read().await
+    }
+
+    fn times(&self, type_: ResourceType) -> &RwLock<TimeIndex> {
+        match type_ {
+            ResourceType::Node => &self.node_times,
+            ResourceType::Device => &self.device_times,
+            ResourceType::Source => &self.source_times,
+            ResourceType::Flow => &self.flow_times,
+            ResourceType::Sender => &self.sender_times,
+            ResourceType::Receiver => &self.receiver_times,
+        }
+    }
+
+    /// Render a resource at an API version.
+    pub async fn render(&self, type_: ResourceType, api: &APIVersion, id: &Uuid) -> Result<Value> {
+        match type_ {
+            ResourceType::Node => render_one(&*self.nodes().await, api, id),
+            ResourceType::Device => render_one(&*self.devices().await, api, id),
+            ResourceType::Source => render_one(&*self.sources().await, api, id),
+            ResourceType::Flow => render_one(&*self.flows().await, api, id),
+            ResourceType::Sender => render_one(&*self.senders().await, api, id),
+            ResourceType::Receiver => render_one(&*self.receivers().await, api, id),
+        }
+    }
+
+    /// Id of the node a node model describes, which is its only node.
+    pub async fn self_id(&self) -> Result<Uuid> {
+        self.nodes()
+            .await
+            .keys()
+            .next()
+            .copied()
+            .ok_or(Error::MissingSelf)
+    }
+
+    /// Render every resource of a type at an API version.
+    pub async fn snapshot(
+        &self,
+        type_: ResourceType,
+        api: &APIVersion,
+    ) -> Result<HashMap<Uuid, Value>> {
+        match type_ {
+            ResourceType::Node => render_map(&*self.nodes().await, api),
+            ResourceType::Device => render_map(&*self.devices().await, api),
+            ResourceType::Source => render_map(&*self.sources().await, api),
+            ResourceType::Flow => render_map(&*self.flows().await, api),
+            ResourceType::Sender => render_map(&*self.senders().await, api),
+            ResourceType::Receiver => render_map(&*self.receivers().await, api),
+        }
+    }
+
+    /// Resources of a type which match an RQL query, rendered at an API
+    /// version.
+    pub async fn select(
+        &self,
+        type_: ResourceType,
+        api: &APIVersion,
+        query: &rql::Query,
+    ) -> Result<Vec<Value>> {
+        Ok(self
+            .snapshot(type_, api)
+            .await?
+            .into_values()
+            .filter(|resource| query.matches(resource))
+            .collect())
+    }
+
+    /// Check that the parents of a source would not make it its own ancestor.
+    ///
+    /// Parents need not be in the model, as they may be held by another
+    /// node or registry.
+    pub async fn check_source_parents(&self, source: &Source) -> Result<()> {
+        if source_cycle(&*self.sources().await, source) {
+            return Err(Error::ParentCycle(ResourceType::Source, source.core.id));
+        }
+
+        Ok(())
+    }
+
+    /// Check that the parents of a flow would not make it its own ancestor.
+    ///
+    /// Parents need not be in the model, as they may be held by another
+    /// node or registry.
+    pub async fn check_flow_parents(&self, flow: &Flow) -> Result<()> {
+        if flow_cycle(&*self.flows().await, flow) {
+            return Err(Error::ParentCycle(ResourceType::Flow, flow.core.id));
+        }
+
+        Ok(())
+    }
+
+    /// Ids of every source a source is derived from, nearest first.
+    pub async fn source_ancestors(&self, id: &Uuid) -> Vec<Uuid> {
+        graph::ancestors(&*self.sources().await, id, source_parents)
+    }
+
+    /// Ids of every source derived from a source, nearest first.
+    pub async fn source_descendants(&self, id: &Uuid) -> Vec<Uuid> {
+        graph::descendants(&*self.sources().await, &[*id], source_parents)
+    }
+
+    /// Ids of every flow a flow is derived from, nearest first.
+    pub async fn flow_ancestors(&self, id: &Uuid) -> Vec<Uuid> {
+        graph::ancestors(&*self.flows().await, id, flow_parents)
+    }
+
+    /// Ids of every flow derived from a flow, nearest first.
+    pub async fn flow_descendants(&self, id: &Uuid) -> Vec<Uuid> {
+        graph::descendants(&*self.flows().await, &[*id], flow_parents)
+    }
+
+    /// Ids of the senders carrying a source, either directly or through
+    /// sources and flows derived from it.
+    pub async fn senders_carrying(&self, source_id: &Uuid) -> Vec<Uuid> {
+        let mut sources: HashSet<Uuid> = self
+            .source_descendants(source_id)
+            .await
+            .into_iter()
+            .collect();
+        sources.insert(*source_id);
+
+        let flows = self.flows().await;
+        let direct: Vec<Uuid> = flows
+            .values()
+            .filter(|flow| sources.contains(&flow.source_id))
+            .map(|flow| flow.core.id)
+            .collect();
+
+        let mut carried: HashSet<Uuid> = graph::descendants(&flows, &direct, flow_parents)
+            .into_iter()
+            .collect();
+        carried.extend(direct);
+        drop(flows);
+
+        self.senders()
+            .await
+            .values()
+            .filter(|sender| carried.contains(&sender.flow_id))
+            .map(|sender| sender.core.id)
+            .collect()
+    }
+
+    /// Receive an event for every subsequent change to the model.
+    pub fn subscribe(&self) -> broadcast::Receiver<ModelEvent> {
+        self.events.subscribe()
+    }
+
+    fn notify(&self, event: ModelEvent) {
+        // Nobody may be listening
+        let _ = self.events.send(event);
+    }
+
+    pub async fn insert_node(&self, node: Node) -> Result<()> {
         let mut nodes = self.nodes.write().await;
+        self.node_times.write().await.touch(node.core.id);
+        self.notify(ModelEvent::Changed(ResourceType::Node, node.core.id));
         nodes.insert(node.core.id, node);
 
-        Some(())
+        Ok(())
     }
 
-    pub async fn insert_device(&self, device: Device) -> Option<()> {
+    pub async fn insert_device(&self, device: Device) -> Result<()> {
         // Check node id in model
         let nodes = self.nodes.read().await;
         if !nodes.contains_key(&device.node_id) {
-            return None;
+            return Err(Error::MissingParent(ResourceType::Node, device.node_id));
         }
 
         let mut devices = self.devices.write().await;
+        self.device_times.write().await.touch(device.core.id);
+        self.notify(ModelEvent::Changed(ResourceType::Device, device.core.id));
         devices.insert(device.core.id, device);
 
-        Some(())
+        Ok(())
     }
 
-    pub async fn insert_receiver(&self, receiver: Receiver) -> Option<()> {
+    pub async fn insert_receiver(&self, receiver: Receiver) -> Result<()> {
         // Check device id in model
         let devices = self.devices.read().await;
         if !devices.contains_key(&receiver.device_id) {
-            return None;
+            return Err(Error::MissingParent(
+                ResourceType::Device,
+                receiver.device_id,
+            ));
         }
 
         let mut receivers = self.receivers.write().await;
+        self.receiver_times.write().await.touch(receiver.core.id);
+        self.notify(ModelEvent::Changed(
+            ResourceType::Receiver,
+            receiver.core.id,
+        ));
         receivers.insert(receiver.core.id, receiver);
 
+        Ok(())
+    }
+
+    pub async fn insert_source(&self, source: Source) -> Result<()> {
+        // Check device id in model
+        let devices = self.devices.read().await;
+        if !devices.contains_key(&source.device_id) {
+            return Err(Error::MissingParent(ResourceType::Device, source.device_id));
+        }
+
+        let mut sources = self.sources.write().await;
+        if source_cycle(&sources, &source) {
+            return Err(Error::ParentCycle(ResourceType::Source, source.core.id));
+        }
+
+        self.source_times.write().await.touch(source.core.id);
+        self.notify(ModelEvent::Changed(ResourceType::Source, source.core.id));
+        sources.insert(source.core.id, source);
+
+        Ok(())
+    }
+
+    pub async fn insert_flow(&self, flow: Flow) -> Result<()> {
+        // Check source id in model
+        let sources = self.sources.read().await;
+        if !sources.contains_key(&flow.source_id) {
+            return Err(Error::MissingParent(ResourceType::Source, flow.source_id));
+        }
+
+        let mut flows = self.flows.write().await;
+        if flow_cycle(&flows, &flow) {
+            return Err(Error::ParentCycle(ResourceType::Flow, flow.core.id));
+        }
+
+        self.flow_times.write().await.touch(flow.core.id);
+        self.notify(ModelEvent::Changed(ResourceType::Flow, flow.core.id));
+        flows.insert(flow.core.id, flow);
+
+        Ok(())
+    }
+
+    pub async fn insert_sender(&self, sender: Sender) -> Result<()> {
+        // Check device id in model
+        let devices = self.devices.read().await;
+        if !devices.contains_key(&sender.device_id) {
+            return Err(Error::MissingParent(ResourceType::Device, sender.device_id));
+        }
+
+        let mut senders = self.senders.write().await;
+        self.sender_times.write().await.touch(sender.core.id);
+        self.notify(ModelEvent::Changed(ResourceType::Sender, sender.core.id));
+        senders.insert(sender.core.id, sender);
+
+        Ok(())
+    }
+
+    /// Remove a node along with its devices and everything under them.
+    pub async fn remove_node(&self, id: &Uuid) -> Option<()> {
+        let mut nodes = self.nodes.write().await;
+        nodes.remove(id)?;
+        self.node_times.write().await.remove(id);
+        self.notify(ModelEvent::Removed(ResourceType::Node, *id));
+        drop(nodes);
+
+        let devices: Vec<Uuid> = self
+            .devices
+            .read()
+            .await
+            .values()
+            .filter(|device| device.node_id == *id)
+            .map(|device| device.core.id)
+            .collect();
+
+        for device in devices {
+            self.remove_device(&device).await;
+        }
+
         Some(())
     }
+
+    /// Remove a device along with its sources, flows, senders and receivers.
+    pub async fn remove_device(&self, id: &Uuid) -> Option<()> {
+        let mut devices = self.devices.write().await;
+        devices.remove(id)?;
+        self.device_times.write().await.remove(id);
+        self.notify(ModelEvent::Removed(ResourceType::Device, *id));
+        drop(devices);
+
+        let sources: Vec<Uuid> = self
+            .sources
+            .read()
+            .await
+            .values()
+            .filter(|source| source.device_id == *id)
+            .map(|source| source.core.id)
+            .collect();
+
+        for source in sources {
+            self.remove_source(&source).await;
+        }
+
+        let mut flows = self.flows.write().await;
+        let mut flow_times = self.flow_times.write().await;
+        for flow in remove_where(&mut flows, |flow| flow.device_id == *id) {
+            flow_times.remove(&flow);
+            self.notify(ModelEvent::Removed(ResourceType::Flow, flow));
+        }
+        drop((flows, flow_times));
+
+        let mut senders = self.senders.write().await;
+        let mut sender_times = self.sender_times.write().await;
+        for sender in remove_where(&mut senders, |sender| sender.device_id == *id) {
+            sender_times.remove(&sender);
+            self.notify(ModelEvent::Removed(ResourceType::Sender, sender));
+        }
+        drop((senders, sender_times));
+
+        let mut receivers = self.receivers.write().await;
+        let mut receiver_times = self.receiver_times.write().await;
+        for receiver in remove_where(&mut receivers, |receiver| receiver.device_id == *id) {
+            receiver_times.remove(&receiver);
+            self.notify(ModelEvent::Removed(ResourceType::Receiver, receiver));
+        }
+
+        Some(())
+    }
+
+    /// Remove a source along with its flows.
+    pub async fn remove_source(&self, id: &Uuid) -> Option<()> {
+        let mut sources = self.sources.write().await;
+        sources.remove(id)?;
+        self.source_times.write().await.remove(id);
+        self.notify(ModelEvent::Removed(ResourceType::Source, *id));
+        drop(sources);
+
+        let mut flows = self.flows.write().await;
+        let mut flow_times = self.flow_times.write().await;
+        for flow in remove_where(&mut flows, |flow| flow.source_id == *id) {
+            flow_times.remove(&flow);
+            self.notify(ModelEvent::Removed(ResourceType::Flow, flow));
+        }
+
+        Some(())
+    }
+
+    pub async fn remove_flow(&self, id: &Uuid) -> Option<()> {
+        let mut flows = self.flows.write().await;
+        flows.remove(id)?;
+        self.flow_times.write().await.remove(id);
+        self.notify(ModelEvent::Removed(ResourceType::Flow, *id));
+
+        Some(())
+    }
+
+    pub async fn remove_sender(&self, id: &Uuid) -> Option<()> {
+        let mut senders = self.senders.write().await;
+        senders.remove(id)?;
+        self.sender_times.write().await.remove(id);
+        self.notify(ModelEvent::Removed(ResourceType::Sender, *id));
+
+        Some(())
+    }
+
+    pub async fn remove_receiver(&self, id: &Uuid) -> Option<()> {
+        let mut receivers = self.receivers.write().await;
+        receivers.remove(id)?;
+        self.receiver_times.write().await.remove(id);
+        self.notify(ModelEvent::Removed(ResourceType::Receiver, *id));
+
+        Some(())
+    }
+
+    pub async fn update_node<F>(&self, id: &Uuid, f: F) -> Option<()>
+    where
+        F: FnOnce(&mut Node),
+    {
+        let mut nodes = self.nodes.write().await;
+        let node = nodes.get_mut(id)?;
+
+        f(node);
+        node.core.version = TaiTime::next_version();
+        self.node_times.write().await.touch(*id);
+        self.notify(ModelEvent::Changed(ResourceType::Node, *id));
+
+        Some(())
+    }
+
+    pub async fn update_device<F>(&self, id: &Uuid, f: F) -> Option<()>
+    where
+        F: FnOnce(&mut Device),
+    {
+        let mut devices = self.devices.write().await;
+        let device = devices.get_mut(id)?;
+
+        f(device);
+        device.core.version = TaiTime::next_version();
+        self.device_times.write().await.touch(*id);
+        self.notify(ModelEvent::Changed(ResourceType::Device, *id));
+
+        Some(())
+    }
+
+    pub async fn update_sender<F>(&self, id: &Uuid, f: F) -> Option<()>
+    where
+        F: FnOnce(&mut Sender),
+    {
+        let mut senders = self.senders.write().await;
+        let sender = senders.get_mut(id)?;
+
+        f(sender);
+        sender.core.version = TaiTime::next_version();
+        self.sender_times.write().await.touch(*id);
+        self.notify(ModelEvent::Changed(ResourceType::Sender, *id));
+
+        Some(())
+    }
+
+    /// Update a sender with a change which may fail, leaving the sender and its
+    /// version as they were on failure.
+    ///
+    /// `f` must not modify the sender before failing.
+    pub async fn try_update_sender<F, T, E>(
+        &self,
+        id: &Uuid,
+        f: F,
+    ) -> Option<std::result::Result<T, E>>
+    where
+        F: FnOnce(&mut Sender) -> std::result::Result<T, E>,
+    {
+        let mut senders = self.senders.write().await;
+        let sender = senders.get_mut(id)?;
+
+        let updated = f(sender);
+        if updated.is_ok() {
+            sender.core.version = TaiTime::next_version();
+            self.sender_times.write().await.touch(*id);
+            self.notify(ModelEvent::Changed(ResourceType::Sender, *id));
+        }
+
+        Some(updated)
+    }
+
+    pub async fn update_receiver<F>(&self, id: &Uuid, f: F) -> Option<()>
+    where
+        F: FnOnce(&mut Receiver),
+    {
+        let mut receivers = self.receivers.write().await;
+        let receiver = receivers.get_mut(id)?;
+
+        f(receiver);
+        receiver.core.version = TaiTime::next_version();
+        self.receiver_times.write().await.touch(*id);
+        self.notify(ModelEvent::Changed(ResourceType::Receiver, *id));
+
+        Some(())
+    }
+
+    /// Update a receiver with a change which may fail, leaving the receiver and
+    /// its version as they were on failure.
+    ///
+    /// `f` must not modify the receiver before failing.
+    pub async fn try_update_receiver<F, T, E>(
+        &self,
+        id: &Uuid,
+        f: F,
+    ) -> Option<std::result::Result<T, E>>
+    where
+        F: FnOnce(&mut Receiver) -> std::result::Result<T, E>,
+    {
+        let mut receivers = self.receivers.write().await;
+        let receiver = receivers.get_mut(id)?;
+
+        let updated = f(receiver);
+        if updated.is_ok() {
+            receiver.core.version = TaiTime::next_version();
+            self.receiver_times.write().await.touch(*id);
+            self.notify(ModelEvent::Changed(ResourceType::Receiver, *id));
+        }
+
+        Some(updated)
+    }
 }
@@ -0,0 +1,380 @@
+//! Saving the model to a JSON file, so that a node keeps its resource ids and
+//! active connections across restarts.
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::path::Path;
+use std::{fmt, fs, io};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    connection::Activation,
+    error::Error,
+    resource::{Device, Flow, Node, Receiver, Resource, ResourceBundle, Sender, Source},
+    Model,
+};
+
+#[derive(Debug)]
+pub enum PersistError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The saved resources are not a valid model.
+    Model(Error),
+}
+
+impl From<io::Error> for PersistError {
+    fn from(e: io::Error) -> Self {
+        PersistError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for PersistError {
+    fn from(e: serde_json::Error) -> Self {
+        PersistError::Json(e)
+    }
+}
+
+impl From<Error> for PersistError {
+    fn from(e: Error) -> Self {
+        PersistError::Model(e)
+    }
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::Io(e) => fmt::Display::fmt(&e, f),
+            PersistError::Json(e) => fmt::Display::fmt(&e, f),
+            PersistError::Model(e) => write!(f, "Invalid saved model: {}", e),
+        }
+    }
+}
+
+impl StdError for PersistError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            PersistError::Io(e) => Some(e),
+            PersistError::Json(e) => Some(e),
+            PersistError::Model(e) => Some(e),
+        }
+    }
+}
+
+// Every resource of a model, as it is saved
+#[derive(Serialize)]
+struct Saved<'a> {
+    nodes: Vec<&'a Node>,
+    devices: Vec<&'a Device>,
+    sources: Vec<&'a Source>,
+    flows: Vec<&'a Flow>,
+    senders: Vec<&'a Sender>,
+    receivers: Vec<&'a Receiver>,
+}
+
+// Every resource of a model, as it is loaded
+#[derive(Deserialize)]
+struct Loaded {
+    nodes: Vec<Node>,
+    devices: Vec<Device>,
+    sources: Vec<Source>,
+    flows: Vec<Flow>,
+    senders: Vec<Sender>,
+    receivers: Vec<Receiver>,
+}
+
+// Resources of a type in a stable order, so unchanged models save the same
+fn sorted<R: Resource>(resources: &HashMap<Uuid, R>) -> Vec<&R> {
+    let mut resources: Vec<&R> = resources.values().collect();
+    resources.sort_by_key(|resource| resource.id());
+    resources
+}
+
+impl Model {
+    /// Save every resource, including the staged and active IS-05 connection
+    /// of each sender and receiver.
+    ///
+    /// Resources are saved as they are held in the model rather than as the
+    /// JSON of an API version, so nothing is lost. The file is replaced in one
+    /// step, so an interrupted or failed save leaves the previous one in place.
+    pub async fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistError> {
+        let nodes = self.nodes().await;
+        let devices = self.devices().await;
+        let sources = self.sources().await;
+        let flows = self.flows().await;
+        let senders = self.senders().await;
+        let receivers = self.receivers().await;
+
+        let json = serde_json::to_vec_pretty(&Saved {
+            nodes: sorted(&nodes),
+            devices: sorted(&devices),
+            sources: sorted(&sources),
+            flows: sorted(&flows),
+            senders: sorted(&senders),
+            receivers: sorted(&receivers),
+        })?;
+
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+
+        fs::write(&temp, json)?;
+        fs::rename(&temp, path)?;
+
+        Ok(())
+    }
+
+    /// Load a model saved by [`Model::save`].
+    ///
+    /// Activations pending when the model was saved are dropped, since
+    /// nothing is left to carry them out.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Model, PersistError> {
+        let loaded: Loaded = serde_json::from_slice(&fs::read(path)?)?;
+
+        let mut bundle = ResourceBundle::new();
+        for node in loaded.nodes {
+            bundle.insert_node(node);
+        }
+        for device in loaded.devices {
+            bundle.insert_device(device);
+        }
+        for source in loaded.sources {
+            bundle.insert_source(source);
+        }
+        for flow in loaded.flows {
+            bundle.insert_flow(flow);
+        }
+        for mut sender in loaded.senders {
+            sender.staged.activation = Activation::default();
+            bundle.insert_sender(sender);
+        }
+        for mut receiver in loaded.receivers {
+            receiver.staged.activation = Activation::default();
+            bundle.insert_receiver(receiver);
+        }
+
+        Ok(Model::from_resources(bundle)?)
+    }
+
+    /// Take the active connections of senders and receivers from a saved
+    /// model, staging and activating them, and keep everything else.
+    ///
+    /// Resources are matched by id, so only those given fixed ids, such as
+    /// with [`derive_id`](crate::resource::derive_id), get their connections
+    /// back. Saved resources the model does not have are ignored.
+    pub fn restore(&mut self, saved: Model) {
+        let saved_senders = saved.senders.into_inner();
+        for (id, sender) in self.senders.get_mut() {
+            if let Some(saved) = saved_senders.get(id) {
+                sender.staged = saved.active.clone();
+                sender.activate();
+            }
+        }
+
+        let saved_receivers = saved.receivers.into_inner();
+        for (id, receiver) in self.receivers.get_mut() {
+            if let Some(saved) = saved_receivers.get(id) {
+                receiver.staged = saved.active.clone();
+                receiver.activate();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::{ActivationMode, ReceiverConnection};
+    use crate::resource::{
+        caps, derive_id, AudioChannel, AudioEssence, Caps, ConstraintSet, ControlType,
+        DeviceControl, Format, ParameterConstraint, Rational, Transport,
+    };
+    use crate::test_support::{device, node, sender_leg};
+    use serde_json::Value;
+
+    // Every resource of a model as JSON, in a stable order
+    async fn resources(model: &Model) -> Vec<Value> {
+        fn json<R: Resource + Serialize>(resources: &HashMap<Uuid, R>) -> Vec<Value> {
+            sorted(resources)
+                .into_iter()
+                .map(|resource| serde_json::to_value(resource).unwrap())
+                .collect()
+        }
+
+        let mut all = json(&*model.nodes().await);
+        all.extend(json(&*model.devices().await));
+        all.extend(json(&*model.sources().await));
+        all.extend(json(&*model.flows().await));
+        all.extend(json(&*model.senders().await));
+        all.extend(json(&*model.receivers().await));
+        all
+    }
+
+    // A node with ids derived from a seed, as a node built the same way on
+    // every start would have, and with its sender and receiver connected to
+    // each other if asked
+    fn model(seed: &Uuid, label: &str, connected: bool) -> Model {
+        let id = |path| derive_id(seed, path);
+
+        let mut node = node();
+        node.core.id = id("node");
+        let mut device = device(&node);
+        device.core.id = id("device");
+        device.controls.push(DeviceControl {
+            href: String::from("http://127.0.0.1/x-nmos/connection/v1.1/"),
+            type_: ControlType::ConnectionV1_1,
+        });
+        let source = Source::builder(label, &device, Format::Audio)
+            .id(id("source"))
+            .channel(AudioChannel::new("Left").symbol("L"))
+            .channel(AudioChannel::new("Right").symbol("R"))
+            .build();
+        let flow = Flow::builder(label, &source)
+            .id(id("flow"))
+            .audio(AudioEssence::new(Rational::new(48000, 1), 24, 2))
+            .build()
+            .unwrap();
+        let mut sender = Sender::builder(label, &device, &flow, Transport::RtpMulticast)
+            .id(id("sender"))
+            .transport_params([sender_leg()])
+            .build();
+
+        // A format no IS-04 schema describes, with caps only the model holds
+        let format = "urn:x-acme:format:telemetry".parse().unwrap();
+        let mut receiver = Receiver::builder(label, &device, format, Transport::Rtp)
+            .id(id("receiver"))
+            .caps(Caps::new().constraint_set(
+                ConstraintSet::new().label(label).preference(10).constraint(
+                    caps::SAMPLE_RATE,
+                    ParameterConstraint::one_of([Rational::new(48000, 1)]),
+                ),
+            ))
+            .build();
+
+        if connected {
+            sender.staged.receiver_id = Some(receiver.core.id);
+            sender.staged.master_enable = true;
+            sender.staged.activation.mode = Some(ActivationMode::ActivateImmediate);
+            sender.activate();
+
+            receiver.staged = ReceiverConnection {
+                sender_id: Some(sender.core.id),
+                master_enable: true,
+                transport_file: Some(String::from("v=0\r\n")),
+                ..ReceiverConnection::default()
+            };
+            receiver.staged.activation.mode = Some(ActivationMode::ActivateImmediate);
+            receiver.activate();
+        }
+
+        let mut resources = ResourceBundle::new();
+        resources.insert_node(node);
+        resources.insert_device(device);
+        resources.insert_source(source);
+        resources.insert_flow(flow);
+        resources.insert_sender(sender);
+        resources.insert_receiver(receiver);
+
+        Model::from_resources(resources).unwrap()
+    }
+
+    async fn save_and_load(model: &Model) -> Result<Model, PersistError> {
+        let path = std::env::temp_dir().join(format!("nmos-persist-{}.json", Uuid::new_v4()));
+
+        model.save(&path).await?;
+        let loaded = Model::load(&path);
+        fs::remove_file(&path)?;
+
+        loaded
+    }
+
+    #[tokio::test]
+    async fn save_load_round_trip() {
+        let seed = Uuid::new_v4();
+        let model = model(&seed, "saved", true);
+
+        let loaded = save_and_load(&model).await.unwrap();
+        assert_eq!(resources(&loaded).await, resources(&model).await);
+
+        let sender_id = derive_id(&seed, "sender");
+        let receiver_id = derive_id(&seed, "receiver");
+
+        let senders = loaded.senders().await;
+        assert_eq!(senders[&sender_id].active.receiver_id, Some(receiver_id));
+        assert!(senders[&sender_id].active.is_active());
+
+        let receivers = loaded.receivers().await;
+        assert_eq!(receivers[&receiver_id].active.sender_id, Some(sender_id));
+        assert!(receivers[&receiver_id].active.master_enable);
+        assert!(receivers[&receiver_id].subscription.active);
+    }
+
+    #[tokio::test]
+    async fn load_drops_pending_activations() {
+        let seed = Uuid::new_v4();
+        let model = model(&seed, "saved", true);
+        let sender_id = derive_id(&seed, "sender");
+
+        model
+            .update_sender(&sender_id, |sender| {
+                sender.staged.activation.mode = Some(ActivationMode::ActivateScheduledRelative);
+                sender.staged.activation.requested_time = Some(String::from("5:0"));
+            })
+            .await;
+
+        let loaded = save_and_load(&model).await.unwrap();
+        assert_eq!(
+            loaded.senders().await[&sender_id].staged.activation,
+            Activation::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn save_fails_loudly() {
+        let model = model(&Uuid::new_v4(), "saved", true);
+        let path = std::env::temp_dir()
+            .join(Uuid::new_v4().to_string())
+            .join("model.json");
+
+        assert!(matches!(model.save(&path).await, Err(PersistError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn restore_takes_connections_by_id() {
+        let seed = Uuid::new_v4();
+        let saved = save_and_load(&model(&seed, "saved", true)).await.unwrap();
+
+        let mut restored = model(&seed, "built", false);
+        let unsaved = {
+            let devices = restored.devices().await;
+            let device = &devices[&derive_id(&seed, "device")];
+            Receiver::builder("unsaved", device, Format::Audio, Transport::Rtp).build()
+        };
+        let unsaved_id = unsaved.core.id;
+        restored.insert_receiver(unsaved).await.unwrap();
+
+        restored.restore(saved);
+
+        let sender_id = derive_id(&seed, "sender");
+        let receiver_id = derive_id(&seed, "receiver");
+
+        let senders = restored.senders().await;
+        let sender = &senders[&sender_id];
+        assert_eq!(sender.core.label, "built");
+        assert_eq!(sender.active.receiver_id, Some(receiver_id));
+        assert_eq!(sender.staged.receiver_id, Some(receiver_id));
+        assert!(sender.subscription.active);
+
+        let receivers = restored.receivers().await;
+        let receiver = &receivers[&receiver_id];
+        assert_eq!(receiver.core.label, "built");
+        assert_eq!(
+            receiver.caps.constraint_sets[0].label.as_deref(),
+            Some("built")
+        );
+        assert_eq!(receiver.active.sender_id, Some(sender_id));
+        assert_eq!(receiver.subscription.sender_id, Some(sender_id));
+        assert!(!receivers[&unsaved_id].active.master_enable);
+    }
+}
@@ -0,0 +1,593 @@
+//! Capabilities of nodes, sources and receivers, including the constraint
+//! sets of BCP-004-01.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::version::{
+    is_04::{V1_1, V1_3},
+    APIVersion,
+};
+
+use super::{
+    json::{self, JsonError},
+    Flow, FlowEssence, Rational, Source,
+};
+
+pub const MEDIA_TYPE: &str = "urn:x-nmos:cap:format:media_type";
+pub const GRAIN_RATE: &str = "urn:x-nmos:cap:format:grain_rate";
+pub const FRAME_WIDTH: &str = "urn:x-nmos:cap:format:frame_width";
+pub const FRAME_HEIGHT: &str = "urn:x-nmos:cap:format:frame_height";
+pub const INTERLACE_MODE: &str = "urn:x-nmos:cap:format:interlace_mode";
+pub const COLORSPACE: &str = "urn:x-nmos:cap:format:colorspace";
+pub const TRANSFER_CHARACTERISTIC: &str = "urn:x-nmos:cap:format:transfer_characteristic";
+pub const COLOR_SAMPLING: &str = "urn:x-nmos:cap:format:color_sampling";
+pub const COMPONENT_DEPTH: &str = "urn:x-nmos:cap:format:component_depth";
+pub const SAMPLE_RATE: &str = "urn:x-nmos:cap:format:sample_rate";
+pub const CHANNEL_COUNT: &str = "urn:x-nmos:cap:format:channel_count";
+pub const SAMPLE_DEPTH: &str = "urn:x-nmos:cap:format:sample_depth";
+pub const PACKET_TIME: &str = "urn:x-nmos:cap:transport:packet_time";
+pub const MAX_PACKET_TIME: &str = "urn:x-nmos:cap:transport:max_packet_time";
+pub const ST2110_21_SENDER_TYPE: &str = "urn:x-nmos:cap:transport:st2110_21_sender_type";
+
+const META_LABEL: &str = "urn:x-nmos:cap:meta:label";
+const META_PREFERENCE: &str = "urn:x-nmos:cap:meta:preference";
+const META_ENABLED: &str = "urn:x-nmos:cap:meta:enabled";
+
+/// A value a parameter may be constrained to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstraintValue {
+    Integer(i64),
+    Number(f64),
+    Boolean(bool),
+    String(String),
+    Rational(Rational),
+}
+
+impl ConstraintValue {
+    fn from_json(value: &Value) -> Result<Self, JsonError> {
+        match value {
+            Value::Bool(b) => Ok(ConstraintValue::Boolean(*b)),
+            Value::String(s) => Ok(ConstraintValue::String(s.clone())),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => Ok(ConstraintValue::Integer(i)),
+                None => Ok(ConstraintValue::Number(n.as_f64().unwrap_or_default())),
+            },
+            Value::Object(_) => {
+                let denominator = json::opt_uint(value, "denominator")?.unwrap_or(1);
+                Ok(ConstraintValue::Rational(Rational::new(
+                    json::uint(value, "numerator")?,
+                    denominator,
+                )))
+            }
+            _ => json::invalid("constraint", format!("unexpected value {}", value)),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            ConstraintValue::Integer(i) => json!(i),
+            ConstraintValue::Number(n) => json!(n),
+            ConstraintValue::Boolean(b) => json!(b),
+            ConstraintValue::String(s) => json!(s),
+            ConstraintValue::Rational(r) => json!({
+                "numerator": r.numerator,
+                "denominator": r.denominator,
+            }),
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            ConstraintValue::Integer(i) => Some(*i as f64),
+            ConstraintValue::Number(n) => Some(*n),
+            ConstraintValue::Rational(r) if r.denominator != 0 => {
+                Some(f64::from(r.numerator) / f64::from(r.denominator))
+            }
+            _ => None,
+        }
+    }
+
+    // Equality across integer, number and rational representations
+    fn matches(&self, other: &ConstraintValue) -> bool {
+        match (self, other) {
+            (ConstraintValue::Rational(a), ConstraintValue::Rational(b)) => {
+                u64::from(a.numerator) * u64::from(b.denominator)
+                    == u64::from(b.numerator) * u64::from(a.denominator)
+            }
+            (ConstraintValue::Boolean(a), ConstraintValue::Boolean(b)) => a == b,
+            (ConstraintValue::String(a), ConstraintValue::String(b)) => a == b,
+            (a, b) => match (a.as_f64(), b.as_f64()) {
+                (Some(a), Some(b)) => (a - b).abs() < f64::EPSILON,
+                _ => false,
+            },
+        }
+    }
+}
+
+impl From<i64> for ConstraintValue {
+    fn from(value: i64) -> Self {
+        ConstraintValue::Integer(value)
+    }
+}
+
+impl From<u32> for ConstraintValue {
+    fn from(value: u32) -> Self {
+        ConstraintValue::Integer(i64::from(value))
+    }
+}
+
+impl From<f64> for ConstraintValue {
+    fn from(value: f64) -> Self {
+        ConstraintValue::Number(value)
+    }
+}
+
+impl From<bool> for ConstraintValue {
+    fn from(value: bool) -> Self {
+        ConstraintValue::Boolean(value)
+    }
+}
+
+impl From<&str> for ConstraintValue {
+    fn from(value: &str) -> Self {
+        ConstraintValue::String(value.to_owned())
+    }
+}
+
+impl From<String> for ConstraintValue {
+    fn from(value: String) -> Self {
+        ConstraintValue::String(value)
+    }
+}
+
+impl From<Rational> for ConstraintValue {
+    fn from(value: Rational) -> Self {
+        ConstraintValue::Rational(value)
+    }
+}
+
+/// Constraint on the values of one parameter.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParameterConstraint {
+    #[serde(rename = "enum")]
+    pub enum_: Vec<ConstraintValue>,
+    pub minimum: Option<ConstraintValue>,
+    pub maximum: Option<ConstraintValue>,
+    pub description: Option<String>,
+}
+
+impl ParameterConstraint {
+    /// A constraint allowing only the given values.
+    pub fn one_of<I, V>(values: I) -> Self
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<ConstraintValue>,
+    {
+        ParameterConstraint {
+            enum_: values.into_iter().map(Into::into).collect(),
+            ..ParameterConstraint::default()
+        }
+    }
+
+    /// A constraint allowing values between an inclusive minimum and maximum.
+    pub fn range<V: Into<ConstraintValue>>(minimum: V, maximum: V) -> Self {
+        ParameterConstraint {
+            minimum: Some(minimum.into()),
+            maximum: Some(maximum.into()),
+            ..ParameterConstraint::default()
+        }
+    }
+
+    #[must_use]
+    pub fn description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Whether a parameter value satisfies the constraint.
+    #[must_use]
+    pub fn allows(&self, value: &ConstraintValue) -> bool {
+        let in_enum = self.enum_.is_empty() || self.enum_.iter().any(|v| v.matches(value));
+
+        let above_minimum = match (&self.minimum, value.as_f64()) {
+            (None, _) => true,
+            (Some(minimum), Some(value)) => minimum.as_f64().map_or(false, |min| value >= min),
+            (Some(_), None) => false,
+        };
+
+        let below_maximum = match (&self.maximum, value.as_f64()) {
+            (None, _) => true,
+            (Some(maximum), Some(value)) => maximum.as_f64().map_or(false, |max| value <= max),
+            (Some(_), None) => false,
+        };
+
+        in_enum && above_minimum && below_maximum
+    }
+
+    fn from_json(constraint: &Value) -> Result<Self, JsonError> {
+        let value = |field: &str| match json::opt_field(constraint, field) {
+            Some(value) => ConstraintValue::from_json(value).map(Some),
+            None => Ok(None),
+        };
+
+        Ok(ParameterConstraint {
+            enum_: json::array(constraint, "enum")?
+                .iter()
+                .map(ConstraintValue::from_json)
+                .collect::<Result<_, _>>()?,
+            minimum: value("minimum")?,
+            maximum: value("maximum")?,
+            description: json::opt_string(constraint, "description")?.map(str::to_owned),
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut json = Map::new();
+
+        if !self.enum_.is_empty() {
+            let values: Vec<_> = self.enum_.iter().map(ConstraintValue::to_json).collect();
+            json.insert(String::from("enum"), json!(values));
+        }
+        if let Some(minimum) = &self.minimum {
+            json.insert(String::from("minimum"), minimum.to_json());
+        }
+        if let Some(maximum) = &self.maximum {
+            json.insert(String::from("maximum"), maximum.to_json());
+        }
+        if let Some(description) = &self.description {
+            json.insert(String::from("description"), json!(description));
+        }
+
+        Value::Object(json)
+    }
+}
+
+/// A BCP-004-01 constraint set, which is satisfied when every parameter
+/// constraint in it is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConstraintSet {
+    pub label: Option<String>,
+    /// Preference from -100 to 100 relative to other constraint sets.
+    pub preference: Option<i8>,
+    pub enabled: bool,
+    /// Constraints keyed by parameter URN, such as [`FRAME_WIDTH`].
+    pub constraints: BTreeMap<String, ParameterConstraint>,
+}
+
+impl Default for ConstraintSet {
+    fn default() -> Self {
+        ConstraintSet {
+            label: None,
+            preference: None,
+            enabled: true,
+            constraints: BTreeMap::new(),
+        }
+    }
+}
+
+impl ConstraintSet {
+    pub fn new() -> Self {
+        ConstraintSet::default()
+    }
+
+    #[must_use]
+    pub fn label<S: Into<String>>(mut self, label: S) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    #[must_use]
+    pub fn preference(mut self, preference: i8) -> Self {
+        self.preference = Some(preference);
+        self
+    }
+
+    #[must_use]
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    #[must_use]
+    pub fn constraint<S: Into<String>>(
+        mut self,
+        param: S,
+        constraint: ParameterConstraint,
+    ) -> Self {
+        self.constraints.insert(param.into(), constraint);
+        self
+    }
+
+    /// Whether parameter values satisfy every constraint.
+    ///
+    /// As BCP-004-01 allows, parameters without a value, such as transport
+    /// parameters a flow does not describe, are not constrained.
+    #[must_use]
+    pub fn satisfied_by(&self, params: &BTreeMap<&str, ConstraintValue>) -> bool {
+        self.enabled
+            && self
+                .constraints
+                .iter()
+                .all(|(param, constraint)| match params.get(param.as_str()) {
+                    Some(value) => constraint.allows(value),
+                    None => true,
+                })
+    }
+
+    fn from_json(set: &Value) -> Result<Self, JsonError> {
+        let object = match set.as_object() {
+            Some(object) => object,
+            None => return json::invalid("constraint_sets", "expected an object"),
+        };
+
+        let mut constraints = BTreeMap::new();
+        for (param, constraint) in object {
+            if !param.starts_with("urn:x-nmos:cap:meta:") {
+                constraints.insert(param.clone(), ParameterConstraint::from_json(constraint)?);
+            }
+        }
+
+        let preference = match json::opt_field(set, META_PREFERENCE).and_then(Value::as_i64) {
+            Some(preference) if (-100..=100).contains(&preference) => Some(preference as i8),
+            Some(_) => return json::invalid(META_PREFERENCE, "expected -100 to 100"),
+            None => None,
+        };
+
+        Ok(ConstraintSet {
+            label: json::opt_string(set, META_LABEL)?.map(str::to_owned),
+            preference,
+            enabled: json::opt_bool(set, META_ENABLED)?.unwrap_or(true),
+            constraints,
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut json: Map<String, Value> = self
+            .constraints
+            .iter()
+            .map(|(param, constraint)| (param.clone(), constraint.to_json()))
+            .collect();
+
+        if let Some(label) = &self.label {
+            json.insert(META_LABEL.to_owned(), json!(label));
+        }
+        if let Some(preference) = self.preference {
+            json.insert(META_PREFERENCE.to_owned(), json!(preference));
+        }
+        if !self.enabled {
+            json.insert(META_ENABLED.to_owned(), json!(false));
+        }
+
+        Value::Object(json)
+    }
+}
+
+/// Capabilities of a node, source or receiver.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Caps {
+    /// Media types a receiver accepts, from v1.1. Empty if unconstrained.
+    pub media_types: Vec<String>,
+    /// IS-07 event types a receiver accepts, from v1.3.
+    pub event_types: Vec<String>,
+    /// BCP-004-01 constraint sets, from v1.3. Empty if unconstrained.
+    pub constraint_sets: Vec<ConstraintSet>,
+    /// Capabilities not modelled above, which are kept as they are.
+    pub other: BTreeMap<String, Value>,
+}
+
+impl Caps {
+    pub fn new() -> Self {
+        Caps::default()
+    }
+
+    #[must_use]
+    pub fn media_types<I, S>(mut self, media_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.media_types = media_types.into_iter().map(Into::into).collect();
+        self
+    }
+
+    #[must_use]
+    pub fn event_types<I, S>(mut self, event_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.event_types = event_types.into_iter().map(Into::into).collect();
+        self
+    }
+
+    #[must_use]
+    pub fn constraint_set(mut self, constraint_set: ConstraintSet) -> Self {
+        self.constraint_sets.push(constraint_set);
+        self
+    }
+
+    /// Whether a media type is accepted.
+    #[must_use]
+    pub fn accepts_media_type(&self, media_type: &str) -> bool {
+        self.media_types.is_empty() || self.media_types.iter().any(|m| m == media_type)
+    }
+
+    /// Whether a flow has an accepted media type and satisfies an enabled
+    /// constraint set.
+    ///
+    /// The source of the flow gives the channel count of audio flows which
+    /// leave it out.
+    ///
+    /// Transport constraints (`urn:x-nmos:cap:transport:*`) are deliberately
+    /// left out: the model holds no packet time or ST 2110-21 sender type for
+    /// a sender, so they are treated as undeclared. The sender's transport is
+    /// checked by [`Receiver::accepts`](super::Receiver::accepts), and interface
+    /// bindings are not a capability of a receiver.
+    #[must_use]
+    pub fn satisfied_by(&self, flow: &Flow, source: Option<&Source>) -> bool {
+        if !self.accepts_media_type(flow.media_type()) {
+            return false;
+        }
+
+        let params = flow_params(flow, source);
+        self.constraint_sets.is_empty()
+            || self
+                .constraint_sets
+                .iter()
+                .any(|set| set.satisfied_by(&params))
+    }
+
+    pub(crate) fn from_json(json: &Value) -> Result<Self, JsonError> {
+        match json::opt_field(json, "caps") {
+            Some(Value::Object(caps)) => Caps::from_schema(&caps.clone().into_iter().collect()),
+            Some(_) => json::invalid("caps", "expected an object"),
+            None => Ok(Caps::default()),
+        }
+    }
+
+    /// Parse the capabilities of a schema type, which keeps them as a map.
+    pub(crate) fn from_schema(caps: &BTreeMap<String, Value>) -> Result<Self, JsonError> {
+        let caps_json = json!(caps);
+        let mut other = caps.clone();
+        for field in ["media_types", "event_types", "constraint_sets"] {
+            other.remove(field);
+        }
+
+        Ok(Caps {
+            media_types: json::strings(&caps_json, "media_types")?,
+            event_types: json::strings(&caps_json, "event_types")?,
+            constraint_sets: json::array(&caps_json, "constraint_sets")?
+                .iter()
+                .map(ConstraintSet::from_json)
+                .collect::<Result<_, _>>()?,
+            other,
+        })
+    }
+
+    /// Capabilities as defined at an API version.
+    pub(crate) fn to_json(&self, api: &APIVersion) -> BTreeMap<String, Value> {
+        let mut json = self.other.clone();
+
+        if *api >= V1_1 && !self.media_types.is_empty() {
+            json.insert(String::from("media_types"), json!(self.media_types));
+        }
+        if *api >= V1_3 && !self.event_types.is_empty() {
+            json.insert(String::from("event_types"), json!(self.event_types));
+        }
+        if *api >= V1_3 && !self.constraint_sets.is_empty() {
+            let sets: Vec<_> = self
+                .constraint_sets
+                .iter()
+                .map(ConstraintSet::to_json)
+                .collect();
+            json.insert(String::from("constraint_sets"), json!(sets));
+        }
+
+        json
+    }
+}
+
+// Format parameters of a flow, keyed by capability URN
+fn flow_params(flow: &Flow, source: Option<&Source>) -> BTreeMap<&'static str, ConstraintValue> {
+    let mut params = BTreeMap::new();
+
+    params.insert(MEDIA_TYPE, ConstraintValue::from(flow.media_type()));
+    if let Some(grain_rate) = flow.grain_rate {
+        params.insert(GRAIN_RATE, ConstraintValue::from(grain_rate));
+    }
+
+    match &flow.essence {
+        Some(FlowEssence::Video(video)) => {
+            params.insert(FRAME_WIDTH, video.frame_width.into());
+            params.insert(FRAME_HEIGHT, video.frame_height.into());
+            params.insert(INTERLACE_MODE, video.interlace_mode.to_string().into());
+            params.insert(COLORSPACE, video.colorspace.to_string().into());
+            params.insert(
+                TRANSFER_CHARACTERISTIC,
+                video.transfer_characteristic.to_string().into(),
+            );
+            params.insert(COLOR_SAMPLING, video.sampling.to_string().into());
+            params.insert(COMPONENT_DEPTH, u32::from(video.bit_depth).into());
+        }
+        Some(FlowEssence::Audio(audio)) => {
+            params.insert(SAMPLE_RATE, audio.sample_rate.into());
+            params.insert(SAMPLE_DEPTH, u32::from(audio.bit_depth).into());
+
+            // Flows parsed from JSON leave channels to the source
+            let channels = if audio.channels > 0 {
+                Some(u32::from(audio.channels))
+            } else {
+                source
+                    .and_then(|source| u32::try_from(source.channels.len()).ok())
+                    .filter(|channels| *channels > 0)
+            };
+            if let Some(channels) = channels {
+                params.insert(CHANNEL_COUNT, channels.into());
+            }
+        }
+        None => {}
+    }
+
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::{AudioChannel, AudioEssence, Format, Source, VideoEssence};
+    use crate::test_support::{device, node};
+
+    #[test]
+    fn undeclared_params_are_unconstrained() {
+        let source = Source::builder("source", &device(&node()), Format::Video).build();
+        let flow = Flow::builder("flow", &source)
+            .grain_rate(Rational::new(50, 1))
+            .video(VideoEssence::new(1920, 1080))
+            .build()
+            .unwrap();
+
+        let caps = Caps::new().media_types(["video/raw"]).constraint_set(
+            ConstraintSet::new()
+                .constraint(FRAME_WIDTH, ParameterConstraint::one_of([1920_u32]))
+                .constraint(PACKET_TIME, ParameterConstraint::one_of([0.125]))
+                .constraint(
+                    ST2110_21_SENDER_TYPE,
+                    ParameterConstraint::one_of(["2110TPN"]),
+                ),
+        );
+        assert!(caps.satisfied_by(&flow, Some(&source)));
+
+        let caps = Caps::new().constraint_set(
+            ConstraintSet::new()
+                .constraint(FRAME_WIDTH, ParameterConstraint::one_of([1280_u32]))
+                .constraint(PACKET_TIME, ParameterConstraint::one_of([0.125])),
+        );
+        assert!(!caps.satisfied_by(&flow, Some(&source)));
+    }
+
+    #[test]
+    fn channel_count_from_source() {
+        let source = Source::builder("source", &device(&node()), Format::Audio)
+            .channel(AudioChannel::new("Left"))
+            .channel(AudioChannel::new("Right"))
+            .build();
+        let flow = Flow::builder("flow", &source)
+            .audio(AudioEssence::new(Rational::new(48000, 1), 24, 0))
+            .build()
+            .unwrap();
+
+        let stereo = Caps::new().constraint_set(
+            ConstraintSet::new().constraint(CHANNEL_COUNT, ParameterConstraint::one_of([2_u32])),
+        );
+        let mono = Caps::new().constraint_set(
+            ConstraintSet::new().constraint(CHANNEL_COUNT, ParameterConstraint::one_of([1_u32])),
+        );
+
+        assert!(stereo.satisfied_by(&flow, Some(&source)));
+        assert!(!mono.satisfied_by(&flow, Some(&source)));
+    }
+}
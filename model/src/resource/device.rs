@@ -1,36 +1,75 @@
-use std::fmt;
+use std::collections::BTreeMap;
 
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::{
+    error::Error,
     resource::Node,
-    version::{is_04::V1_0, APIVersion},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonError},
+    urn::urn_enum,
+    Asset, ParseUrnError, Resource, ResourceCore, ResourceCoreBuilder, ResourceType,
+};
 
-#[derive(Debug, Clone, Copy)]
-pub enum DeviceType {
-    Generic,
-    Pipeline,
-}
+urn_enum!(DeviceType, "device", {
+    Generic => "urn:x-nmos:device:generic",
+    Pipeline => "urn:x-nmos:device:pipeline",
+});
 
-impl fmt::Display for DeviceType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl DeviceType {
+    /// Whether the device type can be used at an API version.
+    ///
+    /// v1.0 only allows the generic and pipeline types, while later
+    /// versions also allow types outside the `urn:x-nmos:` namespace.
+    #[must_use]
+    pub fn supports(&self, api: &APIVersion) -> bool {
         match self {
-            DeviceType::Generic => write!(f, "urn:x-nmos:device:generic"),
-            DeviceType::Pipeline => write!(f, "urn:x-nmos:device:pipeline"),
+            DeviceType::Generic | DeviceType::Pipeline => true,
+            DeviceType::Other(urn) => !urn.is_nmos() && *api >= V1_1,
         }
     }
 }
 
+urn_enum!(ControlType, "control", {
+    ConnectionV1_0 => "urn:x-nmos:control:sr-ctrl/v1.0",
+    ConnectionV1_1 => "urn:x-nmos:control:sr-ctrl/v1.1",
+    ChannelMappingV1_0 => "urn:x-nmos:control:cm-ctrl/v1.0",
+    EventsV1_0 => "urn:x-nmos:control:events/v1.0",
+});
+
+impl ControlType {
+    /// Whether the control type can be used at an API version.
+    ///
+    /// Devices only list controls from v1.1.
+    #[must_use]
+    pub fn supports(&self, api: &APIVersion) -> bool {
+        *api >= V1_1
+    }
+}
+
+/// An API endpoint through which a device can be controlled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceControl {
+    pub href: String,
+    #[serde(rename = "type")]
+    pub type_: ControlType,
+}
+
 #[must_use]
 pub struct DeviceBuilder {
     core: ResourceCoreBuilder,
     type_: DeviceType,
     node_id: Uuid,
+    controls: Vec<DeviceControl>,
 }
 
 impl DeviceBuilder {
@@ -39,9 +78,37 @@ impl DeviceBuilder {
             core: ResourceCoreBuilder::new(label),
             type_: device_type,
             node_id: node.core.id,
+            controls: Vec::new(),
         }
     }
 
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.core = self.core.id(id);
+        self
+    }
+
+    pub fn tag<S, V>(mut self, key: S, values: V) -> Self
+    where
+        S: Into<String>,
+        V: IntoIterator<Item = S>,
+    {
+        self.core = self.core.tag(key, values);
+        self
+    }
+
+    pub fn asset(mut self, asset: &Asset) -> Self {
+        self.core = self.core.asset(asset);
+        self
+    }
+
+    pub fn control<S: Into<String>>(mut self, type_: ControlType, href: S) -> Self {
+        self.controls.push(DeviceControl {
+            href: href.into(),
+            type_,
+        });
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> Device {
         Device {
@@ -50,17 +117,20 @@ impl DeviceBuilder {
             node_id: self.node_id,
             senders: Vec::new(),
             receivers: Vec::new(),
+            controls: self.controls,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Device {
     pub core: ResourceCore,
+    #[serde(rename = "type")]
     pub type_: DeviceType,
     pub node_id: Uuid,
     pub senders: Vec<Uuid>,
     pub receivers: Vec<Uuid>,
+    pub controls: Vec<DeviceControl>,
 }
 
 impl Device {
@@ -72,9 +142,30 @@ impl Device {
         DeviceBuilder::new(label, node, device_type)
     }
 
-    #[must_use]
-    pub fn to_json(&self, api: &APIVersion) -> DeviceJson {
+    /// Parse a device from its JSON representation at an IS-04 version, through
+    /// the schema type of that version.
+    pub fn from_json(device: &Value, api: &APIVersion) -> Result<Self, JsonError> {
         match *api {
+            V1_0 => Device::try_from(json::schema::<is_04::v1_0_x::Device>(device)?),
+            V1_1 => Device::try_from(json::schema::<is_04::v1_1_x::Device>(device)?),
+            V1_2 => Device::try_from(json::schema::<is_04::v1_2_x::Device>(device)?),
+            V1_3 => Device::try_from(json::schema::<is_04::v1_3_x::Device>(device)?),
+            _ => Err(JsonError::UnsupportedVersion(*api)),
+        }
+    }
+}
+
+impl Resource for Device {
+    const RESOURCE_TYPE: ResourceType = ResourceType::Device;
+
+    fn core(&self) -> &ResourceCore {
+        &self.core
+    }
+
+    fn render(&self, api: &APIVersion) -> Result<Value, Error> {
+        self.type_.check(api)?;
+
+        Ok(match *api {
             V1_0 => {
                 // Senders
                 let senders = self.senders.iter().map(ToString::to_string).collect();
@@ -82,7 +173,7 @@ impl Device {
                 // Receivers
                 let receivers = self.receivers.iter().map(ToString::to_string).collect();
 
-                DeviceJson::V1_0(is_04::v1_0_x::Device {
+                json!(is_04::v1_0_x::Device {
                     id: self.core.id.to_string(),
                     version: self.core.version.to_string(),
                     label: self.core.label.clone(),
@@ -92,13 +183,146 @@ impl Device {
                     receivers,
                 })
             }
-            _ => panic!("Unsupported API"),
-        }
+            V1_1 => json!(self.to_v1_1(api)?),
+            V1_2 => json!(self.to_v1_2(api)?),
+            V1_3 => json!(self.to_v1_3(api)?),
+            _ => return Err(Error::UnsupportedVersion(*api)),
+        })
     }
 }
 
-#[derive(Debug, Serialize)]
-#[serde(untagged)]
-pub enum DeviceJson {
-    V1_0(is_04::v1_0_x::Device),
+fn parse_device_type(type_: &str) -> Result<DeviceType, JsonError> {
+    type_
+        .parse()
+        .or_else(|err: ParseUrnError| json::invalid("type", err.to_string()))
 }
+
+fn parse_control_type(type_: &str) -> Result<ControlType, JsonError> {
+    type_
+        .parse()
+        .or_else(|err: ParseUrnError| json::invalid("controls", err.to_string()))
+}
+
+impl TryFrom<is_04::v1_0_x::Device> for Device {
+    type Error = JsonError;
+
+    fn try_from(device: is_04::v1_0_x::Device) -> Result<Self, JsonError> {
+        Ok(Device {
+            core: json::parse_core(
+                &device.id,
+                &device.version,
+                &device.label,
+                "",
+                &BTreeMap::new(),
+            )?,
+            type_: parse_device_type(&device.type_)?,
+            node_id: json::parse_id("node_id", &device.node_id)?,
+            senders: json::parse_ids("senders", &device.senders)?,
+            receivers: json::parse_ids("receivers", &device.receivers)?,
+            controls: Vec::new(),
+        })
+    }
+}
+
+macro_rules! try_from_schema {
+    ($($version:ident),+) => {
+        $(
+            impl TryFrom<is_04::$version::Device> for Device {
+                type Error = JsonError;
+
+                fn try_from(device: is_04::$version::Device) -> Result<Self, JsonError> {
+                    let type_ = match &device.type_ {
+                        is_04::$version::DeviceType::Variant0(type_)
+                        | is_04::$version::DeviceType::Variant1(type_) => {
+                            parse_device_type(json::schema_str("type", type_)?)?
+                        }
+                    };
+
+                    let controls = device
+                        .controls
+                        .into_iter()
+                        .map(|control| {
+                            Ok(DeviceControl {
+                                type_: parse_control_type(&control.type_)?,
+                                href: control.href,
+                            })
+                        })
+                        .collect::<Result<_, JsonError>>()?;
+
+                    Ok(Device {
+                        core: json::parse_core(
+                            &device.id,
+                            &device.version,
+                            &device.label,
+                            &device.description,
+                            &device.tags,
+                        )?,
+                        type_,
+                        node_id: json::parse_id("node_id", &device.node_id)?,
+                        senders: json::parse_ids("senders", &device.senders)?,
+                        receivers: json::parse_ids("receivers", &device.receivers)?,
+                        controls,
+                    })
+                }
+            }
+        )+
+    };
+}
+
+try_from_schema!(v1_1_x, v1_2_x, v1_3_x);
+
+// Controls may say whether they need authorization from v1.3
+macro_rules! to_schema {
+    ($name:ident, $version:ident) => {
+        impl Device {
+            fn $name(&self, api: &APIVersion) -> Result<is_04::$version::Device, Error> {
+                // Types outside the `urn:x-nmos:` namespace match the second form
+                let type_ = match self.type_ {
+                    DeviceType::Other(_) => {
+                        is_04::$version::DeviceType::Variant1(Value::from(self.type_.to_string()))
+                    }
+                    _ => is_04::$version::DeviceType::Variant0(Value::from(self.type_.to_string())),
+                };
+
+                let controls = self
+                    .controls
+                    .iter()
+                    .map(|control| {
+                        control.type_.check(api)?;
+                        Ok(to_schema!(@control $version, control))
+                    })
+                    .collect::<Result<_, Error>>()?;
+
+                Ok(is_04::$version::Device {
+                    id: self.core.id.to_string(),
+                    version: self.core.version.to_string(),
+                    label: self.core.label.clone(),
+                    description: self.core.description.clone(),
+                    tags: json::schema_tags(&self.core.tags),
+                    type_,
+                    node_id: self.node_id.to_string(),
+                    senders: json::schema_ids(&self.senders),
+                    receivers: json::schema_ids(&self.receivers),
+                    controls,
+                })
+            }
+        }
+    };
+    (@control v1_3_x, $control:ident) => {
+        is_04::v1_3_x::DeviceItemControls {
+            href: $control.href.clone(),
+            type_: $control.type_.to_string(),
+            authorization: None,
+        }
+    };
+    (@control $version:ident, $control:ident) => {
+        is_04::$version::DeviceItemControls {
+            href: $control.href.clone(),
+            type_: $control.type_.to_string(),
+        }
+    };
+}
+
+to_schema!(to_v1_1, v1_1_x);
+to_schema!(to_v1_2, v1_2_x);
+to_schema!(to_v1_3, v1_3_x);
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::Rational;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InterlaceMode {
+    Progressive,
+    InterlacedTff,
+    InterlacedBff,
+    InterlacedPsf,
+}
+
+impl fmt::Display for InterlaceMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterlaceMode::Progressive => write!(f, "progressive"),
+            InterlaceMode::InterlacedTff => write!(f, "interlaced_tff"),
+            InterlaceMode::InterlacedBff => write!(f, "interlaced_bff"),
+            InterlaceMode::InterlacedPsf => write!(f, "interlaced_psf"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Colorspace {
+    Bt601,
+    Bt709,
+    Bt2020,
+    Bt2100,
+}
+
+impl fmt::Display for Colorspace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Colorspace::Bt601 => write!(f, "BT601"),
+            Colorspace::Bt709 => write!(f, "BT709"),
+            Colorspace::Bt2020 => write!(f, "BT2020"),
+            Colorspace::Bt2100 => write!(f, "BT2100"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferCharacteristic {
+    Sdr,
+    Hlg,
+    Pq,
+}
+
+impl fmt::Display for TransferCharacteristic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransferCharacteristic::Sdr => write!(f, "SDR"),
+            TransferCharacteristic::Hlg => write!(f, "HLG"),
+            TransferCharacteristic::Pq => write!(f, "PQ"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sampling {
+    YCbCr444,
+    YCbCr422,
+    YCbCr420,
+    Rgb,
+}
+
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sampling::YCbCr444 => write!(f, "YCbCr-4:4:4"),
+            Sampling::YCbCr422 => write!(f, "YCbCr-4:2:2"),
+            Sampling::YCbCr420 => write!(f, "YCbCr-4:2:0"),
+            Sampling::Rgb => write!(f, "RGB"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VideoEssence {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub interlace_mode: InterlaceMode,
+    pub colorspace: Colorspace,
+    pub transfer_characteristic: TransferCharacteristic,
+    pub sampling: Sampling,
+    pub bit_depth: u8,
+}
+
+impl VideoEssence {
+    /// Progressive BT.709 SDR 4:2:2 10-bit video of the given frame size.
+    #[must_use]
+    pub fn new(frame_width: u32, frame_height: u32) -> Self {
+        Self {
+            frame_width,
+            frame_height,
+            interlace_mode: InterlaceMode::Progressive,
+            colorspace: Colorspace::Bt709,
+            transfer_characteristic: TransferCharacteristic::Sdr,
+            sampling: Sampling::YCbCr422,
+            bit_depth: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioEssence {
+    pub sample_rate: Rational,
+    pub bit_depth: u8,
+    pub channels: u8,
+}
+
+impl AudioEssence {
+    #[must_use]
+    pub fn new(sample_rate: Rational, bit_depth: u8, channels: u8) -> Self {
+        Self {
+            sample_rate,
+            bit_depth,
+            channels,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowEssence {
+    Video(VideoEssence),
+    Audio(AudioEssence),
+}
@@ -1,39 +1,74 @@
-use std::collections::BTreeMap;
-
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::{
-    resource::{Format, Source},
-    version::{is_04::V1_0, APIVersion},
+    error::Error,
+    resource::{AudioEssence, FlowEssence, Format, Rational, Sampling, Source, VideoEssence},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonError},
+    Colorspace, InterlaceMode, Resource, ResourceCore, ResourceCoreBuilder, ResourceType,
+    TransferCharacteristic,
+};
 
 #[must_use]
 pub struct FlowBuilder {
     core: ResourceCoreBuilder,
     format: Format,
     source_id: Uuid,
+    device_id: Uuid,
     parents: Vec<Uuid>,
+    media_type: Option<String>,
+    grain_rate: Option<Rational>,
+    essence: Option<FlowEssence>,
 }
 
 impl FlowBuilder {
     pub fn new<S: Into<String>>(label: S, source: &Source) -> Self {
         FlowBuilder {
             core: ResourceCoreBuilder::new(label),
-            format: source.format,
+            format: source.format.clone(),
             source_id: source.core.id,
+            device_id: source.device_id,
             parents: Vec::new(),
+            media_type: None,
+            grain_rate: None,
+            essence: None,
         }
     }
 
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.core = self.core.id(id);
+        self
+    }
+
     pub fn description<S: Into<String>>(mut self, description: S) -> Self {
         self.core = self.core.description(description);
         self
     }
 
+    /// Add a flow this flow is derived from.
+    pub fn parent(self, parent: &Flow) -> Self {
+        self.parents(std::iter::once(parent.core.id))
+    }
+
+    /// Add the ids of flows this flow is derived from, which may be held elsewhere.
+    pub fn parents<I: IntoIterator<Item = Uuid>>(mut self, parents: I) -> Self {
+        for parent in parents {
+            if !self.parents.contains(&parent) {
+                self.parents.push(parent);
+            }
+        }
+        self
+    }
+
     pub fn tag<S, V>(mut self, key: S, values: V) -> Self
     where
         S: Into<String>,
@@ -43,23 +78,61 @@ impl FlowBuilder {
         self
     }
 
-    #[must_use]
-    pub fn build(self) -> Flow {
-        Flow {
-            core: self.core.build(),
+    pub fn media_type<S: Into<String>>(mut self, media_type: S) -> Self {
+        self.media_type = Some(media_type.into());
+        self
+    }
+
+    pub fn grain_rate(mut self, grain_rate: Rational) -> Self {
+        self.grain_rate = Some(grain_rate);
+        self
+    }
+
+    pub fn video(mut self, video: VideoEssence) -> Self {
+        self.essence = Some(FlowEssence::Video(video));
+        self
+    }
+
+    pub fn audio(mut self, audio: AudioEssence) -> Self {
+        self.essence = Some(FlowEssence::Audio(audio));
+        self
+    }
+
+    /// Build the flow, failing if it is a video or audio flow without the
+    /// essence parameters every version from v1.1 requires.
+    pub fn build(self) -> Result<Flow, Error> {
+        let core = self.core.build();
+
+        match (&self.format, &self.essence) {
+            (Format::Video, Some(FlowEssence::Video(_)))
+            | (Format::Audio, Some(FlowEssence::Audio(_))) => {}
+            (Format::Video | Format::Audio, _) => return Err(Error::MissingEssence(core.id)),
+            _ => {}
+        }
+
+        Ok(Flow {
+            core,
             format: self.format,
             source_id: self.source_id,
+            device_id: self.device_id,
             parents: self.parents,
-        }
+            media_type: self.media_type,
+            grain_rate: self.grain_rate,
+            essence: self.essence,
+        })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Flow {
     pub core: ResourceCore,
     pub format: Format,
     pub source_id: Uuid,
+    pub device_id: Uuid,
     pub parents: Vec<Uuid>,
+    pub media_type: Option<String>,
+    pub grain_rate: Option<Rational>,
+    pub essence: Option<FlowEssence>,
 }
 
 impl Flow {
@@ -67,41 +140,404 @@ impl Flow {
         FlowBuilder::new(label, source)
     }
 
-    #[must_use]
-    pub fn to_json(&self, api: &APIVersion) -> FlowJson {
+    /// Parse a flow from its JSON representation at an IS-04 version.
+    ///
+    /// v1.0 flows do not carry a device, so `device_id` is nil until it is
+    /// looked up from the source.
+    pub fn from_json(flow: &Value, api: &APIVersion) -> Result<Self, JsonError> {
         match *api {
-            V1_0 => {
-                // Tags
-                let tags = self
-                    .core
-                    .tags
-                    .iter()
-                    .fold(BTreeMap::new(), |mut map, (key, array)| {
-                        let value = serde_json::Value::from(array.clone());
-                        map.insert(key.clone(), value);
-                        map
-                    });
-
-                let parents = self.parents.iter().map(ToString::to_string).collect();
-
-                FlowJson::V1_0(is_04::v1_0_x::Flow {
-                    id: self.core.id.to_string(),
-                    version: self.core.version.to_string(),
-                    label: self.core.label.clone(),
-                    description: self.core.description.clone(),
-                    format: self.format.to_string(),
-                    tags,
-                    source_id: self.source_id.to_string(),
-                    parents,
-                })
+            V1_0 => Flow::try_from(json::schema::<is_04::v1_0_x::Flow>(flow)?),
+            V1_1 | V1_2 | V1_3 => Flow::from_typed_json(flow),
+            _ => Err(JsonError::UnsupportedVersion(*api)),
+        }
+    }
+
+    // Parse a flow of any of the types from v1.1, which all carry a device and
+    // the essence parameters of video and audio
+    fn from_typed_json(flow: &Value) -> Result<Self, JsonError> {
+        let format = json::format(flow)?;
+
+        let grain_rate = match json::opt_field(flow, "grain_rate") {
+            Some(grain_rate) => Some(rational_from_json(grain_rate)?),
+            None => None,
+        };
+
+        let essence = match format {
+            Format::Video => Some(FlowEssence::Video(video_from_json(flow)?)),
+            Format::Audio => {
+                // Channels are described by the source rather than the flow
+                Some(FlowEssence::Audio(AudioEssence::new(
+                    rational_from_json(json::field(flow, "sample_rate")?)?,
+                    bit_depth_from_json(json::opt_uint(flow, "bit_depth")?.unwrap_or(24))?,
+                    0,
+                )))
             }
-            _ => panic!("Unsupported API"),
+            _ => None,
+        };
+
+        Ok(Flow {
+            core: json::core(flow)?,
+            format,
+            source_id: json::id(flow, "source_id")?,
+            device_id: json::id(flow, "device_id")?,
+            parents: json::ids(flow, "parents")?,
+            media_type: json::opt_string(flow, "media_type")?.map(str::to_owned),
+            grain_rate,
+            essence,
+        })
+    }
+
+    /// Media type of the flow, falling back to the uncompressed type for its format.
+    #[must_use]
+    pub fn media_type(&self) -> &str {
+        match (&self.media_type, &self.format, &self.essence) {
+            (Some(media_type), _, _) => media_type,
+            (None, Format::Video, _) => "video/raw",
+            (None, Format::Audio, Some(FlowEssence::Audio(audio))) if audio.bit_depth == 16 => {
+                "audio/L16"
+            }
+            (None, Format::Audio, _) => "audio/L24",
+            (None, Format::Data, _) => "video/smpte291",
+            (None, Format::Mux, _) => "video/SMPTE2022-6",
+            (None, Format::Other(_), _) => "application/octet-stream",
         }
     }
 }
 
-#[derive(Debug, Serialize)]
-#[serde(untagged)]
-pub enum FlowJson {
-    V1_0(is_04::v1_0_x::Flow),
+fn rational_from_json(rational: &Value) -> Result<Rational, JsonError> {
+    let denominator = match json::opt_field(rational, "denominator") {
+        Some(_) => json::uint(rational, "denominator")?,
+        None => 1,
+    };
+
+    Ok(Rational::new(
+        json::uint(rational, "numerator")?,
+        denominator,
+    ))
+}
+
+fn bit_depth_from_json(bit_depth: u32) -> Result<u8, JsonError> {
+    u8::try_from(bit_depth)
+        .or_else(|_| json::invalid("bit_depth", "expected an 8-bit unsigned integer"))
+}
+
+fn video_from_json(flow: &Value) -> Result<VideoEssence, JsonError> {
+    let mut video = VideoEssence::new(
+        json::uint(flow, "frame_width")?,
+        json::uint(flow, "frame_height")?,
+    );
+
+    if let Some(interlace_mode) = json::opt_string(flow, "interlace_mode")? {
+        video.interlace_mode = match interlace_mode {
+            "progressive" => InterlaceMode::Progressive,
+            "interlaced_tff" => InterlaceMode::InterlacedTff,
+            "interlaced_bff" => InterlaceMode::InterlacedBff,
+            "interlaced_psf" => InterlaceMode::InterlacedPsf,
+            mode => return json::invalid("interlace_mode", format!("unknown mode {}", mode)),
+        };
+    }
+
+    if let Some(colorspace) = json::opt_string(flow, "colorspace")? {
+        video.colorspace = match colorspace {
+            "BT601" => Colorspace::Bt601,
+            "BT709" => Colorspace::Bt709,
+            "BT2020" => Colorspace::Bt2020,
+            "BT2100" => Colorspace::Bt2100,
+            colorspace => {
+                return json::invalid("colorspace", format!("unknown colorspace {}", colorspace))
+            }
+        };
+    }
+
+    if let Some(transfer_characteristic) = json::opt_string(flow, "transfer_characteristic")? {
+        video.transfer_characteristic = match transfer_characteristic {
+            "SDR" => TransferCharacteristic::Sdr,
+            "HLG" => TransferCharacteristic::Hlg,
+            "PQ" => TransferCharacteristic::Pq,
+            tc => {
+                return json::invalid(
+                    "transfer_characteristic",
+                    format!("unknown transfer characteristic {}", tc),
+                )
+            }
+        };
+    }
+
+    // Sampling is implied by the size of the chroma components
+    if let [first, second, ..] = json::array(flow, "components")? {
+        let (width, height) = (json::uint(first, "width")?, json::uint(first, "height")?);
+        let chroma = (json::uint(second, "width")?, json::uint(second, "height")?);
+
+        video.sampling = match json::string(first, "name")? {
+            "R" | "G" | "B" => Sampling::Rgb,
+            _ if chroma == (width, height) => Sampling::YCbCr444,
+            _ if chroma == (width / 2, height) => Sampling::YCbCr422,
+            _ => Sampling::YCbCr420,
+        };
+        video.bit_depth = bit_depth_from_json(json::uint(first, "bit_depth")?)?;
+    }
+
+    Ok(video)
+}
+
+// Picture components of raw video as name, width and height, with chroma
+// subsampling applied
+fn components(video: &VideoEssence) -> Vec<(&'static str, u32, u32)> {
+    let (width, height) = (video.frame_width, video.frame_height);
+
+    let (names, chroma_width, chroma_height) = match video.sampling {
+        Sampling::YCbCr444 => (["Y", "Cb", "Cr"], width, height),
+        Sampling::YCbCr422 => (["Y", "Cb", "Cr"], width / 2, height),
+        Sampling::YCbCr420 => (["Y", "Cb", "Cr"], width / 2, height / 2),
+        Sampling::Rgb => (["R", "G", "B"], width, height),
+    };
+
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            if i == 0 {
+                (*name, width, height)
+            } else {
+                (*name, chroma_width, chroma_height)
+            }
+        })
+        .collect()
+}
+
+impl Resource for Flow {
+    const RESOURCE_TYPE: ResourceType = ResourceType::Flow;
+
+    fn core(&self) -> &ResourceCore {
+        &self.core
+    }
+
+    fn render(&self, api: &APIVersion) -> Result<Value, Error> {
+        self.format.check(api)?;
+
+        Ok(match *api {
+            V1_0 => json!(is_04::v1_0_x::Flow {
+                id: self.core.id.to_string(),
+                version: self.core.version.to_string(),
+                label: self.core.label.clone(),
+                description: self.core.description.clone(),
+                format: self.format.to_string(),
+                tags: json::schema_tags(&self.core.tags),
+                source_id: self.source_id.to_string(),
+                parents: json::schema_ids(&self.parents),
+            }),
+            V1_1 => self.to_v1_1()?,
+            V1_2 => self.to_v1_2()?,
+            V1_3 => self.to_v1_3()?,
+            _ => return Err(Error::UnsupportedVersion(*api)),
+        })
+    }
+}
+
+impl TryFrom<is_04::v1_0_x::Flow> for Flow {
+    type Error = JsonError;
+
+    fn try_from(flow: is_04::v1_0_x::Flow) -> Result<Self, JsonError> {
+        Ok(Flow {
+            core: json::parse_core(
+                &flow.id,
+                &flow.version,
+                &flow.label,
+                &flow.description,
+                &flow.tags,
+            )?,
+            format: json::parse_format(&flow.format)?,
+            source_id: json::parse_id("source_id", &flow.source_id)?,
+            device_id: Uuid::nil(),
+            parents: json::parse_ids("parents", &flow.parents)?,
+            media_type: None,
+            grain_rate: None,
+            essence: None,
+        })
+    }
+}
+
+/// From v1.1 a flow is one of several types, so the schemas of every later
+/// version leave it as JSON.
+impl TryFrom<Value> for Flow {
+    type Error = JsonError;
+
+    fn try_from(flow: Value) -> Result<Self, JsonError> {
+        Flow::from_typed_json(&flow)
+    }
+}
+
+macro_rules! rational {
+    ($version:ident::$schema:ident, $rational:expr) => {
+        is_04::$version::$schema {
+            numerator: $rational.numerator.into(),
+            denominator: Some($rational.denominator.into()),
+        }
+    };
+}
+
+// One of the types of flow, with the fields they all share
+macro_rules! schema_flow {
+    (
+        $flow:ident,
+        $version:ident::$schema:ident,
+        $grain_rate:ident { $($field:ident: $value:expr),* $(,)? }
+    ) => {
+        json!(is_04::$version::$schema {
+            id: $flow.core.id.to_string(),
+            version: $flow.core.version.to_string(),
+            label: $flow.core.label.clone(),
+            description: $flow.core.description.clone(),
+            tags: json::schema_tags(&$flow.core.tags),
+            source_id: $flow.source_id.to_string(),
+            device_id: $flow.device_id.to_string(),
+            parents: json::schema_ids(&$flow.parents),
+            format: $flow.format.to_string(),
+            grain_rate: $flow
+                .grain_rate
+                .map(|rate| rational!($version::$grain_rate, rate)),
+            $($field: $value,)*
+        })
+    };
+}
+
+// The type of a flow follows from its format, media type and essence, with
+// JSON data flows of IS-07 events from v1.3
+macro_rules! to_schema {
+    ($name:ident, $version:ident $(, $json_data:ident, $json_data_grain_rate:ident)?) => {
+        impl Flow {
+            fn $name(&self) -> Result<Value, Error> {
+                let media_type = self.media_type();
+
+                Ok(match (&self.format, &self.essence) {
+                    (Format::Video, Some(FlowEssence::Video(video)))
+                        if media_type == "video/raw" =>
+                    {
+                        schema_flow!(self, $version::FlowVideoRaw, FlowVideoRawGrainRate {
+                            media_type: media_type.to_owned(),
+                            frame_width: video.frame_width.into(),
+                            frame_height: video.frame_height.into(),
+                            interlace_mode: Some(video.interlace_mode.to_string()),
+                            colorspace: Value::from(video.colorspace.to_string()),
+                            transfer_characteristic: Some(Value::from(
+                                video.transfer_characteristic.to_string(),
+                            )),
+                            components: components(video)
+                                .into_iter()
+                                .map(|(name, width, height)| {
+                                    is_04::$version::FlowVideoRawItemComponents {
+                                        name: name.to_owned(),
+                                        width: width.into(),
+                                        height: height.into(),
+                                        bit_depth: video.bit_depth.into(),
+                                    }
+                                })
+                                .collect(),
+                        })
+                    }
+                    (Format::Video, Some(FlowEssence::Video(video))) => {
+                        schema_flow!(self, $version::FlowVideoCoded, FlowVideoCodedGrainRate {
+                            media_type: Value::from(media_type),
+                            frame_width: video.frame_width.into(),
+                            frame_height: video.frame_height.into(),
+                            interlace_mode: Some(video.interlace_mode.to_string()),
+                            colorspace: Value::from(video.colorspace.to_string()),
+                            transfer_characteristic: Some(Value::from(
+                                video.transfer_characteristic.to_string(),
+                            )),
+                        })
+                    }
+                    // Bit depth only applies to linear PCM
+                    (Format::Audio, Some(FlowEssence::Audio(audio)))
+                        if media_type.starts_with("audio/L") =>
+                    {
+                        schema_flow!(self, $version::FlowAudioRaw, FlowAudioRawGrainRate {
+                            media_type: Value::from(media_type),
+                            sample_rate: rational!(
+                                $version::FlowAudioRawSampleRate,
+                                audio.sample_rate
+                            ),
+                            bit_depth: audio.bit_depth.into(),
+                        })
+                    }
+                    (Format::Audio, Some(FlowEssence::Audio(audio))) => {
+                        schema_flow!(self, $version::FlowAudioCoded, FlowAudioCodedGrainRate {
+                            media_type: media_type.to_owned(),
+                            sample_rate: rational!(
+                                $version::FlowAudioCodedSampleRate,
+                                audio.sample_rate
+                            ),
+                        })
+                    }
+                    (Format::Data, _) if media_type == "video/smpte291" => {
+                        schema_flow!(self, $version::FlowSdiancData, FlowSdiancDataGrainRate {
+                            media_type: media_type.to_owned(),
+                            did_sdid: None,
+                        })
+                    }
+                    $(
+                        (Format::Data, _) if media_type == "application/json" => {
+                            schema_flow!(self, $version::$json_data, $json_data_grain_rate {
+                                media_type: media_type.to_owned(),
+                                event_type: None,
+                            })
+                        }
+                    )?
+                    (Format::Data, _) => schema_flow!(self, $version::FlowData, FlowDataGrainRate {
+                        media_type: media_type.to_owned(),
+                    }),
+                    (Format::Mux, _) => schema_flow!(self, $version::FlowMux, FlowMuxGrainRate {
+                        media_type: Value::from(media_type),
+                    }),
+                    _ => return Err(Error::MissingEssence(self.core.id)),
+                })
+            }
+        }
+    };
+}
+
+to_schema!(to_v1_1, v1_1_x);
+to_schema!(to_v1_2, v1_2_x);
+to_schema!(to_v1_3, v1_3_x, FlowJsonData, FlowJsonDataGrainRate);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{device, node};
+
+    #[test]
+    fn essence_is_required() {
+        let device = device(&node());
+
+        for format in [Format::Video, Format::Audio] {
+            let source = Source::builder("source", &device, format).build();
+            let flow = Flow::builder("flow", &source).build();
+            assert!(matches!(flow, Err(Error::MissingEssence(_))));
+        }
+
+        let source = Source::builder("source", &device, Format::Data).build();
+        assert!(Flow::builder("flow", &source).build().is_ok());
+    }
+
+    #[test]
+    fn essence_is_parsed_after_v1_0() {
+        let flow = json!({
+            "id": Uuid::nil(),
+            "version": "0:0",
+            "label": "flow",
+            "description": "",
+            "tags": {},
+            "format": "urn:x-nmos:format:video",
+            "source_id": Uuid::nil(),
+            "parents": [],
+        });
+        assert!(Flow::from_json(&flow, &V1_0).unwrap().essence.is_none());
+
+        let mut flow = flow;
+        flow["device_id"] = json!(Uuid::nil());
+        assert_eq!(
+            Flow::from_json(&flow, &V1_1).unwrap_err(),
+            JsonError::MissingField(String::from("frame_width"))
+        );
+    }
 }
@@ -0,0 +1,336 @@
+use std::{collections::BTreeMap, error::Error as StdError, fmt, str::FromStr};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{tai::TaiTime, version::APIVersion};
+
+use super::{Format, ParseUrnError, ResourceCore, Transport};
+
+/// Error parsing a resource from its IS-04 JSON representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    MissingField(String),
+    InvalidField {
+        field: String,
+        reason: String,
+    },
+    /// The JSON does not match the schema of the API version.
+    Schema(String),
+    /// The API version has no schema to parse with.
+    UnsupportedVersion(APIVersion),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::MissingField(field) => write!(f, "missing field \"{}\"", field),
+            JsonError::InvalidField { field, reason } => {
+                write!(f, "invalid field \"{}\": {}", field, reason)
+            }
+            JsonError::Schema(reason) => write!(f, "does not match the schema: {}", reason),
+            JsonError::UnsupportedVersion(api) => write!(f, "no schema for version {}", api),
+        }
+    }
+}
+
+impl StdError for JsonError {}
+
+pub type Result<T> = std::result::Result<T, JsonError>;
+
+pub(crate) fn invalid<T, R: Into<String>>(field: &str, reason: R) -> Result<T> {
+    Err(JsonError::InvalidField {
+        field: field.to_owned(),
+        reason: reason.into(),
+    })
+}
+
+/// Deserialize the schema type of a resource at an API version.
+pub(crate) fn schema<T: DeserializeOwned>(json: &Value) -> Result<T> {
+    T::deserialize(json).map_err(|err| JsonError::Schema(err.to_string()))
+}
+
+// Present and non-null field
+pub(crate) fn opt_field<'a>(json: &'a Value, field: &str) -> Option<&'a Value> {
+    json.get(field).filter(|value| !value.is_null())
+}
+
+pub(crate) fn field<'a>(json: &'a Value, field: &str) -> Result<&'a Value> {
+    opt_field(json, field).ok_or_else(|| JsonError::MissingField(field.to_owned()))
+}
+
+pub(crate) fn opt_string<'a>(json: &'a Value, field: &str) -> Result<Option<&'a str>> {
+    match opt_field(json, field) {
+        Some(Value::String(s)) => Ok(Some(s)),
+        Some(_) => invalid(field, "expected a string"),
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn string<'a>(json: &'a Value, field: &str) -> Result<&'a str> {
+    opt_string(json, field)?.ok_or_else(|| JsonError::MissingField(field.to_owned()))
+}
+
+pub(crate) fn opt_bool(json: &Value, field: &str) -> Result<Option<bool>> {
+    match opt_field(json, field) {
+        Some(Value::Bool(b)) => Ok(Some(*b)),
+        Some(_) => invalid(field, "expected a boolean"),
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn opt_uint(json: &Value, field: &str) -> Result<Option<u32>> {
+    match opt_field(json, field).map(|value| value.as_u64().map(u32::try_from)) {
+        Some(Some(Ok(n))) => Ok(Some(n)),
+        Some(_) => invalid(field, "expected an unsigned 32-bit integer"),
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn uint(json: &Value, field: &str) -> Result<u32> {
+    opt_uint(json, field)?.ok_or_else(|| JsonError::MissingField(field.to_owned()))
+}
+
+pub(crate) fn opt_id(json: &Value, field: &str) -> Result<Option<Uuid>> {
+    opt_string(json, field)?
+        .map(|id| parse_id(field, id))
+        .transpose()
+}
+
+pub(crate) fn id(json: &Value, field: &str) -> Result<Uuid> {
+    opt_id(json, field)?.ok_or_else(|| JsonError::MissingField(field.to_owned()))
+}
+
+// Optional array, empty if missing
+pub(crate) fn array<'a>(json: &'a Value, field: &str) -> Result<&'a [Value]> {
+    match opt_field(json, field) {
+        Some(Value::Array(array)) => Ok(array),
+        Some(_) => invalid(field, "expected an array"),
+        None => Ok(&[]),
+    }
+}
+
+pub(crate) fn ids(json: &Value, field: &str) -> Result<Vec<Uuid>> {
+    array(json, field)?
+        .iter()
+        .map(|value| match value.as_str().map(Uuid::from_str) {
+            Some(Ok(id)) => Ok(id),
+            _ => invalid(field, "expected an array of UUIDs"),
+        })
+        .collect()
+}
+
+pub(crate) fn strings(json: &Value, field: &str) -> Result<Vec<String>> {
+    array(json, field)?
+        .iter()
+        .map(|value| match value {
+            Value::String(s) => Ok(s.clone()),
+            _ => invalid(field, "expected an array of strings"),
+        })
+        .collect()
+}
+
+/// Parse the fields common to all resources.
+pub(crate) fn core(json: &Value) -> Result<ResourceCore> {
+    let tags = match opt_field(json, "tags") {
+        Some(Value::Object(tags)) => tags
+            .iter()
+            .map(|(key, _)| Ok((key.clone(), strings(&json["tags"], key)?)))
+            .collect::<Result<BTreeMap<_, _>>>()?,
+        Some(_) => return invalid("tags", "expected an object"),
+        None => BTreeMap::new(),
+    };
+
+    Ok(ResourceCore {
+        id: id(json, "id")?,
+        version: parse_version(string(json, "version")?)?,
+        label: string(json, "label")?.to_owned(),
+        description: opt_string(json, "description")?
+            .unwrap_or_default()
+            .to_owned(),
+        tags,
+    })
+}
+
+pub(crate) fn format(json: &Value) -> Result<Format> {
+    parse_format(string(json, "format")?)
+}
+
+// The generated schema types keep IDs, versions and URNs as strings, and
+// leave fields which may take one of several forms as JSON
+
+pub(crate) fn parse_id(field: &str, id: &str) -> Result<Uuid> {
+    Uuid::from_str(id).or_else(|err| invalid(field, err.to_string()))
+}
+
+pub(crate) fn parse_ids(field: &str, ids: &[String]) -> Result<Vec<Uuid>> {
+    ids.iter().map(|id| parse_id(field, id)).collect()
+}
+
+pub(crate) fn parse_version(version: &str) -> Result<TaiTime> {
+    TaiTime::from_str(version).or_else(|err| invalid("version", err.to_string()))
+}
+
+pub(crate) fn parse_tags(tags: &BTreeMap<String, Value>) -> Result<BTreeMap<String, Vec<String>>> {
+    tags.iter()
+        .map(|(key, values)| {
+            let values = values
+                .as_array()
+                .and_then(|values| {
+                    values
+                        .iter()
+                        .map(|value| value.as_str().map(str::to_owned))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .map_or_else(|| invalid(key, "expected an array of strings"), Ok)?;
+            Ok((key.clone(), values))
+        })
+        .collect()
+}
+
+/// Tags as the schema types keep them.
+pub(crate) fn schema_tags(tags: &BTreeMap<String, Vec<String>>) -> BTreeMap<String, Value> {
+    tags.iter()
+        .map(|(key, values)| (key.clone(), Value::from(values.clone())))
+        .collect()
+}
+
+pub(crate) fn schema_ids(ids: &[Uuid]) -> Vec<String> {
+    ids.iter().map(ToString::to_string).collect()
+}
+
+/// Parse the fields common to all resources from a schema type.
+pub(crate) fn parse_core(
+    id: &str,
+    version: &str,
+    label: &str,
+    description: &str,
+    tags: &BTreeMap<String, Value>,
+) -> Result<ResourceCore> {
+    Ok(ResourceCore {
+        id: parse_id("id", id)?,
+        version: parse_version(version)?,
+        label: label.to_owned(),
+        description: description.to_owned(),
+        tags: parse_tags(tags)?,
+    })
+}
+
+pub(crate) fn parse_format(format: &str) -> Result<Format> {
+    format
+        .parse()
+        .or_else(|err: ParseUrnError| invalid("format", err.to_string()))
+}
+
+pub(crate) fn parse_transport(transport: &str) -> Result<Transport> {
+    transport
+        .parse()
+        .or_else(|err: ParseUrnError| invalid("transport", err.to_string()))
+}
+
+/// A string the schema leaves as JSON, such as a URN which is either one of
+/// the registered values or any other.
+pub(crate) fn schema_str<'a>(field: &str, value: &'a Value) -> Result<&'a str> {
+    value
+        .as_str()
+        .map_or_else(|| invalid(field, "expected a string"), Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use nmos_schema::is_04;
+    use serde_json::json;
+
+    use super::*;
+    use crate::resource::{
+        AudioChannel, AudioEssence, Caps, ConstraintSet, Device, Flow, Node, ParameterConstraint,
+        Rational, Receiver, Resource, Sender, Source,
+    };
+    use crate::test_support::{device, node};
+    use crate::version::is_04::{V1_0, V1_1, V1_2, VERSIONS};
+
+    // Parse the rendering of a resource both through `from_json` and by
+    // converting the schema type, and render the results again
+    macro_rules! both_paths {
+        ($type:ident, $resource:expr, $api:expr) => {{
+            let rendered = $resource.render($api).unwrap();
+            let from_json = $type::from_json(&rendered, $api).unwrap();
+            let converted = match *$api {
+                V1_0 => $type::try_from(schema::<is_04::v1_0_x::$type>(&rendered).unwrap()),
+                V1_1 => $type::try_from(schema::<is_04::v1_1_x::$type>(&rendered).unwrap()),
+                V1_2 => $type::try_from(schema::<is_04::v1_2_x::$type>(&rendered).unwrap()),
+                _ => $type::try_from(schema::<is_04::v1_3_x::$type>(&rendered).unwrap()),
+            }
+            .unwrap();
+
+            (
+                from_json.render($api).unwrap(),
+                converted.render($api).unwrap(),
+            )
+        }};
+    }
+
+    #[test]
+    fn from_json_matches_schema_conversion() {
+        let node = node();
+        let device = device(&node);
+        let source = Source::builder("source", &device, Format::Audio)
+            .channel(AudioChannel::new("Left"))
+            .build();
+        let flow = Flow::builder("flow", &source)
+            .audio(AudioEssence::new(Rational::new(48000, 1), 24, 1))
+            .build()
+            .unwrap();
+        let sender = Sender::builder("sender", &device, &flow, Transport::RtpMulticast).build();
+        let receiver =
+            Receiver::builder("receiver", &device, Format::Audio, Transport::RtpMulticast)
+                .caps(Caps::new().media_types(["audio/L24"]))
+                .build();
+
+        for api in VERSIONS {
+            let (from_json, converted) = both_paths!(Node, node, api);
+            assert_eq!(from_json, converted);
+            let (from_json, converted) = both_paths!(Device, device, api);
+            assert_eq!(from_json, converted);
+            let (from_json, converted) = both_paths!(Source, source, api);
+            assert_eq!(from_json, converted);
+            let (from_json, converted) = both_paths!(Sender, sender, api);
+            assert_eq!(from_json, converted);
+            let (from_json, converted) = both_paths!(Receiver, receiver, api);
+            assert_eq!(from_json, converted);
+
+            // From v1.1 the schema of a flow depends on its type, so it is
+            // converted from JSON
+            let rendered = flow.render(api).unwrap();
+            let from_json = Flow::from_json(&rendered, api).unwrap();
+            assert_eq!(from_json.render(api).unwrap(), rendered);
+        }
+    }
+
+    #[test]
+    fn from_json_keeps_constraint_sets() {
+        let device = device(&node());
+        let receiver =
+            Receiver::builder("receiver", &device, Format::Video, Transport::RtpMulticast)
+                .caps(Caps::new().media_types(["video/raw"]).constraint_set(
+                    ConstraintSet::new().constraint(
+                        "urn:x-nmos:cap:format:frame_width",
+                        ParameterConstraint::one_of([1920_u32]),
+                    ),
+                ))
+                .build();
+
+        for api in VERSIONS {
+            let rendered = receiver.render(api).unwrap();
+            let parsed = Receiver::from_json(&rendered, api).unwrap();
+            assert_eq!(parsed.render(api).unwrap(), rendered);
+        }
+
+        let v2_0 = APIVersion { major: 2, minor: 0 };
+        assert_eq!(
+            Receiver::from_json(&json!({}), &v2_0).unwrap_err(),
+            JsonError::UnsupportedVersion(v2_0)
+        );
+    }
+}
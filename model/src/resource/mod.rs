@@ -1,62 +1,181 @@
 use std::{collections::BTreeMap, fmt};
 
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 
-pub use device::{Device, DeviceBuilder, DeviceJson, DeviceType};
-pub use flow::{Flow, FlowBuilder, FlowJson};
-pub use node::{Node, NodeBuilder, NodeJson};
-pub use receiver::{Receiver, ReceiverBuilder, ReceiverJson};
-pub use sender::{Sender, SenderBuilder, SenderJson};
-pub use source::{Source, SourceBuilder, SourceJson};
+pub use caps::{Caps, ConstraintSet, ConstraintValue, ParameterConstraint};
+pub use device::{ControlType, Device, DeviceBuilder, DeviceControl, DeviceType};
+pub use essence::{
+    AudioEssence, Colorspace, FlowEssence, InterlaceMode, Sampling, TransferCharacteristic,
+    VideoEssence,
+};
+pub use flow::{Flow, FlowBuilder};
+pub use json::JsonError;
+pub use node::{Clock, ClockKind, Node, NodeBuilder, NodeInterface, NodeService};
+pub use receiver::{Receiver, ReceiverBuilder, ReceiverSubscription};
+pub use sender::{Sender, SenderBuilder, SenderSubscription};
+pub use source::{AudioChannel, Source, SourceBuilder};
+pub use tags::{Asset, GroupHint, GroupScope, ParseTagError};
+pub use urn::{ParseUrnError, Urn};
 
+use crate::error::Error;
 use crate::tai::TaiTime;
+use crate::version::{
+    is_04::{V1_1, V1_3},
+    APIVersion,
+};
 
+use urn::urn_enum;
+
+pub mod caps;
 mod device;
+mod essence;
 mod flow;
+mod json;
 mod node;
 mod receiver;
 mod sender;
 mod source;
+pub mod tags;
+mod urn;
 
-#[derive(Debug, Clone, Copy)]
-pub enum Format {
-    Video,
-    Audio,
-    Data,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceType {
+    Node,
+    Device,
+    Source,
+    Flow,
+    Sender,
+    Receiver,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum Transport {
-    Rtp,
-    RtpUnicast,
-    RtpMulticast,
-    Dash,
+impl ResourceType {
+    /// Parse a singular resource type name, such as `node`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "node" => Some(ResourceType::Node),
+            "device" => Some(ResourceType::Device),
+            "source" => Some(ResourceType::Source),
+            "flow" => Some(ResourceType::Flow),
+            "sender" => Some(ResourceType::Sender),
+            "receiver" => Some(ResourceType::Receiver),
+            _ => None,
+        }
+    }
+
+    /// Parse the plural form used in resource paths, such as `nodes`.
+    #[must_use]
+    pub fn from_plural(plural: &str) -> Option<Self> {
+        plural.strip_suffix('s').and_then(Self::from_name)
+    }
 }
 
-impl fmt::Display for Format {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+urn_enum!(Format, "format", {
+    Video => "urn:x-nmos:format:video",
+    Audio => "urn:x-nmos:format:audio",
+    Data => "urn:x-nmos:format:data",
+    /// Multiplexed video, audio and data, from v1.1.
+    Mux => "urn:x-nmos:format:mux",
+});
+
+impl Format {
+    /// Whether the format can be used at an API version.
+    ///
+    /// The IS-04 schemas only allow the formats they enumerate.
+    #[must_use]
+    pub fn supports(&self, api: &APIVersion) -> bool {
         match self {
-            Format::Video => write!(f, "urn:x-nmos:format:video"),
-            Format::Audio => write!(f, "urn:x-nmos:format:audio"),
-            Format::Data => write!(f, "urn:x-nmos:format:data"),
+            Format::Video | Format::Audio | Format::Data => true,
+            Format::Mux => *api >= V1_1,
+            Format::Other(_) => false,
         }
     }
 }
 
-impl fmt::Display for Transport {
+urn_enum!(Transport, "transport", {
+    Rtp => "urn:x-nmos:transport:rtp",
+    RtpUnicast => "urn:x-nmos:transport:rtp.ucast",
+    RtpMulticast => "urn:x-nmos:transport:rtp.mcast",
+    Dash => "urn:x-nmos:transport:dash",
+    /// IS-07 events over WebSocket, from v1.3.
+    Websocket => "urn:x-nmos:transport:websocket",
+    /// IS-07 events over MQTT, from v1.3.
+    Mqtt => "urn:x-nmos:transport:mqtt",
+    /// Secure Reliable Transport, from v1.3.
+    Srt => "urn:x-nmos:transport:srt",
+});
+
+impl Transport {
+    /// Whether the transport can be used at an API version.
+    ///
+    /// v1.0 only allows the RTP and DASH transports. Later versions also
+    /// allow transports outside the `urn:x-nmos:` namespace, and v1.3 allows
+    /// any transport within it.
+    #[must_use]
+    pub fn supports(&self, api: &APIVersion) -> bool {
+        match self {
+            Transport::Rtp | Transport::RtpUnicast | Transport::RtpMulticast | Transport::Dash => {
+                true
+            }
+            Transport::Websocket | Transport::Mqtt | Transport::Srt => *api >= V1_3,
+            Transport::Other(urn) if urn.is_nmos() => *api >= V1_3,
+            Transport::Other(_) => *api >= V1_1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rational {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl Rational {
+    #[must_use]
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+}
+
+impl fmt::Display for ResourceType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Transport::Rtp => write!(f, "urn:x-nmos:transport:rtp"),
-            Transport::RtpUnicast => write!(f, "urn:x-nmos:transport:rtp.ucast"),
-            Transport::RtpMulticast => write!(f, "urn:x-nmos:transport:rtp.mcast"),
-            Transport::Dash => write!(f, "urn:x-nmos:transport:dash"),
+            ResourceType::Node => write!(f, "node"),
+            ResourceType::Device => write!(f, "device"),
+            ResourceType::Source => write!(f, "source"),
+            ResourceType::Flow => write!(f, "flow"),
+            ResourceType::Sender => write!(f, "sender"),
+            ResourceType::Receiver => write!(f, "receiver"),
         }
     }
 }
 
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+/// Derive a stable resource id from a seed, such as one per node, and a
+/// path naming the resource within it, such as `device/0/sender/1`.
+#[must_use]
+pub fn derive_id(seed: &Uuid, path: &str) -> Uuid {
+    Uuid::new_v5(seed, path.as_bytes())
+}
+
 #[derive(Debug)]
 #[must_use]
 pub struct ResourceCoreBuilder {
+    pub id: Option<Uuid>,
     pub label: String,
     pub description: Option<String>,
     pub tags: BTreeMap<String, Vec<String>>,
@@ -65,12 +184,19 @@ pub struct ResourceCoreBuilder {
 impl ResourceCoreBuilder {
     pub fn new<S: Into<String>>(label: S) -> Self {
         Self {
+            id: None,
             label: label.into(),
             description: None,
             tags: BTreeMap::new(),
         }
     }
 
+    /// Use a fixed id instead of a random one, so it is kept across restarts.
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.id = Some(id);
+        self
+    }
+
     pub fn description<S: Into<String>>(mut self, description: S) -> Self {
         self.description = Some(description.into());
         self
@@ -87,11 +213,26 @@ impl ResourceCoreBuilder {
         self
     }
 
+    /// Add a BCP-002-01 group hint, keeping any existing ones.
+    pub fn group_hint(mut self, hint: &GroupHint) -> Self {
+        self.tags
+            .entry(tags::GROUP_HINT.to_owned())
+            .or_default()
+            .push(hint.to_string());
+        self
+    }
+
+    /// Set the BCP-002-02 asset tags.
+    pub fn asset(mut self, asset: &Asset) -> Self {
+        self.tags.extend(asset.to_tags());
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> ResourceCore {
         ResourceCore {
-            id: Uuid::new_v4(),
-            version: TaiTime::now(),
+            id: self.id.unwrap_or_else(Uuid::new_v4),
+            version: TaiTime::next_version(),
             label: self.label,
             description: self.description.unwrap_or_default(),
             tags: self.tags,
@@ -99,7 +240,7 @@ impl ResourceCoreBuilder {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ResourceCore {
     pub id: Uuid,
     pub version: TaiTime,
@@ -112,6 +253,30 @@ impl ResourceCore {
     pub fn builder<S: Into<String>>(label: S) -> ResourceCoreBuilder {
         ResourceCoreBuilder::new(label)
     }
+
+    /// The BCP-002-01 group hints of the resource.
+    pub fn group_hints(&self) -> Result<Vec<GroupHint>, ParseTagError> {
+        GroupHint::from_tags(&self.tags)
+    }
+
+    /// The BCP-002-02 asset tags of the resource, if it has any.
+    pub fn asset(&self) -> Result<Option<Asset>, ParseTagError> {
+        Asset::from_tags(&self.tags)
+    }
+}
+
+/// An IS-04 resource, which can be rendered at any supported API version.
+pub trait Resource {
+    const RESOURCE_TYPE: ResourceType;
+
+    fn core(&self) -> &ResourceCore;
+
+    /// Render the resource as the JSON of an API version.
+    fn render(&self, api: &APIVersion) -> Result<Value, Error>;
+
+    fn id(&self) -> Uuid {
+        self.core().id
+    }
 }
 
 #[derive(Debug, Default)]
@@ -1,24 +1,67 @@
 use std::collections::BTreeMap;
 
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
 
-use crate::version::{is_04::V1_0, APIVersion};
+use crate::{
+    error::Error,
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3, VERSIONS},
+        APIVersion,
+    },
+};
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonError},
+    Asset, Caps, Resource, ResourceCore, ResourceCoreBuilder, ResourceType,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct NodeService {
     pub href: String,
+    #[serde(rename = "type")]
     pub type_: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockKind {
+    Internal,
+    Ptp {
+        traceable: bool,
+        version: String,
+        gmid: String,
+        locked: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Clock {
+    pub name: String,
+    pub kind: ClockKind,
+}
+
+/// Network interface of a node, referenced by sender and receiver interface bindings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeInterface {
+    pub name: String,
+    /// LLDP chassis ID, typically the MAC address of the host.
+    pub chassis_id: Option<String>,
+    /// LLDP port ID, typically the MAC address of the interface.
+    pub port_id: String,
+}
+
 #[must_use]
 pub struct NodeBuilder {
     core: ResourceCoreBuilder,
     href: String,
     hostname: Option<String>,
     services: Vec<NodeService>,
+    clocks: Vec<Clock>,
+    interfaces: Vec<NodeInterface>,
+    caps: Caps,
 }
 
 impl NodeBuilder {
@@ -28,14 +71,51 @@ impl NodeBuilder {
             href: href.into(),
             hostname: None,
             services: Vec::new(),
+            clocks: Vec::new(),
+            interfaces: Vec::new(),
+            caps: Caps::default(),
         }
     }
 
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.core = self.core.id(id);
+        self
+    }
+
+    pub fn tag<S, V>(mut self, key: S, values: V) -> Self
+    where
+        S: Into<String>,
+        V: IntoIterator<Item = S>,
+    {
+        self.core = self.core.tag(key, values);
+        self
+    }
+
+    pub fn asset(mut self, asset: &Asset) -> Self {
+        self.core = self.core.asset(asset);
+        self
+    }
+
     pub fn with_service(mut self, service: NodeService) -> Self {
         self.services.push(service);
         self
     }
 
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clocks.push(clock);
+        self
+    }
+
+    pub fn with_interface(mut self, interface: NodeInterface) -> Self {
+        self.interfaces.push(interface);
+        self
+    }
+
+    pub fn caps(mut self, caps: Caps) -> Self {
+        self.caps = caps;
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> Node {
         Node {
@@ -43,16 +123,22 @@ impl NodeBuilder {
             href: self.href,
             hostname: self.hostname,
             services: self.services,
+            clocks: self.clocks,
+            interfaces: self.interfaces,
+            caps: self.caps,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Node {
     pub core: ResourceCore,
     pub href: String,
     pub hostname: Option<String>,
     pub services: Vec<NodeService>,
+    pub clocks: Vec<Clock>,
+    pub interfaces: Vec<NodeInterface>,
+    pub caps: Caps,
 }
 
 impl Node {
@@ -60,9 +146,53 @@ impl Node {
         NodeBuilder::new(label, href)
     }
 
-    #[must_use]
-    pub fn to_json(&self, api: &APIVersion) -> NodeJson {
+    /// Parse a node from its JSON representation at an IS-04 version, through
+    /// the schema type of that version.
+    pub fn from_json(node: &Value, api: &APIVersion) -> Result<Self, JsonError> {
         match *api {
+            V1_0 => Node::try_from(json::schema::<is_04::v1_0_x::Node>(node)?),
+            V1_1 => Node::try_from(json::schema::<is_04::v1_1_x::Node>(node)?),
+            V1_2 => Node::try_from(json::schema::<is_04::v1_2_x::Node>(node)?),
+            V1_3 => Node::try_from(json::schema::<is_04::v1_3_x::Node>(node)?),
+            _ => Err(JsonError::UnsupportedVersion(*api)),
+        }
+    }
+
+    #[must_use]
+    pub fn clock(&self, name: &str) -> Option<&Clock> {
+        self.clocks.iter().find(|clock| clock.name == name)
+    }
+
+    // Node API endpoint as host, port and protocol, taken from the node's href
+    fn endpoint(&self) -> Option<(&str, u16, &str)> {
+        let (protocol, rest) = self.href.split_once("://")?;
+        let authority = rest.split('/').next()?;
+
+        let (host, port) = match authority
+            .rsplit_once(':')
+            .map(|(host, port)| (host, port.parse::<u16>()))
+        {
+            Some((host, Ok(port))) => (host, port),
+            _ => (authority, if protocol == "https" { 443 } else { 80 }),
+        };
+
+        Some((
+            host.trim_start_matches('[').trim_end_matches(']'),
+            port,
+            protocol,
+        ))
+    }
+}
+
+impl Resource for Node {
+    const RESOURCE_TYPE: ResourceType = ResourceType::Node;
+
+    fn core(&self) -> &ResourceCore {
+        &self.core
+    }
+
+    fn render(&self, api: &APIVersion) -> Result<Value, Error> {
+        Ok(match *api {
             V1_0 => {
                 let services = self
                     .services
@@ -73,23 +203,244 @@ impl Node {
                     })
                     .collect();
 
-                NodeJson::V1_0(is_04::v1_0_x::Node {
+                json!(is_04::v1_0_x::Node {
                     id: self.core.id.to_string(),
                     version: self.core.version.to_string(),
                     label: self.core.label.clone(),
                     href: self.href.clone(),
                     hostname: self.hostname.clone(),
-                    caps: BTreeMap::default(),
+                    caps: self.caps.to_json(api),
                     services,
                 })
             }
-            _ => panic!("Unsupported API"),
-        }
+            V1_1 => json!(self.to_v1_1(api)),
+            V1_2 => json!(self.to_v1_2(api)),
+            V1_3 => json!(self.to_v1_3(api)),
+            _ => return Err(Error::UnsupportedVersion(*api)),
+        })
     }
 }
 
-#[derive(Debug, Serialize)]
-#[serde(untagged)]
-pub enum NodeJson {
-    V1_0(is_04::v1_0_x::Node),
+fn clock_from_json(clock: &Value) -> Result<Clock, JsonError> {
+    let kind = match json::string(clock, "ref_type")? {
+        "internal" => ClockKind::Internal,
+        "ptp" => ClockKind::Ptp {
+            traceable: json::opt_bool(clock, "traceable")?.unwrap_or_default(),
+            version: json::string(clock, "version")?.to_owned(),
+            gmid: json::string(clock, "gmid")?.to_owned(),
+            locked: json::opt_bool(clock, "locked")?.unwrap_or_default(),
+        },
+        ref_type => return json::invalid("ref_type", format!("unknown clock type {}", ref_type)),
+    };
+
+    Ok(Clock {
+        name: json::string(clock, "name")?.to_owned(),
+        kind,
+    })
 }
+
+impl TryFrom<is_04::v1_0_x::Node> for Node {
+    type Error = JsonError;
+
+    fn try_from(node: is_04::v1_0_x::Node) -> Result<Self, JsonError> {
+        let services = node
+            .services
+            .into_iter()
+            .map(|service| NodeService {
+                href: service.href,
+                type_: service.type_,
+            })
+            .collect();
+
+        Ok(Node {
+            core: json::parse_core(&node.id, &node.version, &node.label, "", &BTreeMap::new())?,
+            href: node.href,
+            hostname: node.hostname,
+            services,
+            clocks: Vec::new(),
+            interfaces: Vec::new(),
+            caps: Caps::from_schema(&node.caps)?,
+        })
+    }
+}
+
+// Nodes have interfaces from v1.2
+macro_rules! try_from_schema {
+    ($version:ident $(, $interfaces:ident)?) => {
+        impl TryFrom<is_04::$version::Node> for Node {
+            type Error = JsonError;
+
+            fn try_from(node: is_04::$version::Node) -> Result<Self, JsonError> {
+                let services = node
+                    .services
+                    .into_iter()
+                    .map(|service| NodeService {
+                        href: service.href,
+                        type_: service.type_,
+                    })
+                    .collect();
+
+                let clocks = node
+                    .clocks
+                    .iter()
+                    .map(clock_from_json)
+                    .collect::<Result<_, JsonError>>()?;
+
+                let interfaces = try_from_schema!(@interfaces node $(, $interfaces)?);
+
+                Ok(Node {
+                    core: json::parse_core(
+                        &node.id,
+                        &node.version,
+                        &node.label,
+                        &node.description,
+                        &node.tags,
+                    )?,
+                    href: node.href,
+                    hostname: node.hostname,
+                    services,
+                    clocks,
+                    interfaces,
+                    caps: Caps::from_schema(&node.caps)?,
+                })
+            }
+        }
+    };
+    (@interfaces $node:ident) => {
+        Vec::new()
+    };
+    (@interfaces $node:ident, $interfaces:ident) => {
+        $node
+            .$interfaces
+            .into_iter()
+            .map(|interface| NodeInterface {
+                name: interface.name,
+                chassis_id: interface.chassis_id.as_str().map(str::to_owned),
+                port_id: interface.port_id,
+            })
+            .collect()
+    };
+}
+
+try_from_schema!(v1_1_x);
+try_from_schema!(v1_2_x, interfaces);
+try_from_schema!(v1_3_x, interfaces);
+
+// Nodes have interfaces from v1.2, and from v1.3 their services, API
+// endpoints and interfaces have further optional fields
+macro_rules! to_schema {
+    ($name:ident, $version:ident $(, $interfaces:ident)?) => {
+        impl Node {
+            fn $name(&self, api: &APIVersion) -> is_04::$version::Node {
+                let endpoints = self
+                    .endpoint()
+                    .into_iter()
+                    .map(|endpoint| to_schema!(@endpoint $version, endpoint))
+                    .collect();
+
+                let services = self
+                    .services
+                    .iter()
+                    .map(|service| to_schema!(@service $version, service))
+                    .collect();
+
+                let clocks = self
+                    .clocks
+                    .iter()
+                    .map(|clock| match &clock.kind {
+                        ClockKind::Internal => json!(is_04::$version::ClockInternal {
+                            name: clock.name.clone(),
+                            ref_type: String::from("internal"),
+                        }),
+                        ClockKind::Ptp {
+                            traceable,
+                            version,
+                            gmid,
+                            locked,
+                        } => json!(is_04::$version::ClockPtp {
+                            name: clock.name.clone(),
+                            ref_type: String::from("ptp"),
+                            traceable: *traceable,
+                            version: version.clone(),
+                            gmid: gmid.clone(),
+                            locked: *locked,
+                        }),
+                    })
+                    .collect();
+
+                is_04::$version::Node {
+                    id: self.core.id.to_string(),
+                    version: self.core.version.to_string(),
+                    label: self.core.label.clone(),
+                    description: self.core.description.clone(),
+                    tags: json::schema_tags(&self.core.tags),
+                    href: self.href.clone(),
+                    hostname: self.hostname.clone(),
+                    api: is_04::$version::NodeApi {
+                        versions: VERSIONS.iter().map(ToString::to_string).collect(),
+                        endpoints,
+                    },
+                    caps: self.caps.to_json(api),
+                    services,
+                    clocks,
+                    $(
+                        $interfaces: self
+                            .interfaces
+                            .iter()
+                            .map(|interface| to_schema!(@interface $version, interface))
+                            .collect(),
+                    )?
+                }
+            }
+        }
+    };
+    (@endpoint v1_3_x, $endpoint:ident) => {{
+        let (host, port, protocol) = $endpoint;
+        is_04::v1_3_x::NodeApiItemEndpoints {
+            host: Value::from(host),
+            port: port.into(),
+            protocol: protocol.to_owned(),
+            authorization: None,
+        }
+    }};
+    (@endpoint $version:ident, $endpoint:ident) => {{
+        let (host, port, protocol) = $endpoint;
+        is_04::$version::NodeApiItemEndpoints {
+            host: Value::from(host),
+            port: port.into(),
+            protocol: protocol.to_owned(),
+        }
+    }};
+    (@service v1_3_x, $service:ident) => {
+        is_04::v1_3_x::NodeItemServices {
+            href: $service.href.clone(),
+            type_: $service.type_.clone(),
+            authorization: None,
+        }
+    };
+    (@service $version:ident, $service:ident) => {
+        is_04::$version::NodeItemServices {
+            href: $service.href.clone(),
+            type_: $service.type_.clone(),
+        }
+    };
+    (@interface v1_3_x, $interface:ident) => {
+        is_04::v1_3_x::NodeItemInterfaces {
+            name: $interface.name.clone(),
+            chassis_id: json!($interface.chassis_id),
+            port_id: $interface.port_id.clone(),
+            attached_network_device: None,
+        }
+    };
+    (@interface $version:ident, $interface:ident) => {
+        is_04::$version::NodeItemInterfaces {
+            name: $interface.name.clone(),
+            chassis_id: json!($interface.chassis_id),
+            port_id: $interface.port_id.clone(),
+        }
+    };
+}
+
+to_schema!(to_v1_1, v1_1_x);
+to_schema!(to_v1_2, v1_2_x, interfaces);
+to_schema!(to_v1_3, v1_3_x, interfaces);
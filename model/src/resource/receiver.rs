@@ -1,15 +1,29 @@
-use std::collections::BTreeMap;
-
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::{
-    resource::{Device, Format, Transport},
-    version::{is_04::V1_0, APIVersion},
+    connection::{Activation, ReceiverConnection},
+    error::Error,
+    resource::{Caps, Device, Flow, Format, Sender, Source, Transport},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonError},
+    GroupHint, Resource, ResourceCore, ResourceCoreBuilder, ResourceType,
+};
+
+/// Sender a receiver is subscribed to. `active` is reported from IS-04 v1.2.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiverSubscription {
+    pub sender_id: Option<Uuid>,
+    pub active: bool,
+}
 
 #[must_use]
 pub struct ReceiverBuilder {
@@ -17,7 +31,9 @@ pub struct ReceiverBuilder {
     format: Format,
     device_id: Uuid,
     transport: Transport,
-    subscription: Option<Uuid>,
+    interface_bindings: Vec<String>,
+    subscription: ReceiverSubscription,
+    caps: Caps,
 }
 
 impl ReceiverBuilder {
@@ -32,10 +48,17 @@ impl ReceiverBuilder {
             format,
             device_id: device.core.id,
             transport,
-            subscription: None,
+            interface_bindings: Vec::new(),
+            subscription: ReceiverSubscription::default(),
+            caps: Caps::default(),
         }
     }
 
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.core = self.core.id(id);
+        self
+    }
+
     pub fn description<S: Into<String>>(mut self, description: S) -> Self {
         self.core = self.core.description(description);
         self
@@ -50,6 +73,27 @@ impl ReceiverBuilder {
         self
     }
 
+    pub fn group_hint(mut self, hint: &GroupHint) -> Self {
+        self.core = self.core.group_hint(hint);
+        self
+    }
+
+    /// Names of the node interfaces the receiver listens on, one per leg.
+    pub fn interface_bindings<I, S>(mut self, interface_bindings: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.interface_bindings = interface_bindings.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Media types and constraint sets of the flows the receiver accepts.
+    pub fn caps(mut self, caps: Caps) -> Self {
+        self.caps = caps;
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> Receiver {
         Receiver {
@@ -57,18 +101,26 @@ impl ReceiverBuilder {
             format: self.format,
             device_id: self.device_id,
             transport: self.transport,
+            interface_bindings: self.interface_bindings,
             subscription: self.subscription,
+            caps: self.caps,
+            staged: ReceiverConnection::default(),
+            active: ReceiverConnection::default(),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Receiver {
     pub core: ResourceCore,
     pub format: Format,
     pub device_id: Uuid,
     pub transport: Transport,
-    pub subscription: Option<Uuid>,
+    pub interface_bindings: Vec<String>,
+    pub subscription: ReceiverSubscription,
+    pub caps: Caps,
+    pub staged: ReceiverConnection,
+    pub active: ReceiverConnection,
 }
 
 impl Receiver {
@@ -81,44 +133,386 @@ impl Receiver {
         ReceiverBuilder::new(label, device, format, transport)
     }
 
+    /// Parse a receiver from its JSON representation at an IS-04 version,
+    /// through the schema type of that version.
+    ///
+    /// From v1.1 the schema types only know the media and event types in
+    /// `caps`, so the caps, including BCP-004-01 constraint sets, are taken
+    /// from the JSON.
+    pub fn from_json(receiver: &Value, api: &APIVersion) -> Result<Self, JsonError> {
+        let mut parsed = match *api {
+            V1_0 => Receiver::try_from(json::schema::<is_04::v1_0_x::Receiver>(receiver)?),
+            V1_1 => Receiver::try_from(json::schema::<is_04::v1_1_x::Receiver>(receiver)?),
+            V1_2 => Receiver::try_from(json::schema::<is_04::v1_2_x::Receiver>(receiver)?),
+            V1_3 => Receiver::try_from(json::schema::<is_04::v1_3_x::Receiver>(receiver)?),
+            _ => Err(JsonError::UnsupportedVersion(*api)),
+        }?;
+        parsed.caps = Caps::from_json(receiver)?;
+
+        Ok(parsed)
+    }
+
+    /// Whether the receiver can take a sender of a flow, which needs a
+    /// matching format and transport and a flow satisfying its caps.
     #[must_use]
-    pub fn to_json(&self, api: &APIVersion) -> ReceiverJson {
-        match *api {
-            V1_0 => {
-                let tags = self
-                    .core
-                    .tags
-                    .iter()
-                    .fold(BTreeMap::new(), |mut map, (key, array)| {
-                        let value = serde_json::Value::from(array.clone());
-                        map.insert(key.clone(), value);
-                        map
-                    });
+    pub fn accepts(&self, sender: &Sender, flow: &Flow, source: Option<&Source>) -> bool {
+        // Unicast and multicast RTP are both RTP
+        let base = |transport: &Transport| {
+            let urn = transport.as_str();
+            urn.split_once('.').map_or(urn, |(base, _)| base).to_owned()
+        };
+
+        sender.flow_id == flow.core.id
+            && self.format == flow.format
+            && base(&self.transport) == base(&sender.transport)
+            && self.caps.satisfied_by(flow, source)
+    }
 
+    /// Make the staged connection active and update the IS-04 subscription to match.
+    pub fn activate(&mut self) {
+        self.active = self.staged.clone();
+        self.staged.activation = Activation::default();
+        self.subscription = ReceiverSubscription {
+            sender_id: self.active.sender_id,
+            active: self.active.master_enable,
+        };
+    }
+}
+
+impl Resource for Receiver {
+    const RESOURCE_TYPE: ResourceType = ResourceType::Receiver;
+
+    fn core(&self) -> &ResourceCore {
+        &self.core
+    }
+
+    fn render(&self, api: &APIVersion) -> Result<Value, Error> {
+        self.format.check(api)?;
+        self.transport.check(api)?;
+
+        Ok(match *api {
+            V1_0 => {
                 let subscription = is_04::v1_0_x::ReceiverSubscription {
-                    sender_id: self.subscription.map(|s| s.to_string()),
+                    sender_id: self.subscription.sender_id.map(|s| s.to_string()),
                 };
 
-                ReceiverJson::V1_0(is_04::v1_0_x::Receiver {
+                json!(is_04::v1_0_x::Receiver {
                     id: self.core.id.to_string(),
                     version: self.core.version.to_string(),
                     label: self.core.label.clone(),
                     description: self.core.description.clone(),
                     format: self.format.to_string(),
-                    caps: BTreeMap::default(),
-                    tags,
+                    caps: self.caps.to_json(api),
+                    tags: json::schema_tags(&self.core.tags),
                     device_id: self.device_id.to_string(),
                     transport: self.transport.to_string(),
                     subscription,
                 })
             }
-            _ => panic!("Unsupported API"),
-        }
+            V1_1 => self.to_v1_1(api)?,
+            V1_2 => self.to_v1_2(api)?,
+            V1_3 => self.to_v1_3(api)?,
+            _ => return Err(Error::UnsupportedVersion(*api)),
+        })
     }
 }
 
-#[derive(Debug, Serialize)]
-#[serde(untagged)]
-pub enum ReceiverJson {
-    V1_0(is_04::v1_0_x::Receiver),
+impl TryFrom<is_04::v1_0_x::Receiver> for Receiver {
+    type Error = JsonError;
+
+    fn try_from(receiver: is_04::v1_0_x::Receiver) -> Result<Self, JsonError> {
+        let sender_id = match &receiver.subscription.sender_id {
+            Some(sender_id) => Some(json::parse_id("sender_id", sender_id)?),
+            None => None,
+        };
+
+        Ok(Receiver {
+            core: json::parse_core(
+                &receiver.id,
+                &receiver.version,
+                &receiver.label,
+                &receiver.description,
+                &receiver.tags,
+            )?,
+            format: json::parse_format(&receiver.format)?,
+            device_id: json::parse_id("device_id", &receiver.device_id)?,
+            transport: json::parse_transport(&receiver.transport)?,
+            interface_bindings: Vec::new(),
+            // Before v1.2 a subscription is active if it names a sender
+            subscription: ReceiverSubscription {
+                sender_id,
+                active: sender_id.is_some(),
+            },
+            caps: Caps::from_schema(&receiver.caps)?,
+            staged: ReceiverConnection::default(),
+            active: ReceiverConnection::default(),
+        })
+    }
+}
+
+fn media_types(media_types: Option<Vec<Value>>) -> Result<Vec<String>, JsonError> {
+    media_types
+        .unwrap_or_default()
+        .iter()
+        .map(|media_type| json::schema_str("media_types", media_type).map(str::to_owned))
+        .collect()
+}
+
+// From v1.1 a receiver is one of several types, which share the fields used
+// here. Receivers have interface bindings and report whether their
+// subscription is active from v1.2, and data receivers have event types from
+// v1.3.
+macro_rules! try_from_schema {
+    (
+        $version:ident,
+        $v1_2:tt,
+        $($variant:ident => $schema:ident, $transport:ident $events:tt),+
+    ) => {
+        $(
+            try_from_schema!(@variant $version, $schema, $transport, $v1_2, $events);
+        )+
+
+        impl TryFrom<is_04::$version::Receiver> for Receiver {
+            type Error = JsonError;
+
+            fn try_from(receiver: is_04::$version::Receiver) -> Result<Self, JsonError> {
+                match receiver {
+                    $(
+                        is_04::$version::Receiver::$variant(receiver) => {
+                            Receiver::try_from(receiver)
+                        }
+                    )+
+                }
+            }
+        }
+    };
+    (
+        @variant $version:ident,
+        $schema:ident,
+        $transport:ident,
+        [$($bindings:ident, $active:ident)?],
+        [$($events:ident)?]
+    ) => {
+        impl TryFrom<is_04::$version::$schema> for Receiver {
+            type Error = JsonError;
+
+            fn try_from(receiver: is_04::$version::$schema) -> Result<Self, JsonError> {
+                let transport = match &receiver.transport {
+                    is_04::$version::$transport::Variant0(transport)
+                    | is_04::$version::$transport::Variant1(transport) => {
+                        json::parse_transport(json::schema_str("transport", transport)?)?
+                    }
+                };
+
+                let sender_id = match &receiver.subscription.sender_id {
+                    Some(sender_id) => Some(json::parse_id("sender_id", sender_id)?),
+                    None => None,
+                };
+
+                let caps = Caps {
+                    media_types: media_types(receiver.caps.media_types)?,
+                    $($events: receiver.caps.$events.unwrap_or_default(),)?
+                    ..Caps::default()
+                };
+
+                Ok(Receiver {
+                    core: json::parse_core(
+                        &receiver.id,
+                        &receiver.version,
+                        &receiver.label,
+                        &receiver.description,
+                        &receiver.tags,
+                    )?,
+                    format: json::parse_format(&receiver.format)?,
+                    device_id: json::parse_id("device_id", &receiver.device_id)?,
+                    transport,
+                    interface_bindings: try_from_schema!(@bindings receiver $(, $bindings)?),
+                    subscription: ReceiverSubscription {
+                        sender_id,
+                        active: try_from_schema!(@active receiver, sender_id $(, $active)?),
+                    },
+                    caps,
+                    staged: ReceiverConnection::default(),
+                    active: ReceiverConnection::default(),
+                })
+            }
+        }
+    };
+    (@bindings $receiver:ident) => {
+        Vec::new()
+    };
+    (@bindings $receiver:ident, $bindings:ident) => {
+        $receiver.$bindings
+    };
+    (@active $receiver:ident, $sender_id:ident) => {
+        $sender_id.is_some()
+    };
+    (@active $receiver:ident, $sender_id:ident, $active:ident) => {
+        $receiver.subscription.$active
+    };
 }
+
+try_from_schema!(
+    v1_1_x,
+    [],
+    Variant0 => ReceiverVideo, ReceiverVideoTransport [],
+    Variant1 => ReceiverAudio, ReceiverAudioTransport [],
+    Variant2 => ReceiverData, ReceiverDataTransport [],
+    Variant3 => ReceiverMux, ReceiverMuxTransport []
+);
+try_from_schema!(
+    v1_2_x,
+    [interface_bindings, active],
+    Variant0 => ReceiverVideo, ReceiverVideoTransport [],
+    Variant1 => ReceiverAudio, ReceiverAudioTransport [],
+    Variant2 => ReceiverData, ReceiverDataTransport [],
+    Variant3 => ReceiverMux, ReceiverMuxTransport []
+);
+try_from_schema!(
+    v1_3_x,
+    [interface_bindings, active],
+    Variant0 => ReceiverVideo, ReceiverVideoTransport [],
+    Variant1 => ReceiverAudio, ReceiverAudioTransport [],
+    Variant2 => ReceiverData, ReceiverDataTransport [event_types],
+    Variant3 => ReceiverMux, ReceiverMuxTransport []
+);
+
+// Receivers of each format have their own type, with interface bindings and
+// an active subscription from v1.2 and the event types of data receivers
+// from v1.3
+macro_rules! to_schema {
+    (
+        $name:ident,
+        $version:ident,
+        $v1_2:tt,
+        $(
+            $format:ident => $variant:ident,
+            $schema:ident,
+            $caps:ident,
+            $subscription:ident,
+            $transport:ident
+            $events:tt
+        ),+
+    ) => {
+        impl Receiver {
+            fn $name(&self, api: &APIVersion) -> Result<Value, Error> {
+                let mut json = match self.format {
+                    $(
+                        Format::$format => to_schema!(
+                            @variant self,
+                            $version,
+                            $variant,
+                            $schema,
+                            $caps,
+                            $subscription,
+                            $transport,
+                            $v1_2,
+                            $events
+                        ),
+                    )+
+                    Format::Other(_) => {
+                        return Err(Error::UnsupportedUrn(self.format.to_string(), *api))
+                    }
+                };
+
+                // The schema types only know the media and event types of caps, so the
+                // full caps replace them, along with any BCP-004-01 constraint sets
+                json["caps"] = json!(self.caps.to_json(api));
+
+                Ok(json)
+            }
+        }
+    };
+    (
+        @variant $receiver:ident,
+        $version:ident,
+        $variant:ident,
+        $schema:ident,
+        $caps:ident,
+        $subscription:ident,
+        $transport:ident,
+        [$($bindings:ident, $active:ident)?],
+        [$($events:ident)?]
+    ) => {{
+        // Transports outside the `urn:x-nmos:` namespace match the second form
+        let transport = Value::from($receiver.transport.to_string());
+        let transport = match &$receiver.transport {
+            Transport::Other(urn) if !urn.is_nmos() => {
+                is_04::$version::$transport::Variant1(transport)
+            }
+            _ => is_04::$version::$transport::Variant0(transport),
+        };
+
+        let media_types = &$receiver.caps.media_types;
+
+        json!(is_04::$version::Receiver::$variant(is_04::$version::$schema {
+            id: $receiver.core.id.to_string(),
+            version: $receiver.core.version.to_string(),
+            label: $receiver.core.label.clone(),
+            description: $receiver.core.description.clone(),
+            tags: json::schema_tags(&$receiver.core.tags),
+            format: $receiver.format.to_string(),
+            device_id: $receiver.device_id.to_string(),
+            transport,
+            caps: is_04::$version::$caps {
+                media_types: if media_types.is_empty() {
+                    None
+                } else {
+                    Some(media_types.iter().map(|m| Value::from(m.as_str())).collect())
+                },
+                $(
+                    $events: if $receiver.caps.$events.is_empty() {
+                        None
+                    } else {
+                        Some($receiver.caps.$events.clone())
+                    },
+                )?
+            },
+            subscription: is_04::$version::$subscription {
+                sender_id: $receiver.subscription.sender_id.map(|id| id.to_string()),
+                $($active: $receiver.subscription.active,)?
+            },
+            $($bindings: $receiver.interface_bindings.clone(),)?
+        }))
+    }};
+}
+
+to_schema!(
+    to_v1_1,
+    v1_1_x,
+    [],
+    Video => Variant0, ReceiverVideo,
+        ReceiverVideoCaps, ReceiverVideoSubscription, ReceiverVideoTransport [],
+    Audio => Variant1, ReceiverAudio,
+        ReceiverAudioCaps, ReceiverAudioSubscription, ReceiverAudioTransport [],
+    Data => Variant2, ReceiverData,
+        ReceiverDataCaps, ReceiverDataSubscription, ReceiverDataTransport [],
+    Mux => Variant3, ReceiverMux,
+        ReceiverMuxCaps, ReceiverMuxSubscription, ReceiverMuxTransport []
+);
+to_schema!(
+    to_v1_2,
+    v1_2_x,
+    [interface_bindings, active],
+    Video => Variant0, ReceiverVideo,
+        ReceiverVideoCaps, ReceiverVideoSubscription, ReceiverVideoTransport [],
+    Audio => Variant1, ReceiverAudio,
+        ReceiverAudioCaps, ReceiverAudioSubscription, ReceiverAudioTransport [],
+    Data => Variant2, ReceiverData,
+        ReceiverDataCaps, ReceiverDataSubscription, ReceiverDataTransport [],
+    Mux => Variant3, ReceiverMux,
+        ReceiverMuxCaps, ReceiverMuxSubscription, ReceiverMuxTransport []
+);
+to_schema!(
+    to_v1_3,
+    v1_3_x,
+    [interface_bindings, active],
+    Video => Variant0, ReceiverVideo,
+        ReceiverVideoCaps, ReceiverVideoSubscription, ReceiverVideoTransport [],
+    Audio => Variant1, ReceiverAudio,
+        ReceiverAudioCaps, ReceiverAudioSubscription, ReceiverAudioTransport [],
+    Data => Variant2, ReceiverData,
+        ReceiverDataCaps, ReceiverDataSubscription, ReceiverDataTransport [event_types],
+    Mux => Variant3, ReceiverMux,
+        ReceiverMuxCaps, ReceiverMuxSubscription, ReceiverMuxTransport []
+);
@@ -1,15 +1,38 @@
-use std::collections::BTreeMap;
-
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::{
+    connection::{Activation, RtpSenderTransportParams, SenderConnection},
+    error::Error,
     resource::{Device, Flow, Transport},
-    version::{is_04::V1_0, APIVersion},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+use super::{
+    json::{self, JsonError},
+    GroupHint, Resource, ResourceCore, ResourceCoreBuilder, ResourceType,
+};
+
+/// Receiver a sender is sending to. Reported from IS-04 v1.2.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SenderSubscription {
+    pub receiver_id: Option<Uuid>,
+    pub active: bool,
+}
+
+impl From<&SenderConnection> for SenderSubscription {
+    fn from(connection: &SenderConnection) -> Self {
+        Self {
+            receiver_id: connection.receiver_id,
+            active: connection.master_enable,
+        }
+    }
+}
 
 #[must_use]
 pub struct SenderBuilder {
@@ -18,6 +41,8 @@ pub struct SenderBuilder {
     transport: Transport,
     device_id: Uuid,
     manifest_href: Option<String>,
+    interface_bindings: Vec<String>,
+    active: SenderConnection,
 }
 
 impl SenderBuilder {
@@ -33,9 +58,22 @@ impl SenderBuilder {
             transport,
             device_id: device.core.id,
             manifest_href: None,
+            interface_bindings: Vec::new(),
+            active: SenderConnection {
+                transport_params: vec![RtpSenderTransportParams {
+                    rtp_enabled: true,
+                    ..RtpSenderTransportParams::default()
+                }],
+                ..SenderConnection::default()
+            },
         }
     }
 
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.core = self.core.id(id);
+        self
+    }
+
     pub fn description<S: Into<String>>(mut self, description: S) -> Self {
         self.core = self.core.description(description);
         self
@@ -50,12 +88,40 @@ impl SenderBuilder {
         self
     }
 
+    pub fn group_hint(mut self, hint: &GroupHint) -> Self {
+        self.core = self.core.group_hint(hint);
+        self
+    }
+
+    /// Use an externally hosted manifest instead of the SDP served by the node.
     pub fn manifest<S: Into<String>>(mut self, manifest: S) -> Self {
-        // TODO: Store manifest and generate href
         self.manifest_href = Some(manifest.into());
         self
     }
 
+    /// Names of the node interfaces the sender transmits from, one per leg.
+    pub fn interface_bindings<I, S>(mut self, interface_bindings: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.interface_bindings = interface_bindings.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn transport_params<I>(mut self, transport_params: I) -> Self
+    where
+        I: IntoIterator<Item = RtpSenderTransportParams>,
+    {
+        self.active.transport_params = transport_params.into_iter().collect();
+        self
+    }
+
+    pub fn master_enable(mut self, master_enable: bool) -> Self {
+        self.active.master_enable = master_enable;
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> Sender {
         Sender {
@@ -64,17 +130,28 @@ impl SenderBuilder {
             transport: self.transport,
             device_id: self.device_id,
             manifest_href: self.manifest_href.unwrap_or_default(),
+            interface_bindings: self.interface_bindings,
+            subscription: SenderSubscription::from(&self.active),
+            staged: SenderConnection {
+                activation: Activation::default(),
+                ..self.active.clone()
+            },
+            active: self.active,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Sender {
     pub core: ResourceCore,
     pub flow_id: Uuid,
     pub transport: Transport,
     pub device_id: Uuid,
     pub manifest_href: String,
+    pub interface_bindings: Vec<String>,
+    pub subscription: SenderSubscription,
+    pub staged: SenderConnection,
+    pub active: SenderConnection,
 }
 
 impl Sender {
@@ -87,25 +164,48 @@ impl Sender {
         SenderBuilder::new(label, device, flow, transport)
     }
 
-    #[must_use]
-    pub fn to_json(&self, api: &APIVersion) -> SenderJson {
+    /// Parse a sender from its JSON representation at an IS-04 version, through
+    /// the schema type of that version.
+    ///
+    /// Only the IS-04 view of the connection is known, so the IS-05 transport
+    /// parameters are left empty.
+    pub fn from_json(sender: &Value, api: &APIVersion) -> Result<Self, JsonError> {
         match *api {
+            V1_0 => Sender::try_from(json::schema::<is_04::v1_0_x::Sender>(sender)?),
+            V1_1 => Sender::try_from(json::schema::<is_04::v1_1_x::Sender>(sender)?),
+            V1_2 => Sender::try_from(json::schema::<is_04::v1_2_x::Sender>(sender)?),
+            V1_3 => Sender::try_from(json::schema::<is_04::v1_3_x::Sender>(sender)?),
+            _ => Err(JsonError::UnsupportedVersion(*api)),
+        }
+    }
+
+    /// Make the staged connection active and update the IS-04 subscription to match.
+    pub fn activate(&mut self) {
+        self.active = self.staged.clone();
+        self.staged.activation = Activation::default();
+        self.subscription = SenderSubscription::from(&self.active);
+    }
+}
+
+impl Resource for Sender {
+    const RESOURCE_TYPE: ResourceType = ResourceType::Sender;
+
+    fn core(&self) -> &ResourceCore {
+        &self.core
+    }
+
+    fn render(&self, api: &APIVersion) -> Result<Value, Error> {
+        self.transport.check(api)?;
+
+        Ok(match *api {
             V1_0 => {
-                let tags =
-                    if self.core.tags.is_empty() {
-                        None
-                    } else {
-                        Some(self.core.tags.iter().fold(
-                            BTreeMap::new(),
-                            |mut map, (key, array)| {
-                                let value = serde_json::Value::from(array.clone());
-                                map.insert(key.clone(), value);
-                                map
-                            },
-                        ))
-                    };
-
-                SenderJson::V1_0(is_04::v1_0_x::Sender {
+                let tags = if self.core.tags.is_empty() {
+                    None
+                } else {
+                    Some(json::schema_tags(&self.core.tags))
+                };
+
+                json!(is_04::v1_0_x::Sender {
                     id: self.core.id.to_string(),
                     version: self.core.version.to_string(),
                     label: self.core.label.clone(),
@@ -117,13 +217,144 @@ impl Sender {
                     manifest_href: self.manifest_href.clone(),
                 })
             }
-            _ => panic!("Unsupported API"),
-        }
+            V1_1 => json!(self.to_v1_1()),
+            V1_2 => json!(self.to_v1_2()),
+            V1_3 => json!(self.to_v1_3()),
+            _ => return Err(Error::UnsupportedVersion(*api)),
+        })
     }
 }
 
-#[derive(Debug, Serialize)]
-#[serde(untagged)]
-pub enum SenderJson {
-    V1_0(is_04::v1_0_x::Sender),
+impl TryFrom<is_04::v1_0_x::Sender> for Sender {
+    type Error = JsonError;
+
+    fn try_from(sender: is_04::v1_0_x::Sender) -> Result<Self, JsonError> {
+        Ok(Sender {
+            core: json::parse_core(
+                &sender.id,
+                &sender.version,
+                &sender.label,
+                &sender.description,
+                &sender.tags.unwrap_or_default(),
+            )?,
+            flow_id: json::parse_id("flow_id", &sender.flow_id)?,
+            transport: json::parse_transport(&sender.transport)?,
+            device_id: json::parse_id("device_id", &sender.device_id)?,
+            manifest_href: sender.manifest_href,
+            interface_bindings: Vec::new(),
+            subscription: SenderSubscription::default(),
+            staged: SenderConnection::default(),
+            active: SenderConnection::default(),
+        })
+    }
 }
+
+// Senders have interface bindings and a subscription from v1.2
+macro_rules! try_from_schema {
+    ($version:ident $(, $bindings:ident, $subscription:ident)?) => {
+        impl TryFrom<is_04::$version::Sender> for Sender {
+            type Error = JsonError;
+
+            fn try_from(sender: is_04::$version::Sender) -> Result<Self, JsonError> {
+                let transport = match &sender.transport {
+                    is_04::$version::SenderTransport::Variant0(transport)
+                    | is_04::$version::SenderTransport::Variant1(transport) => {
+                        json::parse_transport(json::schema_str("transport", transport)?)?
+                    }
+                };
+
+                Ok(Sender {
+                    core: json::parse_core(
+                        &sender.id,
+                        &sender.version,
+                        &sender.label,
+                        &sender.description,
+                        &sender.tags,
+                    )?,
+                    // Senders may have no flow
+                    flow_id: match &sender.flow_id {
+                        Some(flow_id) => json::parse_id("flow_id", flow_id)?,
+                        None => Uuid::nil(),
+                    },
+                    transport,
+                    device_id: json::parse_id("device_id", &sender.device_id)?,
+                    manifest_href: sender.manifest_href.clone().unwrap_or_default(),
+                    interface_bindings: try_from_schema!(@bindings sender $(, $bindings)?),
+                    subscription: try_from_schema!(@subscription sender $(, $subscription)?),
+                    staged: SenderConnection::default(),
+                    active: SenderConnection::default(),
+                })
+            }
+        }
+    };
+    (@bindings $sender:ident) => {
+        Vec::new()
+    };
+    (@bindings $sender:ident, $bindings:ident) => {
+        $sender.$bindings.clone()
+    };
+    (@subscription $sender:ident) => {
+        SenderSubscription::default()
+    };
+    (@subscription $sender:ident, $subscription:ident) => {
+        SenderSubscription {
+            receiver_id: match &$sender.$subscription.receiver_id {
+                Some(receiver_id) => Some(json::parse_id("receiver_id", receiver_id)?),
+                None => None,
+            },
+            active: $sender.$subscription.active,
+        }
+    };
+}
+
+try_from_schema!(v1_1_x);
+try_from_schema!(v1_2_x, interface_bindings, subscription);
+try_from_schema!(v1_3_x, interface_bindings, subscription);
+
+// Senders have interface bindings and a subscription from v1.2, and may have
+// capabilities from v1.3
+macro_rules! to_schema {
+    ($name:ident, $version:ident $(, $bindings:ident, $subscription:ident)? $(; $caps:ident)?) => {
+        impl Sender {
+            fn $name(&self) -> is_04::$version::Sender {
+                // Transports outside the `urn:x-nmos:` namespace match the second form
+                let transport = Value::from(self.transport.to_string());
+                let transport = match &self.transport {
+                    Transport::Other(urn) if !urn.is_nmos() => {
+                        is_04::$version::SenderTransport::Variant1(transport)
+                    }
+                    _ => is_04::$version::SenderTransport::Variant0(transport),
+                };
+
+                is_04::$version::Sender {
+                    id: self.core.id.to_string(),
+                    version: self.core.version.to_string(),
+                    label: self.core.label.clone(),
+                    description: self.core.description.clone(),
+                    tags: json::schema_tags(&self.core.tags),
+                    // A nil flow id is a sender with no flow
+                    flow_id: if self.flow_id.is_nil() {
+                        None
+                    } else {
+                        Some(self.flow_id.to_string())
+                    },
+                    transport,
+                    device_id: self.device_id.to_string(),
+                    manifest_href: Some(self.manifest_href.clone()),
+                    $(
+                        $bindings: self.interface_bindings.clone(),
+                        $subscription: is_04::$version::SenderSubscription {
+                            receiver_id: self.subscription.receiver_id.map(|id| id.to_string()),
+                            active: self.subscription.active,
+                        },
+                    )?
+                    $($caps: None,)?
+                }
+            }
+        }
+    };
+}
+
+to_schema!(to_v1_1, v1_1_x);
+to_schema!(to_v1_2, v1_2_x, interface_bindings, subscription);
+to_schema!(to_v1_3, v1_3_x, interface_bindings, subscription; caps);
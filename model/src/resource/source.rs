@@ -1,15 +1,45 @@
-use std::collections::BTreeMap;
-
 use nmos_schema::is_04;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::{
+    error::Error,
     resource::{Device, Format},
-    version::{is_04::V1_0, APIVersion},
+    version::{
+        is_04::{V1_0, V1_1, V1_2, V1_3},
+        APIVersion,
+    },
+};
+
+use super::{
+    json::{self, JsonError},
+    Caps, Resource, ResourceCore, ResourceCoreBuilder, ResourceType,
 };
 
-use super::{ResourceCore, ResourceCoreBuilder};
+/// Channel of an audio source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioChannel {
+    pub label: String,
+    /// Symbol of the channel, such as `L` or `U01`.
+    pub symbol: Option<String>,
+}
+
+impl AudioChannel {
+    #[must_use]
+    pub fn new<S: Into<String>>(label: S) -> Self {
+        AudioChannel {
+            label: label.into(),
+            symbol: None,
+        }
+    }
+
+    #[must_use]
+    pub fn symbol<S: Into<String>>(mut self, symbol: S) -> Self {
+        self.symbol = Some(symbol.into());
+        self
+    }
+}
 
 #[must_use]
 pub struct SourceBuilder {
@@ -17,6 +47,9 @@ pub struct SourceBuilder {
     format: Format,
     device_id: Uuid,
     parents: Vec<Uuid>,
+    clock_name: Option<String>,
+    channels: Vec<AudioChannel>,
+    caps: Caps,
 }
 
 impl SourceBuilder {
@@ -26,14 +59,62 @@ impl SourceBuilder {
             format,
             device_id: device.core.id,
             parents: Vec::new(),
+            clock_name: None,
+            channels: Vec::new(),
+            caps: Caps::default(),
         }
     }
 
+    pub fn id(mut self, id: Uuid) -> Self {
+        self.core = self.core.id(id);
+        self
+    }
+
     pub fn description<S: Into<String>>(mut self, description: S) -> Self {
         self.core = self.core.description(description);
         self
     }
 
+    /// Add a source this source is derived from.
+    pub fn parent(self, parent: &Source) -> Self {
+        self.parents(std::iter::once(parent.core.id))
+    }
+
+    /// Add the ids of sources this source is derived from, which may be held elsewhere.
+    pub fn parents<I: IntoIterator<Item = Uuid>>(mut self, parents: I) -> Self {
+        for parent in parents {
+            if !self.parents.contains(&parent) {
+                self.parents.push(parent);
+            }
+        }
+        self
+    }
+
+    pub fn tag<S, V>(mut self, key: S, values: V) -> Self
+    where
+        S: Into<String>,
+        V: IntoIterator<Item = S>,
+    {
+        self.core = self.core.tag(key, values);
+        self
+    }
+
+    pub fn clock_name<S: Into<String>>(mut self, clock_name: S) -> Self {
+        self.clock_name = Some(clock_name.into());
+        self
+    }
+
+    /// Add a channel of an audio source.
+    pub fn channel(mut self, channel: AudioChannel) -> Self {
+        self.channels.push(channel);
+        self
+    }
+
+    pub fn caps(mut self, caps: Caps) -> Self {
+        self.caps = caps;
+        self
+    }
+
     #[must_use]
     pub fn build(self) -> Source {
         Source {
@@ -41,16 +122,23 @@ impl SourceBuilder {
             format: self.format,
             device_id: self.device_id,
             parents: self.parents,
+            clock_name: self.clock_name,
+            channels: self.channels,
+            caps: self.caps,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Source {
     pub core: ResourceCore,
     pub format: Format,
     pub device_id: Uuid,
     pub parents: Vec<Uuid>,
+    pub clock_name: Option<String>,
+    /// Channels of an audio source, from v1.1.
+    pub channels: Vec<AudioChannel>,
+    pub caps: Caps,
 }
 
 impl Source {
@@ -58,41 +146,190 @@ impl Source {
         SourceBuilder::new(label, device, format)
     }
 
-    #[must_use]
-    pub fn to_json(&self, api: &APIVersion) -> SourceJson {
+    /// Parse a source from its JSON representation at an IS-04 version, through
+    /// the schema type of that version.
+    pub fn from_json(source: &Value, api: &APIVersion) -> Result<Self, JsonError> {
         match *api {
-            V1_0 => {
-                let tags = self
-                    .core
-                    .tags
-                    .iter()
-                    .fold(BTreeMap::new(), |mut map, (key, array)| {
-                        let value = serde_json::Value::from(array.clone());
-                        map.insert(key.clone(), value);
-                        map
-                    });
-
-                let parents = self.parents.iter().map(ToString::to_string).collect();
-
-                SourceJson::V1_0(is_04::v1_0_x::Source {
-                    id: self.core.id.to_string(),
-                    version: self.core.version.to_string(),
-                    label: self.core.label.clone(),
-                    description: self.core.description.clone(),
-                    format: self.format.to_string(),
-                    caps: BTreeMap::default(),
-                    tags,
-                    device_id: self.device_id.to_string(),
-                    parents,
-                })
-            }
-            _ => panic!("Unsupported API"),
+            V1_0 => Source::try_from(json::schema::<is_04::v1_0_x::Source>(source)?),
+            V1_1 => Source::try_from(json::schema::<is_04::v1_1_x::Source>(source)?),
+            V1_2 => Source::try_from(json::schema::<is_04::v1_2_x::Source>(source)?),
+            V1_3 => Source::try_from(json::schema::<is_04::v1_3_x::Source>(source)?),
+            _ => Err(JsonError::UnsupportedVersion(*api)),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
-#[serde(untagged)]
-pub enum SourceJson {
-    V1_0(is_04::v1_0_x::Source),
+impl Resource for Source {
+    const RESOURCE_TYPE: ResourceType = ResourceType::Source;
+
+    fn core(&self) -> &ResourceCore {
+        &self.core
+    }
+
+    fn render(&self, api: &APIVersion) -> Result<Value, Error> {
+        self.format.check(api)?;
+
+        Ok(match *api {
+            V1_0 => json!(is_04::v1_0_x::Source {
+                id: self.core.id.to_string(),
+                version: self.core.version.to_string(),
+                label: self.core.label.clone(),
+                description: self.core.description.clone(),
+                format: self.format.to_string(),
+                caps: self.caps.to_json(api),
+                tags: json::schema_tags(&self.core.tags),
+                device_id: self.device_id.to_string(),
+                parents: json::schema_ids(&self.parents),
+            }),
+            V1_1 => json!(self.to_v1_1(api)),
+            V1_2 => json!(self.to_v1_2(api)),
+            V1_3 => json!(self.to_v1_3(api)),
+            _ => return Err(Error::UnsupportedVersion(*api)),
+        })
+    }
+}
+
+impl TryFrom<is_04::v1_0_x::Source> for Source {
+    type Error = JsonError;
+
+    fn try_from(source: is_04::v1_0_x::Source) -> Result<Self, JsonError> {
+        Ok(Source {
+            core: json::parse_core(
+                &source.id,
+                &source.version,
+                &source.label,
+                &source.description,
+                &source.tags,
+            )?,
+            format: json::parse_format(&source.format)?,
+            device_id: json::parse_id("device_id", &source.device_id)?,
+            parents: json::parse_ids("parents", &source.parents)?,
+            clock_name: None,
+            channels: Vec::new(),
+            caps: Caps::from_schema(&source.caps)?,
+        })
+    }
+}
+
+// From v1.1 a source is one of several types, which share the fields used here
+macro_rules! try_from_schema {
+    ($version:ident, $($variant:ident => $schema:ident),+) => {
+        $(
+            impl TryFrom<is_04::$version::$schema> for Source {
+                type Error = JsonError;
+
+                fn try_from(source: is_04::$version::$schema) -> Result<Self, JsonError> {
+                    Ok(Source {
+                        core: json::parse_core(
+                            &source.id,
+                            &source.version,
+                            &source.label,
+                            &source.description,
+                            &source.tags,
+                        )?,
+                        format: json::parse_format(&source.format)?,
+                        device_id: json::parse_id("device_id", &source.device_id)?,
+                        parents: json::parse_ids("parents", &source.parents)?,
+                        clock_name: source.clock_name,
+                        channels: try_from_schema!(@channels $schema, source),
+                        caps: Caps::from_schema(&source.caps)?,
+                    })
+                }
+            }
+        )+
+
+        impl TryFrom<is_04::$version::Source> for Source {
+            type Error = JsonError;
+
+            fn try_from(source: is_04::$version::Source) -> Result<Self, JsonError> {
+                match source {
+                    $(is_04::$version::Source::$variant(source) => Source::try_from(source),)+
+                }
+            }
+        }
+    };
+    (@channels SourceAudio, $source:ident) => {
+        $source
+            .channels
+            .into_iter()
+            .map(|channel| AudioChannel {
+                label: channel.label,
+                symbol: channel
+                    .symbol
+                    .and_then(|symbol| json!(symbol).as_str().map(str::to_owned)),
+            })
+            .collect()
+    };
+    (@channels $schema:ident, $source:ident) => {
+        Vec::new()
+    };
 }
+
+try_from_schema!(v1_1_x, Variant0 => SourceGeneric, Variant1 => SourceAudio);
+try_from_schema!(v1_2_x, Variant0 => SourceGeneric, Variant1 => SourceAudio);
+try_from_schema!(
+    v1_3_x,
+    Variant0 => SourceGeneric,
+    Variant1 => SourceAudio,
+    Variant2 => SourceData
+);
+
+// Audio sources list their channels, while the generic type covers the
+// other formats
+macro_rules! to_schema {
+    ($name:ident, $version:ident) => {
+        impl Source {
+            fn $name(&self, api: &APIVersion) -> is_04::$version::Source {
+                if self.format == Format::Audio {
+                    let channels = self
+                        .channels
+                        .iter()
+                        .map(|channel| is_04::$version::SourceAudioItemChannels {
+                            label: channel.label.clone(),
+                            // The schema's other forms of symbol are JSON, which
+                            // takes any symbol as is
+                            symbol: channel.symbol.as_ref().map(|symbol| {
+                                is_04::$version::SourceAudioItemChannelsSymbol::Variant1(
+                                    Value::from(symbol.as_str()),
+                                )
+                            }),
+                        })
+                        .collect();
+
+                    is_04::$version::Source::Variant1(is_04::$version::SourceAudio {
+                        id: self.core.id.to_string(),
+                        version: self.core.version.to_string(),
+                        label: self.core.label.clone(),
+                        description: self.core.description.clone(),
+                        tags: json::schema_tags(&self.core.tags),
+                        caps: self.caps.to_json(api),
+                        device_id: self.device_id.to_string(),
+                        parents: json::schema_ids(&self.parents),
+                        clock_name: self.clock_name.clone(),
+                        format: self.format.to_string(),
+                        grain_rate: None,
+                        channels,
+                    })
+                } else {
+                    is_04::$version::Source::Variant0(is_04::$version::SourceGeneric {
+                        id: self.core.id.to_string(),
+                        version: self.core.version.to_string(),
+                        label: self.core.label.clone(),
+                        description: self.core.description.clone(),
+                        tags: json::schema_tags(&self.core.tags),
+                        caps: self.caps.to_json(api),
+                        device_id: self.device_id.to_string(),
+                        parents: json::schema_ids(&self.parents),
+                        clock_name: self.clock_name.clone(),
+                        format: self.format.to_string(),
+                        grain_rate: None,
+                    })
+                }
+            }
+        }
+    };
+}
+
+to_schema!(to_v1_1, v1_1_x);
+to_schema!(to_v1_2, v1_2_x);
+to_schema!(to_v1_3, v1_3_x);
@@ -0,0 +1,405 @@
+//! Typed tags of BCP-002-01 natural grouping and BCP-002-02 asset
+//! distinguishing information.
+
+use std::{collections::BTreeMap, error::Error as StdError, fmt, str::FromStr};
+
+pub const GROUP_HINT: &str = "urn:x-nmos:tag:grouphint/v1.0";
+pub const ASSET_MANUFACTURER: &str = "urn:x-nmos:tag:asset:manufacturer/v1.0";
+pub const ASSET_PRODUCT: &str = "urn:x-nmos:tag:asset:product/v1.0";
+pub const ASSET_INSTANCE_ID: &str = "urn:x-nmos:tag:asset:instance-id/v1.0";
+pub const ASSET_FUNCTION: &str = "urn:x-nmos:tag:asset:function/v1.0";
+
+/// Error parsing or validating the value of a tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTagError {
+    tag: &'static str,
+    value: String,
+}
+
+impl ParseTagError {
+    fn new<S: Into<String>>(tag: &'static str, value: S) -> Self {
+        ParseTagError {
+            tag,
+            value: value.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseTagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\" is not a valid value of {}", self.value, self.tag)
+    }
+}
+
+impl StdError for ParseTagError {}
+
+/// Whether a group hint applies within a device or across the whole node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GroupScope {
+    Device,
+    Node,
+}
+
+impl GroupScope {
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            GroupScope::Device => "device",
+            GroupScope::Node => "node",
+        }
+    }
+}
+
+impl fmt::Display for GroupScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Natural grouping of senders and receivers, such as the video and audio
+/// of one SDI input, as a `<group>:<role>[:<scope>]` value of the
+/// `grouphint` tag.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GroupHint {
+    pub group: String,
+    pub role: String,
+    /// Scope of the group name, which is the device if not given.
+    pub scope: Option<GroupScope>,
+}
+
+impl GroupHint {
+    /// A group hint, failing if the group or role is empty or contains `:`.
+    pub fn new<S: Into<String>>(group: S, role: S) -> Result<Self, ParseTagError> {
+        let group = group.into();
+        let role = role.into();
+
+        let valid = |name: &str| !name.is_empty() && !name.contains(':');
+        if !valid(&group) || !valid(&role) {
+            return Err(ParseTagError::new(
+                GROUP_HINT,
+                format!("{}:{}", group, role),
+            ));
+        }
+
+        Ok(GroupHint {
+            group,
+            role,
+            scope: None,
+        })
+    }
+
+    #[must_use]
+    pub fn scope(mut self, scope: GroupScope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// Parse every group hint in a set of tags.
+    pub fn from_tags(tags: &BTreeMap<String, Vec<String>>) -> Result<Vec<Self>, ParseTagError> {
+        tags.get(GROUP_HINT)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .map(|value| value.parse())
+            .collect()
+    }
+}
+
+impl fmt::Display for GroupHint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.group, self.role)?;
+        if let Some(scope) = self.scope {
+            write!(f, ":{}", scope)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GroupHint {
+    type Err = ParseTagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseTagError::new(GROUP_HINT, s);
+
+        let mut parts = s.split(':');
+        let (group, role) = match (parts.next(), parts.next()) {
+            (Some(group), Some(role)) => (group, role),
+            _ => return Err(invalid()),
+        };
+
+        let scope = match parts.next() {
+            None => None,
+            Some("device") => Some(GroupScope::Device),
+            Some("node") => Some(GroupScope::Node),
+            Some(_) => return Err(invalid()),
+        };
+
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        let hint = GroupHint::new(group, role).map_err(|_| invalid())?;
+        Ok(GroupHint { scope, ..hint })
+    }
+}
+
+/// Identity of the hardware or software product behind a node or device.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Asset {
+    pub manufacturer: String,
+    pub product: String,
+    pub instance_id: String,
+    /// Functions of the asset, such as `Camera`, when the product has several.
+    pub functions: Vec<String>,
+}
+
+impl Asset {
+    /// Asset tags, failing if any value is empty.
+    pub fn new<S: Into<String>>(
+        manufacturer: S,
+        product: S,
+        instance_id: S,
+    ) -> Result<Self, ParseTagError> {
+        let asset = Asset {
+            manufacturer: manufacturer.into(),
+            product: product.into(),
+            instance_id: instance_id.into(),
+            functions: Vec::new(),
+        };
+
+        asset.validate()?;
+        Ok(asset)
+    }
+
+    /// Add a function, failing if it is empty.
+    pub fn function<S: Into<String>>(mut self, function: S) -> Result<Self, ParseTagError> {
+        let function = function.into();
+        if function.is_empty() {
+            return Err(ParseTagError::new(ASSET_FUNCTION, function));
+        }
+
+        self.functions.push(function);
+        Ok(self)
+    }
+
+    /// Parse the asset tags in a set of tags, which may have none.
+    ///
+    /// Manufacturer, product and instance ID must each have exactly one value
+    /// when any asset tag is present.
+    pub fn from_tags(tags: &BTreeMap<String, Vec<String>>) -> Result<Option<Self>, ParseTagError> {
+        let keys = [
+            ASSET_MANUFACTURER,
+            ASSET_PRODUCT,
+            ASSET_INSTANCE_ID,
+            ASSET_FUNCTION,
+        ];
+        if !keys.iter().any(|key| tags.contains_key(*key)) {
+            return Ok(None);
+        }
+
+        let single = |tag: &'static str| match tags.get(tag).map(Vec::as_slice) {
+            Some([value]) => Ok(value.clone()),
+            Some(values) => Err(ParseTagError::new(tag, values.join(", "))),
+            None => Err(ParseTagError::new(tag, "")),
+        };
+
+        let asset = Asset {
+            manufacturer: single(ASSET_MANUFACTURER)?,
+            product: single(ASSET_PRODUCT)?,
+            instance_id: single(ASSET_INSTANCE_ID)?,
+            functions: tags.get(ASSET_FUNCTION).cloned().unwrap_or_default(),
+        };
+
+        asset.validate()?;
+        Ok(Some(asset))
+    }
+
+    /// The tags describing the asset.
+    #[must_use]
+    pub fn to_tags(&self) -> BTreeMap<String, Vec<String>> {
+        let mut tags = BTreeMap::new();
+        tags.insert(
+            ASSET_MANUFACTURER.to_owned(),
+            vec![self.manufacturer.clone()],
+        );
+        tags.insert(ASSET_PRODUCT.to_owned(), vec![self.product.clone()]);
+        tags.insert(ASSET_INSTANCE_ID.to_owned(), vec![self.instance_id.clone()]);
+        if !self.functions.is_empty() {
+            tags.insert(ASSET_FUNCTION.to_owned(), self.functions.clone());
+        }
+        tags
+    }
+
+    fn validate(&self) -> Result<(), ParseTagError> {
+        let values = [
+            (ASSET_MANUFACTURER, &self.manufacturer),
+            (ASSET_PRODUCT, &self.product),
+            (ASSET_INSTANCE_ID, &self.instance_id),
+        ];
+
+        for (tag, value) in values {
+            if value.is_empty() {
+                return Err(ParseTagError::new(tag, value.as_str()));
+            }
+        }
+
+        match self.functions.iter().find(|function| function.is_empty()) {
+            Some(function) => Err(ParseTagError::new(ASSET_FUNCTION, function.as_str())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(entries: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(tag, values)| {
+                let values = values.iter().map(|value| (*value).to_owned()).collect();
+                ((*tag).to_owned(), values)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn group_hint_round_trip() {
+        let hints = [
+            ("SDI 1:Video", GroupHint::new("SDI 1", "Video").unwrap()),
+            (
+                "SDI 1:Audio 1:device",
+                GroupHint::new("SDI 1", "Audio 1")
+                    .unwrap()
+                    .scope(GroupScope::Device),
+            ),
+            (
+                "Camera:Tally:node",
+                GroupHint::new("Camera", "Tally")
+                    .unwrap()
+                    .scope(GroupScope::Node),
+            ),
+        ];
+
+        for (value, hint) in &hints {
+            assert_eq!(value.parse::<GroupHint>().as_ref(), Ok(hint));
+            assert_eq!(hint.to_string(), *value);
+        }
+    }
+
+    #[test]
+    fn group_hint_rejects_malformed() {
+        for value in &[
+            "",
+            "SDI 1",
+            ":Video",
+            "SDI 1:",
+            ":",
+            "SDI 1:Video:",
+            "SDI 1:Video:device:node",
+            "SDI 1:Video:Device",
+            "SDI 1:Video:global",
+        ] {
+            assert_eq!(
+                value.parse::<GroupHint>(),
+                Err(ParseTagError::new(GROUP_HINT, *value)),
+                "{:?}",
+                value
+            );
+        }
+
+        assert!(GroupHint::new("", "Video").is_err());
+        assert!(GroupHint::new("SDI 1", "").is_err());
+        assert!(GroupHint::new("SDI:1", "Video").is_err());
+    }
+
+    #[test]
+    fn group_hint_from_tags() {
+        let hints =
+            GroupHint::from_tags(&tags(&[(GROUP_HINT, &["SDI 1:Video", "SDI 1:Audio:node"])]))
+                .unwrap();
+        assert_eq!(
+            hints,
+            vec![
+                GroupHint::new("SDI 1", "Video").unwrap(),
+                GroupHint::new("SDI 1", "Audio")
+                    .unwrap()
+                    .scope(GroupScope::Node),
+            ]
+        );
+
+        assert_eq!(GroupHint::from_tags(&BTreeMap::new()), Ok(Vec::new()));
+        assert!(GroupHint::from_tags(&tags(&[(GROUP_HINT, &["SDI 1:Video", "SDI 1"])])).is_err());
+    }
+
+    #[test]
+    fn asset_round_trip() {
+        let asset = Asset::new("Acme", "Widget", "XYZ123")
+            .unwrap()
+            .function("Camera")
+            .unwrap()
+            .function("Encoder")
+            .unwrap();
+
+        assert_eq!(Asset::from_tags(&asset.to_tags()), Ok(Some(asset)));
+        assert_eq!(Asset::from_tags(&BTreeMap::new()), Ok(None));
+        assert_eq!(
+            Asset::from_tags(&tags(&[(GROUP_HINT, &["SDI 1:Video"])])),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn asset_rejects_missing_tags() {
+        let complete: [(&str, &[&str]); 3] = [
+            (ASSET_MANUFACTURER, &["Acme"]),
+            (ASSET_PRODUCT, &["Widget"]),
+            (ASSET_INSTANCE_ID, &["XYZ123"]),
+        ];
+
+        for missing in &complete {
+            let partial: Vec<_> = complete
+                .iter()
+                .filter(|(tag, _)| tag != &missing.0)
+                .cloned()
+                .collect();
+
+            assert_eq!(
+                Asset::from_tags(&tags(&partial)),
+                Err(ParseTagError::new(missing.0, "")),
+                "without {}",
+                missing.0
+            );
+        }
+
+        // A function alone still needs the rest of the asset
+        assert!(Asset::from_tags(&tags(&[(ASSET_FUNCTION, &["Camera"])])).is_err());
+    }
+
+    #[test]
+    fn asset_rejects_duplicate_tags() {
+        for duplicate in &[ASSET_MANUFACTURER, ASSET_PRODUCT, ASSET_INSTANCE_ID] {
+            let mut set = Asset::new("Acme", "Widget", "XYZ123").unwrap().to_tags();
+            set.get_mut(*duplicate).unwrap().push("Other".to_owned());
+
+            assert!(Asset::from_tags(&set).is_err(), "{} twice", duplicate);
+        }
+
+        let mut set = Asset::new("Acme", "Widget", "XYZ123").unwrap().to_tags();
+        set.insert(ASSET_PRODUCT.to_owned(), Vec::new());
+        assert!(Asset::from_tags(&set).is_err());
+    }
+
+    #[test]
+    fn asset_rejects_empty_values() {
+        assert!(Asset::new("", "Widget", "XYZ123").is_err());
+        assert!(Asset::new("Acme", "Widget", "XYZ123")
+            .unwrap()
+            .function("")
+            .is_err());
+
+        let mut set = Asset::new("Acme", "Widget", "XYZ123").unwrap().to_tags();
+        set.insert(ASSET_FUNCTION.to_owned(), vec![String::new()]);
+        assert!(Asset::from_tags(&set).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use std::{error::Error as StdError, fmt};
+
+/// A URN of the form `urn:x-<namespace>:<category>:<name>`, as used for
+/// IS-04 formats, transports and device types.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Urn(String);
+
+impl Urn {
+    /// Parse a URN in a category, such as `transport`.
+    pub fn parse(urn: &str, category: &'static str) -> Result<Self, ParseUrnError> {
+        let invalid = || ParseUrnError {
+            urn: urn.to_owned(),
+            category,
+        };
+
+        let mut parts = urn.splitn(4, ':');
+        let valid = parts.next() == Some("urn")
+            && parts.next().map_or(false, |namespace| {
+                namespace.strip_prefix("x-").map_or(false, |namespace| {
+                    !namespace.is_empty()
+                        && namespace
+                            .chars()
+                            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                })
+            })
+            && parts.next() == Some(category)
+            && parts.next().map_or(false, |name| {
+                !name.is_empty() && !name.chars().any(char::is_whitespace)
+            });
+
+        if valid {
+            Ok(Urn(urn.to_owned()))
+        } else {
+            Err(invalid())
+        }
+    }
+
+    /// Namespace of the URN, such as `nmos` for `urn:x-nmos:format:video`.
+    #[must_use]
+    pub fn namespace(&self) -> &str {
+        self.0
+            .split(':')
+            .nth(1)
+            .map_or("", |namespace| &namespace[2..])
+    }
+
+    /// Whether the URN is defined by AMWA in the `urn:x-nmos:` namespace.
+    #[must_use]
+    pub fn is_nmos(&self) -> bool {
+        self.namespace() == "nmos"
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Urn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Error parsing a URN of a given category.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseUrnError {
+    urn: String,
+    category: &'static str,
+}
+
+impl fmt::Display for ParseUrnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\" is not a {} URN", self.urn, self.category)
+    }
+}
+
+impl StdError for ParseUrnError {}
+
+// An enum of the URNs of a category defined by NMOS, with any other valid URN
+// of the category held as `Other`
+macro_rules! urn_enum {
+    (
+        $(#[$attr:meta])*
+        $name:ident, $category:literal,
+        { $($(#[$variant_attr:meta])* $variant:ident => $urn:literal,)+ }
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$variant_attr])* $variant,)+
+            /// Any other URN of the category, such as a vendor extension.
+            Other($crate::resource::Urn),
+        }
+
+        impl $name {
+            #[must_use]
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $($name::$variant => $urn,)+
+                    $name::Other(urn) => urn.as_str(),
+                }
+            }
+
+            // Fail unless the URN is allowed at an API version
+            pub(crate) fn check(
+                &self,
+                api: &$crate::version::APIVersion,
+            ) -> Result<(), $crate::error::Error> {
+                if self.supports(api) {
+                    Ok(())
+                } else {
+                    Err($crate::error::Error::UnsupportedUrn(self.to_string(), *api))
+                }
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = $crate::resource::ParseUrnError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($urn => Ok($name::$variant),)+
+                    urn => $crate::resource::Urn::parse(urn, $category).map($name::Other),
+                }
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                <String as serde::Deserialize>::deserialize(deserializer)?
+                    .parse()
+                    .map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+pub(crate) use urn_enum;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::{DeviceType, Format, Transport};
+    use crate::version::{is_04::VERSIONS, APIVersion};
+
+    #[test]
+    fn parse() {
+        for urn in &[
+            "urn:x-nmos:transport:rtp",
+            "urn:x-nmos:transport:rtp.mcast",
+            "urn:x-vendor:transport:custom",
+            "urn:x-vendor-2:transport:custom/v1.0",
+            "urn:x-nmos:transport:name:with:colons",
+        ] {
+            let parsed = Urn::parse(urn, "transport").unwrap();
+            assert_eq!(parsed.to_string(), *urn);
+            assert_eq!(parsed.as_str(), *urn);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_malformed() {
+        for urn in &[
+            "",
+            "urn",
+            "urn:x-nmos:transport",
+            "urn:x-nmos:transport:",
+            "urn:x-nmos:format:video",
+            "urn:nmos:transport:rtp",
+            "urn:x-:transport:rtp",
+            "urn:x-NMOS:transport:rtp",
+            "urn:x-nm os:transport:rtp",
+            "urn:x-nmos:transport:rtp mcast",
+            "URN:x-nmos:transport:rtp",
+            "x-nmos:transport:rtp",
+        ] {
+            assert_eq!(
+                Urn::parse(urn, "transport"),
+                Err(ParseUrnError {
+                    urn: (*urn).to_owned(),
+                    category: "transport",
+                }),
+                "{:?}",
+                urn
+            );
+        }
+    }
+
+    #[test]
+    fn namespace() {
+        let nmos = Urn::parse("urn:x-nmos:transport:rtp", "transport").unwrap();
+        assert_eq!(nmos.namespace(), "nmos");
+        assert!(nmos.is_nmos());
+
+        let vendor = Urn::parse("urn:x-nmos-ext:transport:rtp", "transport").unwrap();
+        assert_eq!(vendor.namespace(), "nmos-ext");
+        assert!(!vendor.is_nmos());
+    }
+
+    #[test]
+    fn enum_parse_and_display() {
+        assert_eq!("urn:x-nmos:format:mux".parse(), Ok(Format::Mux));
+        assert_eq!(
+            "urn:x-nmos:transport:websocket".parse(),
+            Ok(Transport::Websocket)
+        );
+        assert_eq!(
+            "urn:x-vendor:format:custom".parse::<Format>().unwrap(),
+            Format::Other(Urn("urn:x-vendor:format:custom".to_owned()))
+        );
+        assert!("urn:x-nmos:transport:rtp".parse::<Format>().is_err());
+        assert!("video".parse::<Format>().is_err());
+
+        for urn in &[
+            "urn:x-nmos:format:video",
+            "urn:x-nmos:format:mux",
+            "urn:x-vendor:format:custom",
+        ] {
+            assert_eq!(urn.parse::<Format>().unwrap().to_string(), *urn);
+        }
+    }
+
+    // Whether each URN is supported at v1.0, v1.1, v1.2 and v1.3
+    fn check<T>(table: &[(&str, [bool; 4])], supports: fn(&T, &APIVersion) -> bool)
+    where
+        T: std::str::FromStr,
+        T::Err: fmt::Debug,
+    {
+        for (urn, expected) in table {
+            let parsed = urn.parse::<T>().unwrap();
+
+            for (api, expected) in VERSIONS.iter().zip(expected) {
+                assert_eq!(supports(&parsed, api), *expected, "{} at {}", urn, api);
+            }
+        }
+    }
+
+    #[test]
+    fn format_supports() {
+        check(
+            &[
+                ("urn:x-nmos:format:video", [true, true, true, true]),
+                ("urn:x-nmos:format:audio", [true, true, true, true]),
+                ("urn:x-nmos:format:data", [true, true, true, true]),
+                ("urn:x-nmos:format:mux", [false, true, true, true]),
+                ("urn:x-nmos:format:other", [false, false, false, false]),
+                ("urn:x-vendor:format:custom", [false, false, false, false]),
+            ],
+            Format::supports,
+        );
+    }
+
+    #[test]
+    fn transport_supports() {
+        check(
+            &[
+                ("urn:x-nmos:transport:rtp", [true, true, true, true]),
+                ("urn:x-nmos:transport:rtp.ucast", [true, true, true, true]),
+                ("urn:x-nmos:transport:rtp.mcast", [true, true, true, true]),
+                ("urn:x-nmos:transport:dash", [true, true, true, true]),
+                (
+                    "urn:x-nmos:transport:websocket",
+                    [false, false, false, true],
+                ),
+                ("urn:x-nmos:transport:mqtt", [false, false, false, true]),
+                ("urn:x-nmos:transport:srt", [false, false, false, true]),
+                ("urn:x-nmos:transport:future", [false, false, false, true]),
+                ("urn:x-vendor:transport:custom", [false, true, true, true]),
+            ],
+            Transport::supports,
+        );
+    }
+
+    #[test]
+    fn device_type_supports() {
+        check(
+            &[
+                ("urn:x-nmos:device:generic", [true, true, true, true]),
+                ("urn:x-nmos:device:pipeline", [true, true, true, true]),
+                ("urn:x-nmos:device:other", [false, false, false, false]),
+                ("urn:x-vendor:device:custom", [false, true, true, true]),
+            ],
+            DeviceType::supports,
+        );
+    }
+}
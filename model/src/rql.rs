@@ -0,0 +1,525 @@
+//! Resource Query Language, as used by the IS-04 Query API advanced query
+//! syntax (`query.rql`).
+//!
+//! Queries are evaluated against the JSON rendering of resources, for example
+//! `and(eq(format,urn:x-nmos:format:video),matches(label,camera,i))`.
+
+use std::{error::Error as StdError, fmt, str::FromStr};
+
+use regex::{Regex, RegexBuilder};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RqlError {
+    /// The query is not well formed.
+    Syntax(String),
+    /// The query uses an operator which is not supported.
+    UnsupportedOperator(String),
+}
+
+impl fmt::Display for RqlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RqlError::Syntax(reason) => write!(f, "Invalid RQL query: {}", reason),
+            RqlError::UnsupportedOperator(op) => write!(f, "Unsupported RQL operator {}", op),
+        }
+    }
+}
+
+impl StdError for RqlError {}
+
+fn syntax<T, S: Into<String>>(reason: S) -> Result<T, RqlError> {
+    Err(RqlError::Syntax(reason.into()))
+}
+
+/// Value a property is compared against.
+#[derive(Debug, Clone)]
+pub struct Operand {
+    value: Value,
+    raw: String,
+    // Converted from an untyped value, so may also match as a string
+    inferred: bool,
+}
+
+impl Operand {
+    fn parse(raw: &str) -> Result<Self, RqlError> {
+        let raw = percent_decode(raw)?;
+
+        let typed = match raw.split_once(':') {
+            Some(("string", s)) => Some(Value::String(s.to_owned())),
+            Some(("number", n)) => {
+                match n.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+                    Some(n) => Some(Value::Number(n)),
+                    None => return syntax(format!("{} is not a number", n)),
+                }
+            }
+            Some(("boolean", b)) => match b {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => return syntax(format!("{} is not a boolean", b)),
+            },
+            _ => None,
+        };
+
+        if let Some(value) = typed {
+            return Ok(Operand {
+                value,
+                raw,
+                inferred: false,
+            });
+        }
+
+        let value = match raw.as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "null" => Value::Null,
+            _ => match raw
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+            {
+                Some(n) => Value::Number(n),
+                None => Value::String(raw.clone()),
+            },
+        };
+
+        Ok(Operand {
+            value,
+            raw,
+            inferred: true,
+        })
+    }
+
+    fn equals(&self, value: &Value) -> bool {
+        match (value, &self.value) {
+            (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+            (Value::String(s), _) if self.inferred => *s == self.raw,
+            (value, expected) => value == expected,
+        }
+    }
+
+    fn compare(&self, value: &Value) -> Option<std::cmp::Ordering> {
+        match (value, &self.value) {
+            (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+            (Value::String(s), _) if self.inferred => Some(s.as_str().cmp(&self.raw)),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A parsed RQL query.
+#[derive(Debug, Clone)]
+pub enum Query {
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    Compare(Comparison, Vec<String>, Operand),
+    In(Vec<String>, Vec<Operand>),
+    Out(Vec<String>, Vec<Operand>),
+    Contains(Vec<String>, Operand),
+    Excludes(Vec<String>, Operand),
+    Matches(Vec<String>, Regex),
+}
+
+impl Query {
+    /// Whether a JSON resource satisfies the query.
+    ///
+    /// A property naming an array matches if any of its elements does.
+    #[must_use]
+    pub fn matches(&self, resource: &Value) -> bool {
+        match self {
+            Query::And(queries) => queries.iter().all(|query| query.matches(resource)),
+            Query::Or(queries) => queries.iter().any(|query| query.matches(resource)),
+            Query::Not(query) => !query.matches(resource),
+            Query::Compare(Comparison::Eq, path, operand) => lookup(resource, path)
+                .iter()
+                .any(|value| operand.equals(value)),
+            Query::Compare(Comparison::Ne, path, operand) => !lookup(resource, path)
+                .iter()
+                .any(|value| operand.equals(value)),
+            Query::Compare(comparison, path, operand) => {
+                lookup(resource, path).iter().any(|value| {
+                    match (comparison, operand.compare(value)) {
+                        (Comparison::Lt, Some(ordering)) => ordering.is_lt(),
+                        (Comparison::Le, Some(ordering)) => ordering.is_le(),
+                        (Comparison::Gt, Some(ordering)) => ordering.is_gt(),
+                        (Comparison::Ge, Some(ordering)) => ordering.is_ge(),
+                        _ => false,
+                    }
+                })
+            }
+            Query::In(path, operands) => lookup(resource, path)
+                .iter()
+                .any(|value| operands.iter().any(|operand| operand.equals(value))),
+            Query::Out(path, operands) => !lookup(resource, path)
+                .iter()
+                .any(|value| operands.iter().any(|operand| operand.equals(value))),
+            Query::Contains(path, operand) => lookup(resource, path)
+                .iter()
+                .any(|value| operand.equals(value)),
+            Query::Excludes(path, operand) => !lookup(resource, path)
+                .iter()
+                .any(|value| operand.equals(value)),
+            Query::Matches(path, regex) => lookup(resource, path)
+                .iter()
+                .any(|value| value.as_str().map_or(false, |s| regex.is_match(s))),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = RqlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = tokenize(s).into_iter().peekable();
+        let node = parse_node(&mut tokens)?;
+
+        match tokens.next() {
+            None => query(node),
+            Some(token) => syntax(format!("unexpected {:?}", token)),
+        }
+    }
+}
+
+/// Parse an RQL query.
+pub fn parse(s: &str) -> Result<Query, RqlError> {
+    s.parse()
+}
+
+// Values reached by following a path, flattening arrays along the way
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Vec<&'a Value> {
+    match (value, path.split_first()) {
+        (Value::Array(values), _) => values
+            .iter()
+            .flat_map(|value| lookup(value, path))
+            .collect(),
+        (Value::Object(object), Some((key, rest))) => object
+            .get(key)
+            .map(|value| lookup(value, rest))
+            .unwrap_or_default(),
+        (value, None) => vec![value],
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Comma,
+    Text(String),
+}
+
+fn tokenize(s: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+
+    for c in s.chars() {
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            c => {
+                text.push(c);
+                continue;
+            }
+        };
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(std::mem::take(&mut text)));
+        }
+        tokens.push(token);
+    }
+
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+
+    tokens
+}
+
+// Syntax tree before operators are interpreted
+#[derive(Debug)]
+enum Node {
+    Call(String, Vec<Node>),
+    Array(Vec<Node>),
+    Text(String),
+}
+
+type Tokens = std::iter::Peekable<std::vec::IntoIter<Token>>;
+
+fn parse_node(tokens: &mut Tokens) -> Result<Node, RqlError> {
+    match tokens.next() {
+        Some(Token::Text(text)) if tokens.peek() == Some(&Token::Open) => {
+            tokens.next();
+            Ok(Node::Call(text, parse_args(tokens)?))
+        }
+        Some(Token::Text(text)) => Ok(Node::Text(text)),
+        Some(Token::Open) => Ok(Node::Array(parse_args(tokens)?)),
+        Some(token) => syntax(format!("unexpected {:?}", token)),
+        None => syntax("unexpected end of query"),
+    }
+}
+
+// Comma separated arguments up to and including the closing parenthesis
+fn parse_args(tokens: &mut Tokens) -> Result<Vec<Node>, RqlError> {
+    let mut args = Vec::new();
+
+    if tokens.peek() == Some(&Token::Close) {
+        tokens.next();
+        return Ok(args);
+    }
+
+    loop {
+        args.push(parse_node(tokens)?);
+
+        match tokens.next() {
+            Some(Token::Comma) => {}
+            Some(Token::Close) => return Ok(args),
+            Some(token) => return syntax(format!("unexpected {:?}", token)),
+            None => return syntax("missing closing parenthesis"),
+        }
+    }
+}
+
+fn query(node: Node) -> Result<Query, RqlError> {
+    let (name, args) = match node {
+        Node::Call(name, args) => (name, args),
+        node => return syntax(format!("expected an operator, found {:?}", node)),
+    };
+
+    match (name.as_str(), args.len()) {
+        ("and", _) => Ok(Query::And(queries(args)?)),
+        ("or", _) => Ok(Query::Or(queries(args)?)),
+        ("not", 1) => Ok(Query::Not(Box::new(query(one(args))?))),
+        ("eq" | "ne" | "lt" | "le" | "gt" | "ge", 2) => {
+            let comparison = match name.as_str() {
+                "eq" => Comparison::Eq,
+                "ne" => Comparison::Ne,
+                "lt" => Comparison::Lt,
+                "le" => Comparison::Le,
+                "gt" => Comparison::Gt,
+                _ => Comparison::Ge,
+            };
+            let (path, value) = two(args);
+            Ok(Query::Compare(comparison, property(path)?, operand(value)?))
+        }
+        ("in" | "out", n) if n >= 2 => {
+            let mut args = args.into_iter();
+            let path = property(args.next().expect("Checked argument count"))?;
+
+            // Accept both in(prop,(a,b)) and in(prop,a,b)
+            let values: Vec<Node> = match (args.next(), args.len()) {
+                (Some(Node::Array(values)), 0) => values,
+                (Some(first), _) => std::iter::once(first).chain(args).collect(),
+                (None, _) => Vec::new(),
+            };
+            let operands = values
+                .into_iter()
+                .map(operand)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if name == "in" {
+                Ok(Query::In(path, operands))
+            } else {
+                Ok(Query::Out(path, operands))
+            }
+        }
+        ("contains", 2) => {
+            let (path, value) = two(args);
+            Ok(Query::Contains(property(path)?, operand(value)?))
+        }
+        ("excludes", 2) => {
+            let (path, value) = two(args);
+            Ok(Query::Excludes(property(path)?, operand(value)?))
+        }
+        ("matches", 2 | 3) => {
+            let mut args = args.into_iter();
+            let path = property(args.next().expect("Checked argument count"))?;
+            let pattern = text(args.next().expect("Checked argument count"))?;
+            let flags = match args.next() {
+                Some(flags) => text(flags)?,
+                None => String::new(),
+            };
+
+            let regex = RegexBuilder::new(pattern.strip_prefix("string:").unwrap_or(&pattern))
+                .case_insensitive(flags.contains('i'))
+                .build()
+                .or_else(|err| syntax(err.to_string()))?;
+
+            Ok(Query::Matches(path, regex))
+        }
+        (
+            "not" | "eq" | "ne" | "lt" | "le" | "gt" | "ge" | "in" | "out" | "contains"
+            | "excludes" | "matches",
+            _,
+        ) => syntax(format!("wrong number of arguments to {}", name)),
+        _ => Err(RqlError::UnsupportedOperator(name)),
+    }
+}
+
+fn queries(args: Vec<Node>) -> Result<Vec<Query>, RqlError> {
+    args.into_iter().map(query).collect()
+}
+
+fn one(args: Vec<Node>) -> Node {
+    args.into_iter().next().expect("Checked argument count")
+}
+
+fn two(args: Vec<Node>) -> (Node, Node) {
+    let mut args = args.into_iter();
+    let first = args.next().expect("Checked argument count");
+    let second = args.next().expect("Checked argument count");
+    (first, second)
+}
+
+fn text(node: Node) -> Result<String, RqlError> {
+    match node {
+        Node::Text(text) => percent_decode(&text),
+        node => syntax(format!("expected a value, found {:?}", node)),
+    }
+}
+
+// A property is either dotted, such as caps.media_types, or an array of keys
+fn property(node: Node) -> Result<Vec<String>, RqlError> {
+    match node {
+        Node::Text(text) => Ok(percent_decode(&text)?
+            .split('.')
+            .map(str::to_owned)
+            .collect()),
+        Node::Array(keys) => keys.into_iter().map(text).collect(),
+        node @ Node::Call(..) => syntax(format!("expected a property, found {:?}", node)),
+    }
+}
+
+fn operand(node: Node) -> Result<Operand, RqlError> {
+    match node {
+        Node::Text(text) => Operand::parse(&text),
+        node => syntax(format!("expected a value, found {:?}", node)),
+    }
+}
+
+// Values may themselves be URL encoded to escape reserved characters
+fn percent_decode(s: &str) -> Result<String, RqlError> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex: Vec<u8> = iter.by_ref().take(2).collect();
+            let byte = std::str::from_utf8(&hex)
+                .ok()
+                .filter(|hex| hex.len() == 2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+            match byte {
+                Some(byte) => bytes.push(byte),
+                None => return syntax(format!("invalid percent encoding in {}", s)),
+            }
+        } else {
+            bytes.push(b);
+        }
+    }
+
+    String::from_utf8(bytes).or_else(|_| syntax(format!("invalid UTF-8 in {}", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sender() -> Value {
+        json!({
+            "label": "Camera 1",
+            "transport": "urn:x-nmos:transport:rtp.mcast",
+            "version": "1441812152:154331951",
+            "caps": { "media_types": ["video/raw", "video/jxsv"] },
+            "grain_rate": { "numerator": 50, "denominator": 1 },
+            "tags": { "location": ["studio 1"] },
+        })
+    }
+
+    fn matches(rql: &str) -> bool {
+        parse(rql).unwrap().matches(&sender())
+    }
+
+    #[test]
+    fn nested_and_or() {
+        assert!(matches(
+            "and(or(eq(label,Camera 2),eq(label,Camera 1)),not(eq(transport,urn:x-nmos:transport:websocket)))"
+        ));
+        assert!(matches(
+            "or(and(eq(label,Camera 1),eq(grain_rate.numerator,25)),and(eq(label,Camera 1),eq(grain_rate.numerator,50)))"
+        ));
+        assert!(!matches(
+            "and(eq(label,Camera 1),or(eq(grain_rate.numerator,25),eq(grain_rate.numerator,30)))"
+        ));
+        assert!(matches("and()"));
+        assert!(!matches("or()"));
+    }
+
+    #[test]
+    fn matches_regex() {
+        assert!(matches("matches(label,^Camera)"));
+        assert!(!matches("matches(label,camera)"));
+        assert!(matches("matches(label,camera,i)"));
+        assert!(matches("matches(caps.media_types,jxsv$)"));
+        assert!(!matches("matches(grain_rate.numerator,50)"));
+    }
+
+    #[test]
+    fn numeric_and_string_equality() {
+        // Untyped values match both numbers and strings
+        assert!(matches("eq(grain_rate.numerator,50)"));
+        assert!(matches("eq(grain_rate.numerator,50.0)"));
+        assert!(matches("eq(grain_rate.numerator,number:50)"));
+        assert!(!matches("eq(grain_rate.numerator,string:50)"));
+        assert!(matches("gt(grain_rate.numerator,30)"));
+
+        let resource = json!({ "label": "50" });
+        assert!(parse("eq(label,50)").unwrap().matches(&resource));
+        assert!(parse("eq(label,string:50)").unwrap().matches(&resource));
+        assert!(!parse("eq(label,number:50)").unwrap().matches(&resource));
+    }
+
+    #[test]
+    fn properties_and_values() {
+        assert!(matches("eq((tags,location),studio%201)"));
+        assert!(matches("in(caps.media_types,(audio/L24,video/raw))"));
+        assert!(matches("out(transport,urn:x-nmos:transport:websocket)"));
+        assert!(matches("contains(caps.media_types,video/jxsv)"));
+        assert!(matches("excludes(caps.media_types,video/smpte291)"));
+    }
+
+    #[test]
+    fn malformed() {
+        let syntax = |rql: &str| matches!(parse(rql), Err(RqlError::Syntax(_)));
+
+        assert!(syntax(""));
+        assert!(syntax("label"));
+        assert!(syntax("eq(label,Camera 1"));
+        assert!(syntax("eq(label,Camera 1))"));
+        assert!(syntax("eq(label)"));
+        assert!(syntax("and(eq(label,a),label)"));
+        assert!(syntax("eq(label,number:fifty)"));
+        assert!(syntax("eq(label,%4)"));
+        assert!(syntax("matches(label,[)"));
+        assert_eq!(
+            parse("sort(label)").unwrap_err(),
+            RqlError::UnsupportedOperator(String::from("sort"))
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use std::net::IpAddr;
+
+use crate::{
+    connection::RtpSenderTransportParams,
+    resource::{
+        AudioEssence, ClockKind, Flow, FlowEssence, Format, InterlaceMode, Sender, VideoEssence,
+    },
+};
+
+use super::{Error, Result};
+
+const VIDEO_PAYLOAD_TYPE: u8 = 96;
+const AUDIO_PAYLOAD_TYPE: u8 = 97;
+const DATA_PAYLOAD_TYPE: u8 = 100;
+
+const RTP_CLOCK_RATE: u32 = 90000;
+const MULTICAST_TTL: u8 = 64;
+
+// Coded video encodings carried as SMPTE ST 2110-22 streams
+const ST2110_22_ENCODINGS: [&str; 1] = ["jxsv"];
+
+// Media identifiers of the SMPTE 2022-7 legs
+const MIDS: [&str; 2] = ["primary", "secondary"];
+
+/// Generate an SDP transport file for the active transport parameters of `sender`.
+///
+/// The format parameters follow SMPTE ST 2110-20 for raw video, ST 2110-22 for
+/// JPEG XS and ST 2110-30 for PCM audio. Two enabled legs are described as a
+/// SMPTE 2022-7 duplication group. `clock` is the reference clock of the flow's
+/// source, if known.
+pub fn generate(sender: &Sender, flow: &Flow, clock: Option<&ClockKind>) -> Result<String> {
+    let legs: Vec<_> = sender
+        .active
+        .transport_params
+        .iter()
+        .filter(|leg| leg.rtp_enabled)
+        .collect();
+
+    let origin = legs
+        .first()
+        .ok_or(Error::NoTransport)?
+        .source_ip
+        .ok_or(Error::MissingParameter("source_ip"))?;
+
+    // Session name must not be empty
+    let name = if sender.core.label.is_empty() {
+        " "
+    } else {
+        &sender.core.label
+    };
+
+    let version = sender.core.version.secs();
+
+    let mut lines = vec![
+        String::from("v=0"),
+        format!(
+            "o=- {} {} IN {} {}",
+            version,
+            version,
+            address_type(&origin),
+            origin
+        ),
+        format!("s={}", name),
+    ];
+
+    if !sender.core.description.is_empty() {
+        lines.push(format!("i={}", sender.core.description));
+    }
+
+    lines.push(String::from("t=0 0"));
+
+    let redundant = legs.len() > 1;
+    if redundant {
+        lines.push(format!("a=group:DUP {}", MIDS.join(" ")));
+    }
+
+    for (mid, leg) in MIDS.iter().zip(legs) {
+        media_description(&mut lines, flow, leg, clock)?;
+
+        if redundant {
+            lines.push(format!("a=mid:{}", mid));
+        }
+    }
+
+    // SDP lines are terminated by CRLF
+    let mut sdp = lines.join("\r\n");
+    sdp.push_str("\r\n");
+
+    Ok(sdp)
+}
+
+fn media_description(
+    lines: &mut Vec<String>,
+    flow: &Flow,
+    leg: &RtpSenderTransportParams,
+    clock: Option<&ClockKind>,
+) -> Result<()> {
+    let destination_ip = leg
+        .destination_ip
+        .ok_or(Error::MissingParameter("destination_ip"))?;
+    let destination_port = leg
+        .destination_port
+        .ok_or(Error::MissingParameter("destination_port"))?;
+
+    let (media, payload_type) = match &flow.format {
+        Format::Video => ("video", VIDEO_PAYLOAD_TYPE),
+        Format::Audio => ("audio", AUDIO_PAYLOAD_TYPE),
+        Format::Data => ("video", DATA_PAYLOAD_TYPE),
+        format => return Err(Error::UnsupportedFormat(format.clone())),
+    };
+
+    lines.push(format!(
+        "m={} {} RTP/AVP {}",
+        media, destination_port, payload_type
+    ));
+
+    match destination_ip {
+        IpAddr::V4(ip) if ip.is_multicast() => {
+            lines.push(format!("c=IN IP4 {}/{}", ip, MULTICAST_TTL));
+        }
+        ip => lines.push(format!("c=IN {} {}", address_type(&ip), ip)),
+    }
+
+    // Source specific multicast
+    if let (true, Some(source_ip)) = (destination_ip.is_multicast(), leg.source_ip) {
+        lines.push(format!(
+            "a=source-filter: incl IN {} {} {}",
+            address_type(&destination_ip),
+            destination_ip,
+            source_ip
+        ));
+    }
+
+    // Encoding name is the media subtype
+    let media_type = flow.media_type();
+    let encoding = media_type
+        .split_once('/')
+        .map_or(media_type, |(_, subtype)| subtype);
+
+    match (&flow.format, &flow.essence) {
+        (Format::Video, Some(FlowEssence::Video(video))) => {
+            lines.push(format!(
+                "a=rtpmap:{} {}/{}",
+                payload_type, encoding, RTP_CLOCK_RATE
+            ));
+            if let Some(params) = video_format_parameters(flow, video, encoding)? {
+                lines.push(format!("a=fmtp:{} {}", payload_type, params));
+            }
+        }
+        (Format::Audio, Some(FlowEssence::Audio(audio))) => {
+            lines.push(format!(
+                "a=rtpmap:{} {}/{}/{}",
+                payload_type,
+                encoding,
+                audio_clock_rate(audio)?,
+                audio.channels
+            ));
+            lines.push(format!(
+                "a=fmtp:{} channel-order={}",
+                payload_type,
+                channel_order(audio)
+            ));
+            lines.push(String::from("a=ptime:1"));
+        }
+        (Format::Data, _) => {
+            lines.push(format!(
+                "a=rtpmap:{} {}/{}",
+                payload_type, encoding, RTP_CLOCK_RATE
+            ));
+        }
+        _ => return Err(Error::MissingEssence),
+    }
+
+    if let Some(clock) = clock {
+        lines.push(format!("a=ts-refclk:{}", reference_clock(clock)));
+    }
+
+    lines.push(String::from("a=mediaclk:direct=0"));
+
+    Ok(())
+}
+
+// RTP clock rate of PCM audio, which is its whole number sample rate
+fn audio_clock_rate(audio: &AudioEssence) -> Result<u32> {
+    let rate = &audio.sample_rate;
+    if rate.denominator == 0 || rate.numerator % rate.denominator != 0 {
+        return Err(Error::InvalidParameter("sample_rate"));
+    }
+
+    Ok(rate.numerator / rate.denominator)
+}
+
+// Format parameters of the video encodings mapped by SMPTE ST 2110
+//
+// Other coded video, such as VP8 (RFC 7741), is fully described by its rtpmap.
+fn video_format_parameters(
+    flow: &Flow,
+    video: &VideoEssence,
+    encoding: &str,
+) -> Result<Option<String>> {
+    let mut params = Vec::new();
+
+    if encoding == "raw" {
+        // ST 2110-20 requires the exact frame rate
+        let grain_rate = flow
+            .grain_rate
+            .ok_or(Error::MissingParameter("grain_rate"))?;
+
+        params.push(format!("sampling={}", video.sampling));
+        params.push(format!("width={}", video.frame_width));
+        params.push(format!("height={}", video.frame_height));
+        params.push(format!("exactframerate={}", grain_rate));
+        params.push(format!("depth={}", video.bit_depth));
+        params.push(format!("TCS={}", video.transfer_characteristic));
+        params.push(format!("colorimetry={}", video.colorspace));
+        params.push(String::from("PM=2110GPM"));
+        params.push(String::from("SSN=ST2110-20:2017"));
+        params.push(String::from("TP=2110TPN"));
+    } else if ST2110_22_ENCODINGS.contains(&encoding) {
+        params.push(format!("width={}", video.frame_width));
+        params.push(format!("height={}", video.frame_height));
+        if let Some(grain_rate) = flow.grain_rate {
+            params.push(format!("exactframerate={}", grain_rate));
+        }
+        params.push(format!("sampling={}", video.sampling));
+        params.push(format!("depth={}", video.bit_depth));
+        params.push(format!("TCS={}", video.transfer_characteristic));
+        params.push(format!("colorimetry={}", video.colorspace));
+        params.push(String::from("SSN=ST2110-22:2019"));
+        params.push(String::from("TP=2110TPN"));
+    } else {
+        return Ok(None);
+    }
+
+    match video.interlace_mode {
+        InterlaceMode::Progressive => {}
+        InterlaceMode::InterlacedTff | InterlaceMode::InterlacedBff => {
+            params.push(String::from("interlace"));
+        }
+        InterlaceMode::InterlacedPsf => {
+            params.push(String::from("interlace"));
+            params.push(String::from("segmented"));
+        }
+    }
+
+    // Each parameter is terminated by a semicolon
+    Ok(Some(
+        params
+            .iter()
+            .map(|param| format!("{};", param))
+            .collect::<Vec<_>>()
+            .join(" "),
+    ))
+}
+
+fn channel_order(audio: &AudioEssence) -> String {
+    match audio.channels {
+        1 => String::from("SMPTE2110.(M)"),
+        2 => String::from("SMPTE2110.(ST)"),
+        n => format!("SMPTE2110.(U{:02})", n),
+    }
+}
+
+fn reference_clock(clock: &ClockKind) -> String {
+    match clock {
+        ClockKind::Internal => String::from("local"),
+        ClockKind::Ptp {
+            traceable: true,
+            version,
+            ..
+        } => format!("ptp={}:traceable", version),
+        ClockKind::Ptp { version, gmid, .. } => {
+            format!("ptp={}:{}", version, gmid.to_uppercase())
+        }
+    }
+}
+
+fn address_type(ip: &IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "IP4",
+        IpAddr::V6(_) => "IP6",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::{Rational, Source, Transport};
+    use crate::test_support::{device, node, sender_leg};
+
+    fn source(format: Format) -> Source {
+        Source::builder("source", &device(&node()), format).build()
+    }
+
+    fn sender(flow: &Flow) -> Sender {
+        Sender::builder("sender", &device(&node()), flow, Transport::RtpMulticast)
+            .transport_params(vec![sender_leg()])
+            .build()
+    }
+
+    fn media_lines(sdp: &str) -> Vec<&str> {
+        sdp.split("\r\n")
+            .skip_while(|line| !line.starts_with("m="))
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn raw_video() {
+        let source = source(Format::Video);
+        let flow = Flow::builder("flow", &source)
+            .grain_rate(Rational::new(50, 1))
+            .video(VideoEssence::new(1920, 1080))
+            .build()
+            .unwrap();
+        let sdp = generate(&sender(&flow), &flow, None).unwrap();
+
+        assert_eq!(
+            media_lines(&sdp),
+            [
+                "m=video 5004 RTP/AVP 96",
+                "c=IN IP4 239.1.1.1/64",
+                "a=source-filter: incl IN IP4 239.1.1.1 192.168.1.10",
+                "a=rtpmap:96 raw/90000",
+                "a=fmtp:96 sampling=YCbCr-4:2:2; width=1920; height=1080; exactframerate=50; \
+                 depth=10; TCS=SDR; colorimetry=BT709; PM=2110GPM; SSN=ST2110-20:2017; TP=2110TPN;",
+                "a=mediaclk:direct=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn audio() {
+        let source = source(Format::Audio);
+        let flow = Flow::builder("flow", &source)
+            .audio(AudioEssence::new(Rational::new(48000, 1), 24, 8))
+            .build()
+            .unwrap();
+        let sdp = generate(&sender(&flow), &flow, Some(&ClockKind::Internal)).unwrap();
+
+        assert_eq!(
+            media_lines(&sdp),
+            [
+                "m=audio 5004 RTP/AVP 97",
+                "c=IN IP4 239.1.1.1/64",
+                "a=source-filter: incl IN IP4 239.1.1.1 192.168.1.10",
+                "a=rtpmap:97 L24/48000/8",
+                "a=fmtp:97 channel-order=SMPTE2110.(U08)",
+                "a=ptime:1",
+                "a=ts-refclk:local",
+                "a=mediaclk:direct=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_sample_rate() {
+        let source = source(Format::Audio);
+        for rate in [Rational::new(48000, 0), Rational::new(48000, 1001)] {
+            let flow = Flow::builder("flow", &source)
+                .audio(AudioEssence::new(rate, 24, 2))
+                .build()
+                .unwrap();
+
+            assert_eq!(
+                generate(&sender(&flow), &flow, None),
+                Err(Error::InvalidParameter("sample_rate"))
+            );
+        }
+    }
+
+    #[test]
+    fn jpeg_xs_video() {
+        let source = source(Format::Video);
+        let flow = Flow::builder("flow", &source)
+            .media_type("video/jxsv")
+            .grain_rate(Rational::new(50, 1))
+            .video(VideoEssence::new(1920, 1080))
+            .build()
+            .unwrap();
+        let sdp = generate(&sender(&flow), &flow, None).unwrap();
+        let lines = media_lines(&sdp);
+
+        assert!(lines.contains(&"a=rtpmap:96 jxsv/90000"));
+        let fmtp = lines
+            .iter()
+            .find(|line| line.starts_with("a=fmtp:96 "))
+            .unwrap();
+        assert!(fmtp.contains("SSN=ST2110-22:2019;"));
+        assert!(fmtp.contains("width=1920;"));
+    }
+
+    #[test]
+    fn vp8_video() {
+        let source = source(Format::Video);
+        let flow = Flow::builder("flow", &source)
+            .media_type("video/VP8")
+            .video(VideoEssence::new(1280, 720))
+            .build()
+            .unwrap();
+        let sdp = generate(&sender(&flow), &flow, None).unwrap();
+
+        assert_eq!(
+            media_lines(&sdp),
+            [
+                "m=video 5004 RTP/AVP 96",
+                "c=IN IP4 239.1.1.1/64",
+                "a=source-filter: incl IN IP4 239.1.1.1 192.168.1.10",
+                "a=rtpmap:96 VP8/90000",
+                "a=mediaclk:direct=0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+mod generate;
+mod parse;
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use crate::resource::Format;
+
+pub use generate::generate;
+pub use parse::{
+    Attribute, Connection, FilterMode, Fmtp, MediaDescription, Origin, RtpMap, SessionDescription,
+    SourceFilter,
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// No RTP enabled transport leg to describe.
+    NoTransport,
+    /// A transport leg or flow is missing a parameter required by the SDP.
+    MissingParameter(&'static str),
+    /// A flow parameter has a value the SDP cannot describe.
+    InvalidParameter(&'static str),
+    /// Flow has no essence parameters matching its format.
+    MissingEssence,
+    /// Flow has a format with no RTP mapping.
+    UnsupportedFormat(Format),
+    /// Malformed SDP, with the 1-based line number of the offending line.
+    Parse { line: usize, reason: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoTransport => write!(f, "No RTP enabled transport legs"),
+            Error::MissingParameter(param) => write!(f, "Missing parameter: {}", param),
+            Error::InvalidParameter(param) => write!(f, "Invalid parameter: {}", param),
+            Error::MissingEssence => write!(f, "Flow is missing essence parameters"),
+            Error::UnsupportedFormat(format) => write!(f, "No RTP mapping for format {}", format),
+            Error::Parse { line, reason } => write!(f, "SDP line {}: {}", line, reason),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::connection::{RtpReceiverTransportParams, RtpSenderTransportParams};
+    use crate::resource::{AudioEssence, Flow, Rational, Sender, Source, Transport, VideoEssence};
+    use crate::test_support::{device, node, sender_leg};
+
+    // A SMPTE 2022-7 leg on a second network
+    fn secondary_leg() -> RtpSenderTransportParams {
+        RtpSenderTransportParams {
+            source_ip: Some("192.168.2.10".parse().unwrap()),
+            destination_ip: Some("239.2.1.1".parse().unwrap()),
+            ..sender_leg()
+        }
+    }
+
+    // Generate the SDP of a sender of the flow, and parse it back
+    fn round_trip(
+        format: Format,
+        flow: impl FnOnce(&Source) -> Flow,
+        legs: Vec<RtpSenderTransportParams>,
+    ) -> (String, SessionDescription) {
+        let device = device(&node());
+        let source = Source::builder("source", &device, format).build();
+        let flow = flow(&source);
+        let sender = Sender::builder("sender", &device, &flow, Transport::RtpMulticast)
+            .transport_params(legs)
+            .build();
+
+        let sdp = generate(&sender, &flow, None).unwrap();
+        let session = SessionDescription::from_str(&sdp).unwrap();
+        (sdp, session)
+    }
+
+    #[test]
+    fn video_round_trip() {
+        let (_, session) = round_trip(
+            Format::Video,
+            |source| {
+                Flow::builder("flow", source)
+                    .grain_rate(Rational::new(60000, 1001))
+                    .video(VideoEssence::new(1920, 1080))
+                    .build()
+                    .unwrap()
+            },
+            vec![sender_leg()],
+        );
+
+        assert_eq!(session.name, "sender");
+        assert_eq!(session.media_type().as_deref(), Some("video/raw"));
+
+        let media = session.legs()[0];
+        assert_eq!(media.payload_types, [96]);
+        assert_eq!(
+            media.rtpmap(96),
+            Some(&RtpMap {
+                payload_type: 96,
+                encoding: String::from("raw"),
+                clock_rate: 90000,
+                channels: None,
+            })
+        );
+
+        let fmtp = media.fmtp(96).unwrap();
+        assert_eq!(fmtp.get("width"), Some("1920"));
+        assert_eq!(fmtp.get("height"), Some("1080"));
+        assert_eq!(fmtp.get("exactframerate"), Some("60000/1001"));
+        assert_eq!(fmtp.get("sampling"), Some("YCbCr-4:2:2"));
+        assert!(!fmtp.has_flag("interlace"));
+
+        assert_eq!(
+            session.receiver_transport_params().unwrap(),
+            [RtpReceiverTransportParams {
+                source_ip: Some("192.168.1.10".parse().unwrap()),
+                multicast_ip: Some("239.1.1.1".parse().unwrap()),
+                interface_ip: None,
+                destination_port: Some(5004),
+                rtp_enabled: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn redundant_audio_round_trip() {
+        let (_, session) = round_trip(
+            Format::Audio,
+            |source| {
+                Flow::builder("flow", source)
+                    .audio(AudioEssence::new(Rational::new(48000, 1), 24, 2))
+                    .build()
+                    .unwrap()
+            },
+            vec![sender_leg(), secondary_leg()],
+        );
+
+        assert_eq!(session.media_type().as_deref(), Some("audio/L24"));
+
+        let legs = session.legs();
+        assert_eq!(legs.len(), 2);
+        assert_eq!(legs[0].mid(), Some("primary"));
+        assert_eq!(legs[1].mid(), Some("secondary"));
+        assert_eq!(legs[0].rtpmap(97).unwrap().channels, Some(2));
+        assert_eq!(
+            legs[0].fmtp(97).unwrap().get("channel-order"),
+            Some("SMPTE2110.(ST)")
+        );
+
+        let params = session.receiver_transport_params().unwrap();
+        let multicast: Vec<_> = params.iter().map(|leg| leg.multicast_ip).collect();
+        assert_eq!(
+            multicast,
+            [
+                Some("239.1.1.1".parse().unwrap()),
+                Some("239.2.1.1".parse().unwrap())
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_legs_are_left_out() {
+        let mut secondary = secondary_leg();
+        secondary.rtp_enabled = false;
+
+        let (sdp, session) = round_trip(
+            Format::Video,
+            |source| {
+                Flow::builder("flow", source)
+                    .grain_rate(Rational::new(25, 1))
+                    .video(VideoEssence::new(1280, 720))
+                    .build()
+                    .unwrap()
+            },
+            vec![sender_leg(), secondary],
+        );
+
+        assert!(!sdp.contains("a=group:DUP"));
+        assert_eq!(session.media.len(), 1);
+        assert_eq!(session.receiver_transport_params().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            SessionDescription::from_str(""),
+            Err(Error::Parse { line: 1, .. })
+        ));
+        assert!(matches!(
+            SessionDescription::from_str("v=1\r\n"),
+            Err(Error::Parse { line: 1, .. })
+        ));
+        assert!(matches!(
+            SessionDescription::from_str("v=0\r\nm=video 5004 RTP/AVP 96\r\n"),
+            Err(Error::Parse { line: 2, .. })
+        ));
+        assert_eq!(
+            SessionDescription::from_str("v=0\r\no=- 1 1 IN IP4 192.168.1.10\r\n"),
+            Err(Error::MissingParameter("session name"))
+        );
+    }
+}
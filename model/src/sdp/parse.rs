@@ -0,0 +1,561 @@
+use std::{net::IpAddr, str::FromStr};
+
+use crate::connection::RtpReceiverTransportParams;
+
+use super::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub username: String,
+    pub session_id: String,
+    pub session_version: String,
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connection {
+    pub address: IpAddr,
+    pub ttl: Option<u8>,
+    pub count: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    Include,
+    Exclude,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFilter {
+    pub mode: FilterMode,
+    /// Destination address, or `None` for the wildcard `*`.
+    pub destination: Option<IpAddr>,
+    pub sources: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtpMap {
+    pub payload_type: u8,
+    pub encoding: String,
+    pub clock_rate: u32,
+    pub channels: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fmtp {
+    pub payload_type: u8,
+    /// Format parameters in order of appearance. Flags such as `interlace` have no value.
+    pub parameters: Vec<(String, Option<String>)>,
+}
+
+impl Fmtp {
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(key, _)| key == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    #[must_use]
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.parameters.iter().any(|(key, _)| key == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attribute {
+    RtpMap(RtpMap),
+    Fmtp(Fmtp),
+    SourceFilter(SourceFilter),
+    Group {
+        semantics: String,
+        mids: Vec<String>,
+    },
+    Mid(String),
+    TsRefClk(String),
+    MediaClk(String),
+    Other {
+        name: String,
+        value: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaDescription {
+    pub media: String,
+    pub port: u16,
+    pub port_count: Option<u16>,
+    pub protocol: String,
+    pub payload_types: Vec<u8>,
+    pub information: Option<String>,
+    pub connection: Option<Connection>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl MediaDescription {
+    #[must_use]
+    pub fn rtpmap(&self, payload_type: u8) -> Option<&RtpMap> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::RtpMap(rtpmap) if rtpmap.payload_type == payload_type => Some(rtpmap),
+                _ => None,
+            })
+    }
+
+    #[must_use]
+    pub fn fmtp(&self, payload_type: u8) -> Option<&Fmtp> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::Fmtp(fmtp) if fmtp.payload_type == payload_type => Some(fmtp),
+                _ => None,
+            })
+    }
+
+    #[must_use]
+    pub fn mid(&self) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::Mid(mid) => Some(mid.as_str()),
+                _ => None,
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionDescription {
+    pub origin: Origin,
+    pub name: String,
+    pub information: Option<String>,
+    pub connection: Option<Connection>,
+    pub attributes: Vec<Attribute>,
+    pub media: Vec<MediaDescription>,
+}
+
+impl SessionDescription {
+    /// Connection of a media description, falling back to the session level.
+    #[must_use]
+    pub fn connection(&self, media: &MediaDescription) -> Option<Connection> {
+        media.connection.or(self.connection)
+    }
+
+    /// Source filter of a media description, falling back to the session level.
+    #[must_use]
+    pub fn source_filter<'a>(&'a self, media: &'a MediaDescription) -> Option<&'a SourceFilter> {
+        let find = |attributes: &'a [Attribute]| {
+            attributes.iter().find_map(|attribute| match attribute {
+                Attribute::SourceFilter(filter) => Some(filter),
+                _ => None,
+            })
+        };
+
+        find(&media.attributes).or_else(|| find(&self.attributes))
+    }
+
+    /// Media descriptions in the order of a SMPTE 2022-7 `DUP` group if present.
+    #[must_use]
+    pub fn legs(&self) -> Vec<&MediaDescription> {
+        let group = self
+            .attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::Group { semantics, mids } if semantics == "DUP" => Some(mids),
+                _ => None,
+            });
+
+        match group {
+            Some(mids) => mids
+                .iter()
+                .filter_map(|mid| self.media.iter().find(|media| media.mid() == Some(mid)))
+                .collect(),
+            None => self.media.iter().take(1).collect(),
+        }
+    }
+
+    /// Media type of the session, such as `video/raw`, from the media and
+    /// encoding name of the first leg.
+    #[must_use]
+    pub fn media_type(&self) -> Option<String> {
+        let media = self.legs().into_iter().next()?;
+        let rtpmap = media.rtpmap(*media.payload_types.first()?)?;
+        Some(format!("{}/{}", media.media, rtpmap.encoding))
+    }
+
+    /// IS-05 receiver transport parameters for each leg of the session.
+    pub fn receiver_transport_params(&self) -> Result<Vec<RtpReceiverTransportParams>> {
+        let legs = self.legs();
+        if legs.is_empty() {
+            return Err(Error::NoTransport);
+        }
+
+        legs.into_iter()
+            .map(|media| {
+                let connection = self
+                    .connection(media)
+                    .ok_or(Error::MissingParameter("connection"))?;
+
+                let multicast_ip = Some(connection.address).filter(IpAddr::is_multicast);
+
+                let source_ip = self
+                    .source_filter(media)
+                    .filter(|filter| filter.mode == FilterMode::Include)
+                    .and_then(|filter| filter.sources.first().copied());
+
+                Ok(RtpReceiverTransportParams {
+                    source_ip,
+                    multicast_ip,
+                    interface_ip: None,
+                    destination_port: Some(media.port),
+                    rtp_enabled: true,
+                })
+            })
+            .collect()
+    }
+}
+
+impl FromStr for SessionDescription {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut origin = None;
+        let mut name = None;
+        let mut information = None;
+        let mut connection = None;
+        let mut attributes = Vec::new();
+        let mut media: Vec<MediaDescription> = Vec::new();
+
+        let lines = s
+            .split('\n')
+            .map(|line| line.trim_end_matches('\r'))
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty());
+
+        let mut seen_version = false;
+
+        for (index, line) in lines {
+            let line_number = index + 1;
+            let error = |reason: String| Error::Parse {
+                line: line_number,
+                reason,
+            };
+
+            let (kind, value) = match line.split_once('=') {
+                Some((kind, value)) if kind.len() == 1 => (kind, value),
+                _ => return Err(error(format!("expected <type>=<value>, found {:?}", line))),
+            };
+
+            if !seen_version {
+                if kind != "v" {
+                    return Err(error(String::from("session must start with v=")));
+                }
+                if value != "0" {
+                    return Err(error(format!("unsupported SDP version {:?}", value)));
+                }
+                seen_version = true;
+                continue;
+            }
+
+            match (kind, media.last_mut()) {
+                ("o", None) => origin = Some(parse_origin(value).map_err(error)?),
+                ("s", None) => name = Some(value.to_string()),
+                ("i", None) => information = Some(value.to_string()),
+                ("c", None) => connection = Some(parse_connection(value).map_err(error)?),
+                ("a", None) => attributes.push(parse_attribute(value).map_err(error)?),
+                ("i", Some(current)) => current.information = Some(value.to_string()),
+                ("c", Some(current)) => {
+                    current.connection = Some(parse_connection(value).map_err(error)?);
+                }
+                ("a", Some(current)) => {
+                    current
+                        .attributes
+                        .push(parse_attribute(value).map_err(error)?);
+                }
+                ("m", _) => {
+                    if origin.is_none() || name.is_none() {
+                        return Err(error(String::from(
+                            "media description before o= and s= lines",
+                        )));
+                    }
+                    media.push(parse_media(value).map_err(error)?);
+                }
+                ("o", Some(_)) | ("s", Some(_)) => {
+                    return Err(error(format!(
+                        "{}= is not allowed in a media description",
+                        kind
+                    )))
+                }
+                // Timing, bandwidth, encryption keys etc. are not needed
+                _ => {}
+            }
+        }
+
+        if !seen_version {
+            return Err(Error::Parse {
+                line: 1,
+                reason: String::from("empty session description"),
+            });
+        }
+
+        Ok(Self {
+            origin: origin.ok_or(Error::MissingParameter("origin"))?,
+            name: name.ok_or(Error::MissingParameter("session name"))?,
+            information,
+            connection,
+            attributes,
+            media,
+        })
+    }
+}
+
+fn parse_origin(value: &str) -> std::result::Result<Origin, String> {
+    let fields: Vec<_> = value.split(' ').collect();
+
+    match fields.as_slice() {
+        [username, session_id, session_version, "IN", _, address] => Ok(Origin {
+            username: username.to_string(),
+            session_id: session_id.to_string(),
+            session_version: session_version.to_string(),
+            address: address.to_string(),
+        }),
+        _ => Err(format!("malformed origin {:?}", value)),
+    }
+}
+
+fn parse_connection(value: &str) -> std::result::Result<Connection, String> {
+    let fields: Vec<_> = value.split(' ').collect();
+
+    let (address_type, address) = match fields.as_slice() {
+        ["IN", address_type, address] => (*address_type, *address),
+        _ => return Err(format!("malformed connection {:?}", value)),
+    };
+
+    let mut parts = address.split('/');
+    let address = parts.next().unwrap_or_default();
+    let address = IpAddr::from_str(address)
+        .map_err(|_| format!("invalid connection address {:?}", address))?;
+
+    match (address_type, address) {
+        ("IP4", IpAddr::V4(_)) | ("IP6", IpAddr::V6(_)) => {}
+        _ => {
+            return Err(format!(
+                "address {} does not match address type {}",
+                address, address_type
+            ))
+        }
+    }
+
+    let mut next_number = |name: &str| -> std::result::Result<Option<u32>, String> {
+        parts
+            .next()
+            .map(|part| {
+                part.parse::<u32>()
+                    .map_err(|_| format!("invalid {} {:?}", name, part))
+            })
+            .transpose()
+    };
+
+    // IPv4 multicast addresses carry a TTL before the address count
+    let (ttl, count) = match address {
+        IpAddr::V4(ip) if ip.is_multicast() => {
+            let ttl = next_number("TTL")?
+                .map(|ttl| u8::try_from(ttl).map_err(|_| format!("TTL {} out of range", ttl)))
+                .transpose()?;
+            (ttl, next_number("address count")?)
+        }
+        _ => (None, next_number("address count")?),
+    };
+
+    Ok(Connection {
+        address,
+        ttl,
+        count,
+    })
+}
+
+fn parse_media(value: &str) -> std::result::Result<MediaDescription, String> {
+    let fields: Vec<_> = value.split(' ').collect();
+
+    let (media, port, protocol, formats) = match fields.as_slice() {
+        [media, port, protocol, formats @ ..] if !formats.is_empty() => {
+            (*media, *port, *protocol, formats)
+        }
+        _ => return Err(format!("malformed media description {:?}", value)),
+    };
+
+    let (port, port_count) = match port.split_once('/') {
+        Some((port, count)) => (port, Some(count)),
+        None => (port, None),
+    };
+    let port = port
+        .parse::<u16>()
+        .map_err(|_| format!("invalid port {:?}", port))?;
+    let port_count = port_count
+        .map(|count| {
+            count
+                .parse::<u16>()
+                .map_err(|_| format!("invalid port count {:?}", count))
+        })
+        .transpose()?;
+
+    if !protocol.starts_with("RTP/") {
+        return Err(format!("unsupported transport protocol {:?}", protocol));
+    }
+
+    let payload_types = formats
+        .iter()
+        .map(|format| parse_payload_type(format))
+        .collect::<std::result::Result<_, _>>()?;
+
+    Ok(MediaDescription {
+        media: media.to_string(),
+        port,
+        port_count,
+        protocol: protocol.to_string(),
+        payload_types,
+        information: None,
+        connection: None,
+        attributes: Vec::new(),
+    })
+}
+
+fn parse_payload_type(value: &str) -> std::result::Result<u8, String> {
+    match value.parse::<u8>() {
+        Ok(payload_type) if payload_type < 128 => Ok(payload_type),
+        _ => Err(format!("invalid payload type {:?}", value)),
+    }
+}
+
+fn parse_attribute(value: &str) -> std::result::Result<Attribute, String> {
+    let (name, value) = match value.split_once(':') {
+        Some((name, value)) => (name, Some(value)),
+        None => (value, None),
+    };
+
+    let attribute = match (name, value) {
+        ("rtpmap", Some(value)) => Attribute::RtpMap(parse_rtpmap(value)?),
+        ("fmtp", Some(value)) => Attribute::Fmtp(parse_fmtp(value)?),
+        ("source-filter", Some(value)) => Attribute::SourceFilter(parse_source_filter(value)?),
+        ("group", Some(value)) => {
+            let mut fields = value.split(' ');
+            Attribute::Group {
+                semantics: fields.next().unwrap_or_default().to_string(),
+                mids: fields.map(ToString::to_string).collect(),
+            }
+        }
+        ("mid", Some(value)) => Attribute::Mid(value.to_string()),
+        ("ts-refclk", Some(value)) => Attribute::TsRefClk(value.to_string()),
+        ("mediaclk", Some(value)) => Attribute::MediaClk(value.to_string()),
+        ("rtpmap", None)
+        | ("fmtp", None)
+        | ("source-filter", None)
+        | ("group", None)
+        | ("mid", None)
+        | ("ts-refclk", None)
+        | ("mediaclk", None) => return Err(format!("a={} requires a value", name)),
+        (name, value) => Attribute::Other {
+            name: name.to_string(),
+            value: value.map(ToString::to_string),
+        },
+    };
+
+    Ok(attribute)
+}
+
+fn parse_rtpmap(value: &str) -> std::result::Result<RtpMap, String> {
+    let (payload_type, encoding) = value
+        .split_once(' ')
+        .ok_or_else(|| format!("malformed rtpmap {:?}", value))?;
+
+    let payload_type = parse_payload_type(payload_type)?;
+
+    let mut parts = encoding.split('/');
+    let encoding = parts.next().unwrap_or_default().to_string();
+
+    let clock_rate = parts
+        .next()
+        .ok_or_else(|| format!("rtpmap {:?} is missing a clock rate", value))?;
+    let clock_rate = clock_rate
+        .parse::<u32>()
+        .map_err(|_| format!("invalid clock rate {:?}", clock_rate))?;
+
+    let channels = parts
+        .next()
+        .map(|channels| {
+            channels
+                .parse::<u8>()
+                .map_err(|_| format!("invalid channel count {:?}", channels))
+        })
+        .transpose()?;
+
+    Ok(RtpMap {
+        payload_type,
+        encoding,
+        clock_rate,
+        channels,
+    })
+}
+
+fn parse_fmtp(value: &str) -> std::result::Result<Fmtp, String> {
+    let (payload_type, parameters) = match value.split_once(' ') {
+        Some((payload_type, parameters)) => (payload_type, parameters),
+        None => (value, ""),
+    };
+
+    let payload_type = parse_payload_type(payload_type)?;
+
+    let parameters = parameters
+        .split(';')
+        .map(str::trim)
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| match parameter.split_once('=') {
+            Some((key, value)) => (key.trim().to_string(), Some(value.trim().to_string())),
+            None => (parameter.to_string(), None),
+        })
+        .collect();
+
+    Ok(Fmtp {
+        payload_type,
+        parameters,
+    })
+}
+
+fn parse_source_filter(value: &str) -> std::result::Result<SourceFilter, String> {
+    let fields: Vec<_> = value.split_whitespace().collect();
+
+    let (mode, destination, sources) = match fields.as_slice() {
+        [mode, "IN", _, destination, sources @ ..] if !sources.is_empty() => {
+            (*mode, *destination, sources)
+        }
+        _ => return Err(format!("malformed source filter {:?}", value)),
+    };
+
+    let mode = match mode {
+        "incl" => FilterMode::Include,
+        "excl" => FilterMode::Exclude,
+        _ => return Err(format!("invalid source filter mode {:?}", mode)),
+    };
+
+    let destination = match destination {
+        "*" => None,
+        destination => Some(
+            IpAddr::from_str(destination)
+                .map_err(|_| format!("invalid destination address {:?}", destination))?,
+        ),
+    };
+
+    let sources = sources
+        .iter()
+        .map(|source| {
+            IpAddr::from_str(source).map_err(|_| format!("invalid source address {:?}", source))
+        })
+        .collect::<std::result::Result<_, _>>()?;
+
+    Ok(SourceFilter {
+        mode,
+        destination,
+        sources,
+    })
+}
@@ -1,30 +1,208 @@
 use std::{
+    error::Error as StdError,
     fmt,
+    ops::{Add, Sub},
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::clock;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Unix times at which the offset of TAI from UTC changed, with the new
+/// offset in seconds.
+///
+/// Must be extended when the IERS announces a new leap second.
+const LEAP_SECONDS: &[(u64, u64)] = &[
+    (63_072_000, 10),    // 1972-01-01
+    (78_796_800, 11),    // 1972-07-01
+    (94_694_400, 12),    // 1973-01-01
+    (126_230_400, 13),   // 1974-01-01
+    (157_766_400, 14),   // 1975-01-01
+    (189_302_400, 15),   // 1976-01-01
+    (220_924_800, 16),   // 1977-01-01
+    (252_460_800, 17),   // 1978-01-01
+    (283_996_800, 18),   // 1979-01-01
+    (315_532_800, 19),   // 1980-01-01
+    (362_793_600, 20),   // 1981-07-01
+    (394_329_600, 21),   // 1982-07-01
+    (425_865_600, 22),   // 1983-07-01
+    (489_024_000, 23),   // 1985-07-01
+    (567_993_600, 24),   // 1988-01-01
+    (631_152_000, 25),   // 1990-01-01
+    (662_688_000, 26),   // 1991-01-01
+    (709_948_800, 27),   // 1992-07-01
+    (741_484_800, 28),   // 1993-07-01
+    (773_020_800, 29),   // 1994-07-01
+    (820_454_400, 30),   // 1996-01-01
+    (867_715_200, 31),   // 1997-07-01
+    (915_148_800, 32),   // 1999-01-01
+    (1_136_073_600, 33), // 2006-01-01
+    (1_230_768_000, 34), // 2009-01-01
+    (1_341_100_800, 35), // 2012-07-01
+    (1_435_708_800, 36), // 2015-07-01
+    (1_483_228_800, 37), // 2017-01-01
+];
+
+// Offset of TAI from UTC at a Unix time, taking the first offset before 1972
+fn utc_offset(unix_secs: u64) -> u64 {
+    LEAP_SECONDS
+        .iter()
+        .rev()
+        .find(|(since, _)| *since <= unix_secs)
+        .unwrap_or(&LEAP_SECONDS[0])
+        .1
+}
+
+// Offset of TAI from UTC at a TAI time
+fn tai_offset(tai_secs: u64) -> u64 {
+    LEAP_SECONDS
+        .iter()
+        .rev()
+        .find(|(since, offset)| since + offset <= tai_secs)
+        .unwrap_or(&LEAP_SECONDS[0])
+        .1
+}
+
+// Nanoseconds since the TAI epoch of the last version handed out
+static LAST_VERSION: AtomicU64 = AtomicU64::new(0);
+
+/// A time since the TAI epoch of 1970-01-01 00:00:00 TAI, as used for
+/// resource versions and PTP timestamps.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TaiTime {
     secs: u64,
     nanos: u32,
 }
 
 impl TaiTime {
+    /// The TAI epoch.
+    pub const EPOCH: TaiTime = TaiTime { secs: 0, nanos: 0 };
+
+    #[must_use]
+    pub fn new(secs: u64, nanos: u32) -> TaiTime {
+        TaiTime::from(Duration::new(secs, nanos))
+    }
+
+    /// The current time from the clock source, which is the system clock
+    /// unless another has been set with [`crate::clock::set_clock_source`].
     #[must_use]
     pub fn now() -> TaiTime {
-        let tai_offset = Duration::from_secs(37);
+        clock::now()
+    }
+
+    /// The current time, or just after the last version generated if that
+    /// is not earlier, so that every version is distinct and increasing.
+    #[must_use]
+    pub fn next_version() -> TaiTime {
+        let now = TaiTime::now().as_nanos();
+
+        let previous = LAST_VERSION
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                Some(now.max(last + 1))
+            })
+            .unwrap_or_else(|last| last);
+
+        TaiTime::from_nanos(now.max(previous + 1))
+    }
+
+    #[must_use]
+    pub fn secs(&self) -> u64 {
+        self.secs
+    }
+
+    #[must_use]
+    pub fn nanos(&self) -> u32 {
+        self.nanos
+    }
 
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("System time before Unix epoch")
-            + tai_offset;
+    /// Time elapsed since an earlier time, or `None` if it is later.
+    #[must_use]
+    pub fn checked_duration_since(&self, earlier: TaiTime) -> Option<Duration> {
+        Duration::from(*self).checked_sub(Duration::from(earlier))
+    }
+
+    #[must_use]
+    pub fn checked_add(&self, duration: Duration) -> Option<TaiTime> {
+        Duration::from(*self)
+            .checked_add(duration)
+            .map(TaiTime::from)
+    }
+
+    #[must_use]
+    pub fn checked_sub(&self, duration: Duration) -> Option<TaiTime> {
+        Duration::from(*self)
+            .checked_sub(duration)
+            .map(TaiTime::from)
+    }
+
+    // Nanoseconds since the epoch, which fit in 64 bits until 2554
+    fn as_nanos(self) -> u64 {
+        self.secs * NANOS_PER_SEC + u64::from(self.nanos)
+    }
+
+    fn from_nanos(nanos: u64) -> TaiTime {
+        TaiTime::from(Duration::from_nanos(nanos))
+    }
+}
 
+impl From<Duration> for TaiTime {
+    /// The time a duration after the TAI epoch.
+    fn from(duration: Duration) -> Self {
         TaiTime {
-            secs: now.as_secs(),
-            nanos: now.subsec_nanos(),
+            secs: duration.as_secs(),
+            nanos: duration.subsec_nanos(),
         }
     }
 }
 
+impl From<TaiTime> for Duration {
+    /// The duration since the TAI epoch.
+    fn from(time: TaiTime) -> Self {
+        Duration::new(time.secs, time.nanos)
+    }
+}
+
+impl From<SystemTime> for TaiTime {
+    /// Convert a UTC system time, clamping times before the Unix epoch to it.
+    fn from(time: SystemTime) -> Self {
+        let unix = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let offset = Duration::from_secs(utc_offset(unix.as_secs()));
+
+        TaiTime::from(unix + offset)
+    }
+}
+
+impl From<TaiTime> for SystemTime {
+    fn from(time: TaiTime) -> Self {
+        let offset = Duration::from_secs(tai_offset(time.secs));
+
+        UNIX_EPOCH + Duration::from(time).saturating_sub(offset)
+    }
+}
+
+impl Add<Duration> for TaiTime {
+    type Output = TaiTime;
+
+    fn add(self, duration: Duration) -> TaiTime {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to TAI time")
+    }
+}
+
+impl Sub<Duration> for TaiTime {
+    type Output = TaiTime;
+
+    fn sub(self, duration: Duration) -> TaiTime {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from TAI time")
+    }
+}
+
 impl fmt::Debug for TaiTime {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}:{}", self.secs, self.nanos)
@@ -36,3 +214,150 @@ impl fmt::Display for TaiTime {
         write!(f, "{}:{}", self.secs, self.nanos)
     }
 }
+
+/// Error parsing a "secs:nanos" TAI timestamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTaiTimeError(String);
+
+impl fmt::Display for ParseTaiTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\" is not a TAI timestamp", self.0)
+    }
+}
+
+impl StdError for ParseTaiTimeError {}
+
+impl FromStr for TaiTime {
+    type Err = ParseTaiTimeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Integer parsing alone would also accept a leading '+'
+        let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+        match s
+            .split_once(':')
+            .filter(|(secs, nanos)| digits(secs) && digits(nanos))
+            .map(|(secs, nanos)| (secs.parse(), nanos.parse::<u32>()))
+        {
+            Some((Ok(secs), Ok(nanos))) if nanos < 1_000_000_000 => Ok(TaiTime { secs, nanos }),
+            _ => Err(ParseTaiTimeError(s.to_owned())),
+        }
+    }
+}
+
+impl Serialize for TaiTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TaiTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2017-01-01 00:00:00 UTC, when the offset went from 36 to 37 seconds
+    const LEAP: u64 = 1_483_228_800;
+
+    fn unix(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn from_system_time_across_leap_second() {
+        assert_eq!(
+            TaiTime::from(unix(LEAP - 1)),
+            TaiTime::new(LEAP - 1 + 36, 0)
+        );
+        assert_eq!(TaiTime::from(unix(LEAP)), TaiTime::new(LEAP + 37, 0));
+        assert_eq!(
+            TaiTime::from(unix(LEAP + 1)),
+            TaiTime::new(LEAP + 1 + 37, 0)
+        );
+    }
+
+    #[test]
+    fn to_system_time_across_leap_second() {
+        assert_eq!(
+            SystemTime::from(TaiTime::new(LEAP - 1 + 36, 0)),
+            unix(LEAP - 1)
+        );
+        assert_eq!(SystemTime::from(TaiTime::new(LEAP + 37, 0)), unix(LEAP));
+        assert_eq!(
+            SystemTime::from(TaiTime::new(LEAP + 1 + 37, 0)),
+            unix(LEAP + 1)
+        );
+
+        // The inserted second itself has no distinct UTC time
+        assert_eq!(SystemTime::from(TaiTime::new(LEAP + 36, 0)), unix(LEAP));
+    }
+
+    #[test]
+    fn round_trip_across_leap_second() {
+        for secs in LEAP - 2..LEAP + 2 {
+            let time = unix(secs) + Duration::from_nanos(500);
+
+            assert_eq!(SystemTime::from(TaiTime::from(time)), time);
+        }
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("0:0".parse(), Ok(TaiTime::EPOCH));
+        assert_eq!(
+            "1483228837:999999999".parse(),
+            Ok(TaiTime::new(1_483_228_837, 999_999_999))
+        );
+        assert_eq!(
+            TaiTime::new(1_483_228_837, 5).to_string().parse(),
+            Ok(TaiTime::new(1_483_228_837, 5))
+        );
+    }
+
+    #[test]
+    fn from_str_rejects_malformed() {
+        for s in &[
+            "",
+            ":",
+            "1",
+            "1:",
+            ":0",
+            "1:0:0",
+            "+1:0",
+            "1:+0",
+            "-1:0",
+            " 1:0",
+            "1:0 ",
+            "a:b",
+            "1.5:0",
+            "1:1000000000",
+            "18446744073709551616:0",
+        ] {
+            assert_eq!(
+                s.parse::<TaiTime>(),
+                Err(ParseTaiTimeError((*s).to_owned())),
+                "{:?}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn next_version_strictly_increases() {
+        let mut previous = TaiTime::next_version();
+
+        for _ in 0..10_000 {
+            let version = TaiTime::next_version();
+
+            assert!(version > previous, "{} after {}", version, previous);
+            previous = version;
+        }
+    }
+}
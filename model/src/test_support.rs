@@ -0,0 +1,31 @@
+//! Resources shared by the tests of the model and of the crates built on it,
+//! which enable the `test-support` feature to use them.
+
+use crate::{
+    connection::RtpSenderTransportParams,
+    resource::{Device, DeviceType, Node},
+};
+
+/// A node served from localhost.
+#[must_use]
+pub fn node() -> Node {
+    Node::builder("node", "http://127.0.0.1/").build()
+}
+
+/// A generic device of a node.
+#[must_use]
+pub fn device(node: &Node) -> Device {
+    Device::builder("device", node, DeviceType::Generic).build()
+}
+
+/// An enabled RTP leg sending from 192.168.1.10 to 239.1.1.1, port 5004.
+#[must_use]
+pub fn sender_leg() -> RtpSenderTransportParams {
+    RtpSenderTransportParams {
+        source_ip: Some("192.168.1.10".parse().unwrap()),
+        destination_ip: Some("239.1.1.1".parse().unwrap()),
+        source_port: Some(5004),
+        destination_port: Some(5004),
+        rtp_enabled: true,
+    }
+}
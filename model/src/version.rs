@@ -3,34 +3,30 @@ use std::{
     str::FromStr,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct APIVersion {
     pub major: u8,
     pub minor: u8,
 }
 
 impl FromStr for APIVersion {
-    type Err = std::num::ParseIntError;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut versions = s.trim_matches('v').split('.');
-
-        // TODO: Proper error handling
-        assert!(versions.clone().count() == 2);
-
-        let major = match versions.next() {
-            Some(v) => v.parse::<u8>()?,
-            None => panic!("Missing major version"),
-        };
-
-        let minor = match versions.next() {
-            Some(v) => v.parse::<u8>()?,
-            None => panic!("Missing major version"),
-        };
-
-        assert!(versions.next() == None);
-
-        Ok(Self { major, minor })
+        let invalid = || Error::InvalidVersion(s.to_owned());
+
+        let (major, minor) = s
+            .strip_prefix('v')
+            .unwrap_or(s)
+            .split_once('.')
+            .ok_or_else(invalid)?;
+
+        Ok(Self {
+            major: major.parse().map_err(|_| invalid())?,
+            minor: minor.parse().map_err(|_| invalid())?,
+        })
     }
 }
 
@@ -44,4 +40,17 @@ pub mod is_04 {
     use super::APIVersion;
 
     pub const V1_0: APIVersion = APIVersion { major: 1, minor: 0 };
+    pub const V1_1: APIVersion = APIVersion { major: 1, minor: 1 };
+    pub const V1_2: APIVersion = APIVersion { major: 1, minor: 2 };
+    pub const V1_3: APIVersion = APIVersion { major: 1, minor: 3 };
+
+    /// Versions resources can be rendered as.
+    pub const VERSIONS: &[APIVersion] = &[V1_0, V1_1, V1_2, V1_3];
+}
+
+pub mod is_05 {
+    use super::APIVersion;
+
+    pub const V1_0: APIVersion = APIVersion { major: 1, minor: 0 };
+    pub const V1_1: APIVersion = APIVersion { major: 1, minor: 1 };
 }
@@ -1,6 +1,6 @@
 use gst::{prelude::*, Pipeline};
 use gstreamer as gst;
-use nmos_model::resource;
+use nmos_model::{connection::RtpSenderTransportParams, resource};
 use nmos_node::Node;
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
@@ -37,13 +37,14 @@ fn create_pipeline() -> Result<Pipeline, Box<dyn std::error::Error>> {
     enc.set_property("deadline", 1i64);
 
     // UDP sink properties
-    sink.set_property("host", "0.0.0.0");
+    sink.set_property("host", "127.0.0.1");
+    sink.set_property("port", 5004i32);
     sink.set_property("sync", true);
 
     Ok(pipeline)
 }
 
-fn create_node() -> Node {
+fn create_node() -> Result<Node, nmos_model::Error> {
     // Create NMOS node
     let node = resource::Node::builder("GStreamer test node", "http://127.0.0.1:3000/test").build();
     let device = resource::Device::builder(
@@ -58,7 +59,11 @@ fn create_node() -> Node {
         resource::Source::builder("GStreamer test source", &device, resource::Format::Video)
             .description("SMPTE video test stream")
             .build();
-    let flow = resource::Flow::builder("GStreamer VP8 test flow", &source).build();
+    let flow = resource::Flow::builder("GStreamer VP8 test flow", &source)
+        .media_type("video/VP8")
+        .grain_rate(resource::Rational::new(30, 1))
+        .video(resource::VideoEssence::new(320, 240))
+        .build()?;
 
     // Create sender
     let sender = resource::Sender::builder(
@@ -67,7 +72,14 @@ fn create_node() -> Node {
         &flow,
         resource::Transport::RtpUnicast,
     )
-    .manifest("file:///path/to/sdp/file")
+    .transport_params([RtpSenderTransportParams {
+        source_ip: Some([127, 0, 0, 1].into()),
+        destination_ip: Some([127, 0, 0, 1].into()),
+        source_port: Some(5004),
+        destination_port: Some(5004),
+        rtp_enabled: true,
+    }])
+    .master_enable(true)
     .build();
 
     let mut bundle = resource::ResourceBundle::new();
@@ -77,7 +89,7 @@ fn create_node() -> Node {
     bundle.insert_flow(flow);
     bundle.insert_sender(sender);
 
-    Node::builder_from_resources(bundle).build()
+    Ok(Node::builder_from_resources(bundle)?.build())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -94,7 +106,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let pipeline = create_pipeline()?;
 
     // Create NMOS node
-    let node = create_node();
+    let node = create_node()?;
 
     // Start pipeline on separate thread
     std::thread::spawn(move || {
@@ -31,7 +31,13 @@ async fn main() {
     resources.insert_receiver(receiver);
 
     // Create node
-    let node = Node::builder_from_resources(resources).build();
+    let node = match Node::builder_from_resources(resources) {
+        Ok(builder) => builder.build(),
+        Err(e) => {
+            println!("Invalid resources: {}", e);
+            return;
+        }
+    };
 
     if let Err(e) = node.start().await {
         println!("Node error: {:?}", e);
@@ -0,0 +1,1002 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::Path;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use nmos_model::connection::{
+    Activation, ActivationMode, ReceiverConnection, RtpReceiverTransportParams,
+    RtpSenderTransportParams, SenderConnection,
+};
+use nmos_model::resource::{Caps, ControlType, DeviceControl, Flow, Receiver, Sender, Source};
+use nmos_model::sdp::SessionDescription;
+use nmos_model::tai::TaiTime;
+use nmos_model::version::is_05::{V1_0, V1_1};
+use nmos_model::version::APIVersion;
+use nmos_model::Model;
+use reqwest::Url;
+use serde_json::{json, Map, Value};
+use tracing::error;
+use uuid::Uuid;
+
+use super::manifest::sender_transport_file;
+use super::{parse_api_version, ServiceError};
+use crate::EventHandler;
+
+const SUPPORTED_API_VERSIONS: &[APIVersion] = &[V1_0, V1_1];
+
+// RTP port used when a port is left to the node
+const DEFAULT_RTP_PORT: u16 = 5004;
+
+fn control_type(api: APIVersion) -> Option<ControlType> {
+    match api {
+        V1_0 => Some(ControlType::ConnectionV1_0),
+        V1_1 => Some(ControlType::ConnectionV1_1),
+        _ => None,
+    }
+}
+
+/// List the Connection API served by the node among the controls of every device.
+pub async fn assign_control_hrefs(model: &Model) {
+    let base = {
+        let nodes = model.nodes().await;
+        let node = match nodes.values().next() {
+            Some(node) => node,
+            None => return,
+        };
+
+        match Url::parse(&node.href) {
+            Ok(url) => url,
+            Err(err) => {
+                error!("Cannot build control URLs from {}: {}", node.href, err);
+                return;
+            }
+        }
+    };
+
+    let mut controls = Vec::new();
+    for api in SUPPORTED_API_VERSIONS {
+        let type_ = match control_type(*api) {
+            Some(type_) => type_,
+            None => continue,
+        };
+
+        match base.join(&format!("x-nmos/connection/{}/", api)) {
+            Ok(href) => controls.push(DeviceControl {
+                href: href.to_string(),
+                type_,
+            }),
+            Err(err) => error!(
+                "Cannot build control URL for Connection API {}: {}",
+                api, err
+            ),
+        }
+    }
+
+    let ids: Vec<Uuid> = model
+        .devices()
+        .await
+        .values()
+        .filter(|device| {
+            controls
+                .iter()
+                .any(|control| !device.controls.contains(control))
+        })
+        .map(|device| device.core.id)
+        .collect();
+
+    for id in ids {
+        model
+            .update_device(&id, |device| {
+                for control in &controls {
+                    if !device.controls.contains(control) {
+                        device.controls.push(control.clone());
+                    }
+                }
+            })
+            .await;
+    }
+}
+
+// Node address, which senders transmit from and receivers listen on
+async fn node_ip(model: &Model) -> Option<IpAddr> {
+    let nodes = model.nodes().await;
+    let url = Url::parse(&nodes.values().next()?.href).ok()?;
+    url.host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+// Multicast group of a sender leg, unique to the sender within 239.0.0.0/8
+fn default_multicast_ip(id: &Uuid, leg: usize) -> IpAddr {
+    let bytes = id.as_bytes();
+    IpAddr::V4(Ipv4Addr::new(239, bytes[0], bytes[1], leg as u8 + 1))
+}
+
+/// Resolve the "auto" parameters of a sender to the values it uses once active.
+fn resolve_sender_params(id: &Uuid, connection: &mut SenderConnection, node_ip: Option<IpAddr>) {
+    for (index, leg) in connection.transport_params.iter_mut().enumerate() {
+        leg.source_ip = leg.source_ip.or(node_ip);
+        leg.destination_ip = leg
+            .destination_ip
+            .or_else(|| Some(default_multicast_ip(id, index)));
+        leg.source_port = leg.source_port.or(Some(DEFAULT_RTP_PORT));
+        leg.destination_port = leg.destination_port.or(Some(DEFAULT_RTP_PORT));
+    }
+}
+
+/// Resolve the "auto" parameters of a receiver to the values it uses once active.
+fn resolve_receiver_params(connection: &mut ReceiverConnection, node_ip: Option<IpAddr>) {
+    for leg in &mut connection.transport_params {
+        leg.interface_ip = leg.interface_ip.or(node_ip);
+        leg.destination_port = leg.destination_port.or(Some(DEFAULT_RTP_PORT));
+    }
+}
+
+fn bad_request(debug: String) -> ServiceError {
+    ServiceError::new(StatusCode::BAD_REQUEST, Some(debug))
+}
+
+fn parse_body(
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<Map<String, Value>, ServiceError> {
+    match body {
+        Ok(Json(Value::Object(patch))) => Ok(patch),
+        Ok(_) => Err(bad_request(String::from("Request body must be an object"))),
+        Err(err) => Err(bad_request(err.to_string())),
+    }
+}
+
+pub async fn get_senders(
+    Path(api): Path<String>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    let senders = model.senders().await;
+
+    let senders: Vec<_> = senders.keys().map(|id| format!("{}/", id)).collect();
+
+    Ok(Json(json!(senders)))
+}
+
+pub async fn get_sender(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    if !model.senders().await.contains_key(&id) {
+        return Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Sender {} does not exist", id)),
+        ));
+    }
+
+    let mut endpoints = vec!["constraints/", "staged/", "active/", "transportfile/"];
+    if api == V1_1 {
+        endpoints.push("transporttype/");
+    }
+
+    Ok(Json(json!(endpoints)))
+}
+
+pub async fn get_sender_constraints(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    let senders = model.senders().await;
+
+    let constraints = match senders.get(&id) {
+        Some(s) => s
+            .staged
+            .transport_params
+            .iter()
+            .map(|_| {
+                json!({
+                    "source_ip": {},
+                    "destination_ip": {},
+                    "source_port": {},
+                    "destination_port": {},
+                    "rtp_enabled": {},
+                })
+            })
+            .collect::<Vec<_>>(),
+        None => {
+            return Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                Some(format!("Sender {} does not exist", id)),
+            ))
+        }
+    };
+
+    Ok(Json(json!(constraints)))
+}
+
+pub async fn get_sender_staged(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    let senders = model.senders().await;
+
+    match senders.get(&id) {
+        Some(s) => Ok(Json(sender_connection_json(&s.staged, false))),
+        None => Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Sender {} does not exist", id)),
+        )),
+    }
+}
+
+pub async fn get_sender_active(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    let senders = model.senders().await;
+
+    match senders.get(&id) {
+        Some(s) => Ok(Json(sender_connection_json(&s.active, true))),
+        None => Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Sender {} does not exist", id)),
+        )),
+    }
+}
+
+pub async fn patch_sender_staged(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<(StatusCode, Json<Value>), ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+    let patch = parse_body(body)?;
+    let node_ip = node_ip(&model).await;
+
+    // The patch is applied under the same lock as the sender it patches
+    let patched: Option<Result<_, ServiceError>> = model
+        .try_update_sender(&id, |s| {
+            check_unlocked(&s.staged.activation, &patch)?;
+
+            let mut staged = s.staged.clone();
+            apply_sender_patch(&mut staged, &patch).map_err(bad_request)?;
+            let schedule = match patch.get("activation") {
+                Some(_) => schedule_activation(&mut staged.activation)?,
+                None => Schedule::None,
+            };
+
+            let response = sender_connection_json(&staged, false);
+            s.staged = staged;
+            if let Schedule::Immediate = schedule {
+                s.activate();
+                resolve_sender_params(&id, &mut s.active, node_ip);
+            }
+
+            Ok((schedule, s.staged.activation.clone(), response))
+        })
+        .await;
+
+    let (schedule, activation, response) = match patched {
+        Some(patched) => patched?,
+        None => {
+            return Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                Some(format!("Sender {} does not exist", id)),
+            ))
+        }
+    };
+
+    let status = match schedule {
+        Schedule::None | Schedule::Immediate => StatusCode::OK,
+        Schedule::After(delay) => {
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+
+                model
+                    .update_sender(&id, |s| {
+                        // Activation may have been cancelled meanwhile
+                        if s.staged.activation == activation {
+                            s.activate();
+                            resolve_sender_params(&id, &mut s.active, node_ip);
+                        }
+                    })
+                    .await;
+            });
+            StatusCode::ACCEPTED
+        }
+    };
+
+    Ok((status, Json(response)))
+}
+
+pub async fn get_sender_transportfile(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<impl IntoResponse, ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    let sdp = sender_transport_file(&model, id).await?;
+
+    Ok(([(header::CONTENT_TYPE, "application/sdp")], sdp))
+}
+
+pub async fn get_sender_transporttype(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    if api != V1_1 {
+        return Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Transport type is not available in {}", api)),
+        ));
+    }
+
+    match model.senders().await.get(&id) {
+        Some(_) => Ok(Json(json!("urn:x-nmos:transport:rtp"))),
+        None => Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Sender {} does not exist", id)),
+        )),
+    }
+}
+
+pub async fn get_receivers(
+    Path(api): Path<String>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    let receivers = model.receivers().await;
+
+    let receivers: Vec<_> = receivers.keys().map(|id| format!("{}/", id)).collect();
+
+    Ok(Json(json!(receivers)))
+}
+
+pub async fn get_receiver(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    if !model.receivers().await.contains_key(&id) {
+        return Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Receiver {} does not exist", id)),
+        ));
+    }
+
+    let mut endpoints = vec!["constraints/", "staged/", "active/"];
+    if api == V1_1 {
+        endpoints.push("transporttype/");
+    }
+
+    Ok(Json(json!(endpoints)))
+}
+
+pub async fn get_receiver_constraints(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    let receivers = model.receivers().await;
+
+    let constraints = match receivers.get(&id) {
+        Some(r) => r
+            .staged
+            .transport_params
+            .iter()
+            .map(|_| {
+                json!({
+                    "source_ip": {},
+                    "multicast_ip": {},
+                    "interface_ip": {},
+                    "destination_port": {},
+                    "rtp_enabled": {},
+                })
+            })
+            .collect::<Vec<_>>(),
+        None => {
+            return Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                Some(format!("Receiver {} does not exist", id)),
+            ))
+        }
+    };
+
+    Ok(Json(json!(constraints)))
+}
+
+pub async fn get_receiver_staged(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    let receivers = model.receivers().await;
+
+    match receivers.get(&id) {
+        Some(r) => Ok(Json(receiver_connection_json(&r.staged, false))),
+        None => Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Receiver {} does not exist", id)),
+        )),
+    }
+}
+
+pub async fn get_receiver_active(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    let receivers = model.receivers().await;
+
+    match receivers.get(&id) {
+        Some(r) => Ok(Json(receiver_connection_json(&r.active, true))),
+        None => Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Receiver {} does not exist", id)),
+        )),
+    }
+}
+
+pub async fn patch_receiver_staged(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(event_handler): Extension<Option<Arc<dyn EventHandler>>>,
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<(StatusCode, Json<Value>), ServiceError> {
+    parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+    let patch = parse_body(body)?;
+
+    let (status, response) = stage_receiver(model, event_handler, id, &patch).await?;
+
+    Ok((status, Json(response)))
+}
+
+/// Apply a patch to the staged parameters of a receiver, activating them as
+/// the patch requests, and return the status and body of the response.
+pub async fn stage_receiver(
+    model: Arc<Model>,
+    event_handler: Option<Arc<dyn EventHandler>>,
+    id: Uuid,
+    patch: &Map<String, Value>,
+) -> Result<(StatusCode, Value), ServiceError> {
+    let node_ip = node_ip(&model).await;
+
+    // The patch is applied under the same lock as the receiver it patches,
+    // with the senders it may name held for checking
+    let patched: Option<Result<_, ServiceError>> = {
+        let sources = model.sources().await;
+        let flows = model.flows().await;
+        let senders = model.senders().await;
+
+        model
+            .try_update_receiver(&id, |r| {
+                check_unlocked(&r.staged.activation, patch)?;
+
+                let mut staged = r.staged.clone();
+                apply_receiver_patch(&mut staged, &r.caps, patch).map_err(bad_request)?;
+                check_local_sender(&sources, &flows, &senders, r, staged.sender_id)?;
+                let schedule = match patch.get("activation") {
+                    Some(_) => schedule_activation(&mut staged.activation)?,
+                    None => Schedule::None,
+                };
+
+                let response = receiver_connection_json(&staged, false);
+                r.staged = staged;
+                if let Schedule::Immediate = schedule {
+                    r.activate();
+                    resolve_receiver_params(&mut r.active, node_ip);
+                }
+
+                Ok((schedule, r.staged.clone(), response))
+            })
+            .await
+    };
+
+    let (schedule, staged, response) = match patched {
+        Some(patched) => patched?,
+        None => {
+            return Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                Some(format!("Receiver {} does not exist", id)),
+            ))
+        }
+    };
+
+    let status = match schedule {
+        Schedule::None => StatusCode::OK,
+        Schedule::Immediate => {
+            if let Some(event_handler) = event_handler {
+                event_handler
+                    .receiver_subscription(id, staged.sender_id)
+                    .await;
+            }
+            StatusCode::OK
+        }
+        Schedule::After(delay) => {
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+
+                let mut activated = None;
+                model
+                    .update_receiver(&id, |r| {
+                        // Activation may have been cancelled meanwhile
+                        if r.staged.activation == staged.activation {
+                            r.activate();
+                            resolve_receiver_params(&mut r.active, node_ip);
+                            activated = Some(r.active.sender_id);
+                        }
+                    })
+                    .await;
+
+                if let (Some(event_handler), Some(sender_id)) = (event_handler, activated) {
+                    event_handler.receiver_subscription(id, sender_id).await;
+                }
+            });
+            StatusCode::ACCEPTED
+        }
+    };
+
+    Ok((status, response))
+}
+
+pub async fn get_receiver_transporttype(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, SUPPORTED_API_VERSIONS)?;
+
+    if api != V1_1 {
+        return Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Transport type is not available in {}", api)),
+        ));
+    }
+
+    match model.receivers().await.get(&id) {
+        Some(_) => Ok(Json(json!("urn:x-nmos:transport:rtp"))),
+        None => Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Receiver {} does not exist", id)),
+        )),
+    }
+}
+
+enum Schedule {
+    None,
+    Immediate,
+    After(Duration),
+}
+
+/// Fail while a scheduled activation is pending, unless the patch cancels it.
+fn check_unlocked(staged: &Activation, patch: &Map<String, Value>) -> Result<(), ServiceError> {
+    let pending = matches!(
+        staged.mode,
+        Some(ActivationMode::ActivateScheduledAbsolute | ActivationMode::ActivateScheduledRelative)
+    );
+    let cancels = patch
+        .get("activation")
+        .and_then(|activation| activation.get("mode"))
+        .map_or(false, Value::is_null);
+
+    if pending && !cancels {
+        Err(ServiceError::new(
+            StatusCode::LOCKED,
+            Some(format!(
+                "Activation at {} is pending",
+                staged
+                    .activation_time
+                    .as_deref()
+                    .unwrap_or("an unknown time")
+            )),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Validate a staged activation and fill in its activation time.
+fn schedule_activation(activation: &mut Activation) -> Result<Schedule, ServiceError> {
+    let now = Duration::from(TaiTime::now());
+
+    let requested_time = match &activation.requested_time {
+        Some(time) => Some(
+            parse_tai(time)
+                .ok_or_else(|| bad_request(format!("Requested time {} is badly formed", time)))?,
+        ),
+        None => None,
+    };
+
+    match (activation.mode, requested_time) {
+        (None, _) => {
+            *activation = Activation::default();
+            Ok(Schedule::None)
+        }
+        (Some(ActivationMode::ActivateImmediate), _) => {
+            activation.requested_time = None;
+            activation.activation_time = Some(format_tai(now));
+            Ok(Schedule::Immediate)
+        }
+        (Some(ActivationMode::ActivateScheduledRelative), Some(offset)) => {
+            activation.activation_time = Some(format_tai(now + offset));
+            Ok(Schedule::After(offset))
+        }
+        (Some(ActivationMode::ActivateScheduledAbsolute), Some(time)) => {
+            activation.activation_time = Some(format_tai(time));
+            Ok(Schedule::After(time.saturating_sub(now)))
+        }
+        (Some(mode), None) => Err(bad_request(format!(
+            "Activation mode {} requires a requested time",
+            mode
+        ))),
+    }
+}
+
+fn parse_tai(time: &str) -> Option<Duration> {
+    time.parse::<TaiTime>().ok().map(Duration::from)
+}
+
+fn format_tai(time: Duration) -> String {
+    TaiTime::from(time).to_string()
+}
+
+fn activation_json(activation: &Activation) -> Value {
+    json!({
+        "mode": activation.mode.map(|mode| mode.to_string()),
+        "requested_time": activation.requested_time,
+        "activation_time": activation.activation_time,
+    })
+}
+
+// Unset IS-05 parameters are either "auto" or null depending on the parameter.
+// Active parameters are never "auto", so any left unresolved are null.
+fn auto_or<T: ToString>(value: &Option<T>, active: bool) -> Value {
+    match value {
+        Some(value) => Value::String(value.to_string()),
+        None if active => Value::Null,
+        None => Value::String(String::from("auto")),
+    }
+}
+
+fn auto_or_port(port: &Option<u16>, active: bool) -> Value {
+    match port {
+        Some(port) => json!(port),
+        None if active => Value::Null,
+        None => Value::String(String::from("auto")),
+    }
+}
+
+fn sender_connection_json(connection: &SenderConnection, active: bool) -> Value {
+    let transport_params: Vec<_> = connection
+        .transport_params
+        .iter()
+        .map(|leg| {
+            json!({
+                "source_ip": auto_or(&leg.source_ip, active),
+                "destination_ip": auto_or(&leg.destination_ip, active),
+                "source_port": auto_or_port(&leg.source_port, active),
+                "destination_port": auto_or_port(&leg.destination_port, active),
+                "rtp_enabled": leg.rtp_enabled,
+            })
+        })
+        .collect();
+
+    json!({
+        "receiver_id": connection.receiver_id,
+        "master_enable": connection.master_enable,
+        "activation": activation_json(&connection.activation),
+        "transport_params": transport_params,
+    })
+}
+
+fn receiver_connection_json(connection: &ReceiverConnection, active: bool) -> Value {
+    let transport_params: Vec<_> = connection
+        .transport_params
+        .iter()
+        .map(|leg| {
+            json!({
+                "source_ip": leg.source_ip,
+                "multicast_ip": leg.multicast_ip,
+                "interface_ip": auto_or(&leg.interface_ip, active),
+                "destination_port": auto_or_port(&leg.destination_port, active),
+                "rtp_enabled": leg.rtp_enabled,
+            })
+        })
+        .collect();
+
+    let transport_file = match &connection.transport_file {
+        Some(data) => json!({ "data": data, "type": "application/sdp" }),
+        None => json!({ "data": null, "type": null }),
+    };
+
+    json!({
+        "sender_id": connection.sender_id,
+        "master_enable": connection.master_enable,
+        "activation": activation_json(&connection.activation),
+        "transport_file": transport_file,
+        "transport_params": transport_params,
+    })
+}
+
+fn parse_uuid(key: &str, value: &Value) -> Result<Option<Uuid>, String> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) => Uuid::from_str(s)
+            .map(Some)
+            .map_err(|_| format!("{} is not a valid UUID", key)),
+        _ => Err(format!("{} must be a UUID or null", key)),
+    }
+}
+
+fn parse_bool(key: &str, value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("{} must be a boolean", key))
+}
+
+fn parse_auto_ip(key: &str, value: &Value) -> Result<Option<IpAddr>, String> {
+    match value.as_str() {
+        Some("auto") => Ok(None),
+        Some(s) => IpAddr::from_str(s)
+            .map(Some)
+            .map_err(|_| format!("{} is not a valid IP address", key)),
+        None => Err(format!("{} must be an IP address or \"auto\"", key)),
+    }
+}
+
+fn parse_nullable_ip(key: &str, value: &Value) -> Result<Option<IpAddr>, String> {
+    match value {
+        Value::Null => Ok(None),
+        Value::String(s) => IpAddr::from_str(s)
+            .map(Some)
+            .map_err(|_| format!("{} is not a valid IP address", key)),
+        _ => Err(format!("{} must be an IP address or null", key)),
+    }
+}
+
+fn parse_auto_port(key: &str, value: &Value) -> Result<Option<u16>, String> {
+    match value {
+        Value::String(s) if s == "auto" => Ok(None),
+        Value::Number(n) => n
+            .as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .map(Some)
+            .ok_or_else(|| format!("{} is not a valid port", key)),
+        _ => Err(format!("{} must be a port or \"auto\"", key)),
+    }
+}
+
+fn parse_activation(value: &Value) -> Result<Activation, String> {
+    let activation = value
+        .as_object()
+        .ok_or_else(|| String::from("activation must be an object"))?;
+
+    let mut parsed = Activation::default();
+
+    for (key, value) in activation {
+        match key.as_str() {
+            "mode" => {
+                parsed.mode = match value {
+                    Value::Null => None,
+                    Value::String(mode) => Some(match mode.as_str() {
+                        "activate_immediate" => ActivationMode::ActivateImmediate,
+                        "activate_scheduled_absolute" => ActivationMode::ActivateScheduledAbsolute,
+                        "activate_scheduled_relative" => ActivationMode::ActivateScheduledRelative,
+                        _ => return Err(format!("Unknown activation mode {}", mode)),
+                    }),
+                    _ => return Err(String::from("mode must be a string or null")),
+                }
+            }
+            "requested_time" => {
+                parsed.requested_time = match value {
+                    Value::Null => None,
+                    Value::String(time) => Some(time.clone()),
+                    _ => return Err(String::from("requested_time must be a string or null")),
+                }
+            }
+            _ => return Err(format!("Unknown activation parameter {}", key)),
+        }
+    }
+
+    Ok(parsed)
+}
+
+// Each array entry patches the transport parameters of the matching leg
+fn patch_legs<T, F>(legs: &mut [T], value: &Value, mut patch_leg: F) -> Result<(), String>
+where
+    F: FnMut(&mut T, &str, &Value) -> Result<(), String>,
+{
+    let patches = value
+        .as_array()
+        .ok_or_else(|| String::from("transport_params must be an array"))?;
+
+    if patches.len() != legs.len() {
+        return Err(format!(
+            "transport_params must have {} entries, found {}",
+            legs.len(),
+            patches.len()
+        ));
+    }
+
+    for (leg, patch) in legs.iter_mut().zip(patches) {
+        let patch = patch
+            .as_object()
+            .ok_or_else(|| String::from("transport_params entries must be objects"))?;
+
+        for (key, value) in patch {
+            patch_leg(leg, key, value)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_sender_patch(
+    staged: &mut SenderConnection,
+    patch: &Map<String, Value>,
+) -> Result<(), String> {
+    for (key, value) in patch {
+        match key.as_str() {
+            "receiver_id" => staged.receiver_id = parse_uuid(key, value)?,
+            "master_enable" => staged.master_enable = parse_bool(key, value)?,
+            "activation" => staged.activation = parse_activation(value)?,
+            "transport_params" => patch_legs(
+                &mut staged.transport_params,
+                value,
+                |leg: &mut RtpSenderTransportParams, key, value| {
+                    match key {
+                        "source_ip" => leg.source_ip = parse_auto_ip(key, value)?,
+                        "destination_ip" => leg.destination_ip = parse_auto_ip(key, value)?,
+                        "source_port" => leg.source_port = parse_auto_port(key, value)?,
+                        "destination_port" => leg.destination_port = parse_auto_port(key, value)?,
+                        "rtp_enabled" => leg.rtp_enabled = parse_bool(key, value)?,
+                        _ => return Err(format!("Unknown transport parameter {}", key)),
+                    }
+                    Ok(())
+                },
+            )?,
+            _ => return Err(format!("Unknown parameter {}", key)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Fail unless the receiver can take the sender, when it is one of the node's
+/// own. Other senders are only known through their transport files.
+fn check_local_sender(
+    sources: &HashMap<Uuid, Source>,
+    flows: &HashMap<Uuid, Flow>,
+    senders: &HashMap<Uuid, Sender>,
+    receiver: &Receiver,
+    sender_id: Option<Uuid>,
+) -> Result<(), ServiceError> {
+    let sender = match sender_id.and_then(|id| senders.get(&id)) {
+        Some(sender) => sender,
+        None => return Ok(()),
+    };
+
+    let accepted = flows.get(&sender.flow_id).map_or(false, |flow| {
+        receiver.accepts(sender, flow, sources.get(&flow.source_id))
+    });
+
+    if accepted {
+        Ok(())
+    } else {
+        Err(bad_request(format!(
+            "Receiver {} cannot take sender {}",
+            receiver.core.id, sender.core.id
+        )))
+    }
+}
+
+fn apply_receiver_patch(
+    staged: &mut ReceiverConnection,
+    caps: &Caps,
+    patch: &Map<String, Value>,
+) -> Result<(), String> {
+    // Explicit transport parameters take precedence over the transport file
+    if let Some(transport_file) = patch.get("transport_file") {
+        apply_transport_file(staged, caps, transport_file)?;
+    }
+
+    for (key, value) in patch {
+        match key.as_str() {
+            "sender_id" => staged.sender_id = parse_uuid(key, value)?,
+            "master_enable" => staged.master_enable = parse_bool(key, value)?,
+            "activation" => staged.activation = parse_activation(value)?,
+            "transport_file" => {}
+            "transport_params" => patch_legs(
+                &mut staged.transport_params,
+                value,
+                |leg: &mut RtpReceiverTransportParams, key, value| {
+                    match key {
+                        "source_ip" => leg.source_ip = parse_nullable_ip(key, value)?,
+                        "multicast_ip" => leg.multicast_ip = parse_nullable_ip(key, value)?,
+                        "interface_ip" => leg.interface_ip = parse_auto_ip(key, value)?,
+                        "destination_port" => leg.destination_port = parse_auto_port(key, value)?,
+                        "rtp_enabled" => leg.rtp_enabled = parse_bool(key, value)?,
+                        _ => return Err(format!("Unknown transport parameter {}", key)),
+                    }
+                    Ok(())
+                },
+            )?,
+            _ => return Err(format!("Unknown parameter {}", key)),
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_transport_file(
+    staged: &mut ReceiverConnection,
+    caps: &Caps,
+    value: &Value,
+) -> Result<(), String> {
+    let transport_file = value
+        .as_object()
+        .ok_or_else(|| String::from("transport_file must be an object"))?;
+
+    let data = match transport_file.get("data") {
+        Some(Value::String(data)) => data,
+        Some(Value::Null) | None => {
+            staged.transport_file = None;
+            return Ok(());
+        }
+        Some(_) => return Err(String::from("transport_file data must be a string or null")),
+    };
+
+    match transport_file.get("type").and_then(Value::as_str) {
+        Some("application/sdp") => {}
+        Some(type_) => return Err(format!("Unsupported transport file type {}", type_)),
+        None => return Err(String::from("transport_file type is required with data")),
+    }
+
+    let session = SessionDescription::from_str(data).map_err(|err| err.to_string())?;
+
+    // The receiver must be able to take the stream the file describes
+    let media_type = session
+        .media_type()
+        .ok_or_else(|| String::from("transport_file has no media type"))?;
+    if !caps.accepts_media_type(&media_type) {
+        return Err(format!("Receiver does not accept {}", media_type));
+    }
+    let params = session
+        .receiver_transport_params()
+        .map_err(|err| err.to_string())?;
+
+    // Legs missing from the transport file are disabled
+    for (index, leg) in staged.transport_params.iter_mut().enumerate() {
+        match params.get(index) {
+            Some(params) => {
+                *leg = RtpReceiverTransportParams {
+                    interface_ip: leg.interface_ip,
+                    ..params.clone()
+                }
+            }
+            None => leg.rtp_enabled = false,
+        }
+    }
+
+    staged.transport_file = Some(data.clone());
+
+    Ok(())
+}
@@ -3,6 +3,7 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
+use nmos_model::Error;
 use nmos_schema::is_04;
 
 #[derive(Debug)]
@@ -28,3 +29,21 @@ impl IntoResponse for ServiceError {
         (self.status, body).into_response()
     }
 }
+
+impl From<Error> for ServiceError {
+    fn from(err: Error) -> Self {
+        let status = match err {
+            Error::InvalidVersion(_) | Error::MissingParent(..) | Error::ParentCycle(..) => {
+                StatusCode::BAD_REQUEST
+            }
+            // Resources using URNs a version does not allow are left out of
+            // it, as they are from resource lists
+            Error::NotFound(..) | Error::UnsupportedUrn(..) => StatusCode::NOT_FOUND,
+            Error::UnsupportedVersion(_) | Error::MissingSelf | Error::MissingEssence(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        ServiceError::new(status, Some(err.to_string()))
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use axum::extract::Path;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use nmos_model::{sdp, Model};
+use reqwest::Url;
+use serde_json::{json, Value};
+use tracing::error;
+use uuid::Uuid;
+
+use super::ServiceError;
+
+/// Point every sender without an external manifest at the SDP served by the node.
+pub async fn assign_manifest_hrefs(model: &Model) {
+    let base = {
+        let nodes = model.nodes().await;
+        let node = match nodes.values().next() {
+            Some(node) => node,
+            None => return,
+        };
+
+        match Url::parse(&node.href) {
+            Ok(url) => url,
+            Err(err) => {
+                error!("Cannot build manifest URLs from {}: {}", node.href, err);
+                return;
+            }
+        }
+    };
+
+    let ids: Vec<Uuid> = model
+        .senders()
+        .await
+        .values()
+        .filter(|sender| sender.manifest_href.is_empty())
+        .map(|sender| sender.core.id)
+        .collect();
+
+    for id in ids {
+        let href = match base.join(&format!("x-manifest/senders/{}/manifest", id)) {
+            Ok(href) => href,
+            Err(err) => {
+                error!("Cannot build manifest URL for sender {}: {}", id, err);
+                continue;
+            }
+        };
+
+        model
+            .update_sender(&id, |sender| sender.manifest_href = href.to_string())
+            .await;
+    }
+}
+
+pub async fn get_manifest(
+    Path(id): Path<Uuid>,
+    Extension(model): Extension<Arc<Model>>,
+) -> Result<impl IntoResponse, ServiceError> {
+    let sdp = sender_transport_file(&model, id).await?;
+
+    Ok(([(header::CONTENT_TYPE, "application/sdp")], sdp))
+}
+
+pub async fn get_manifest_senders(Extension(model): Extension<Arc<Model>>) -> Json<Value> {
+    let senders = model.senders().await;
+
+    let senders: Vec<_> = senders.keys().map(|id| format!("{}/", id)).collect();
+
+    Json(json!(senders))
+}
+
+/// SDP describing the active transport parameters of a sender.
+pub async fn sender_transport_file(model: &Model, id: Uuid) -> Result<String, ServiceError> {
+    let senders = model.senders().await;
+    let flows = model.flows().await;
+    let sources = model.sources().await;
+    let nodes = model.nodes().await;
+
+    let sender = match senders.get(&id) {
+        Some(s) => s,
+        None => {
+            return Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                Some(format!("Sender {} does not exist", id)),
+            ))
+        }
+    };
+
+    // Inactive senders have no transport file
+    if !sender.active.is_active() {
+        return Err(ServiceError::new(
+            StatusCode::NOT_FOUND,
+            Some(format!("Sender {} is not active", id)),
+        ));
+    }
+
+    let flow = match flows.get(&sender.flow_id) {
+        Some(f) => f,
+        None => {
+            return Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                Some(format!("Flow {} does not exist", sender.flow_id)),
+            ))
+        }
+    };
+
+    // Reference clock of the flow's source
+    let clock = sources
+        .get(&flow.source_id)
+        .and_then(|source| source.clock_name.as_ref())
+        .and_then(|name| nodes.values().find_map(|node| node.clock(name)))
+        .map(|clock| &clock.kind);
+
+    match sdp::generate(sender, flow, clock) {
+        Ok(sdp) => Ok(sdp),
+        Err(err) => Err(ServiceError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Some(format!("Cannot describe sender {}: {}", id, err)),
+        )),
+    }
+}
@@ -1,29 +1,38 @@
+mod connection;
 mod error;
+mod manifest;
 mod node;
 mod registration;
 
 use std::convert::Infallible;
 use std::pin::Pin;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
 
 use axum::{
     body::Body, extract::OriginalUri, handler::Handler, http::Request, http::StatusCode,
-    response::Response, routing::get, Extension, Json, Router,
+    response::Response, routing::get, routing::put, Extension, Json, Router,
 };
-use error::ServiceError;
 use futures::Future;
+use nmos_model::version::APIVersion;
 use nmos_model::Model;
 use serde_json::json;
 use tower::Service;
 
+use crate::EventHandler;
+
+use self::manifest::{get_manifest, get_manifest_senders};
 use self::node::{
-    get_device, get_devices, get_flow, get_flows, get_receiver, get_receivers, get_self,
-    get_sender, get_senders, get_source, get_sources,
+    get_device, get_devices, get_flow, get_flows, get_receiver, get_receivers, get_root, get_self,
+    get_sender, get_senders, get_source, get_sources, get_versions, put_receiver_target,
 };
 
-pub use registration::RegistrationApi;
+pub use connection::assign_control_hrefs;
+pub use error::ServiceError;
+pub use manifest::assign_manifest_hrefs;
+pub use registration::{RegisteredVersions, RegistrationApi};
 
 #[derive(Debug, Clone)]
 pub struct NodeApi {
@@ -31,45 +40,126 @@ pub struct NodeApi {
 }
 
 impl NodeApi {
-    pub fn new(model: Arc<Model>) -> Self {
+    pub fn new(model: Arc<Model>, event_handler: Option<Arc<dyn EventHandler>>) -> Self {
         let router = Router::new()
             .route(
                 "/",
                 get(|| async { Json(json!(["x-manifest/", "x-nmos/"])) }),
             )
-            .route("/x-nmos/", get(|| async { Json(json!(["node/"])) }))
-            .route("/x-nmos/node/", get(|| async { Json(json!(["v1.0/"])) }))
-            .route(
-                "/x-nmos/node/v1.0/",
-                get(|| async {
-                    Json(json!([
-                        "devices/",
-                        "flows/",
-                        "receivers/",
-                        "self/",
-                        "senders/",
-                        "sources/"
-                    ]))
-                }),
+            .route(
+                "/x-nmos/",
+                get(|| async { Json(json!(["connection/", "node/"])) }),
             )
+            .route("/x-nmos/node/", get(get_versions))
+            .route("/x-nmos/node/:api/", get(get_root))
             .route("/x-nmos/node/:api/self", get(get_self))
             .route("/x-nmos/node/:api/devices/", get(get_devices))
             .route("/x-nmos/node/:api/devices/:id", get(get_device))
             .route("/x-nmos/node/:api/receivers/", get(get_receivers))
             .route("/x-nmos/node/:api/receivers/:id", get(get_receiver))
+            .route(
+                "/x-nmos/node/:api/receivers/:id/target",
+                put(put_receiver_target),
+            )
             .route("/x-nmos/node/:api/senders/", get(get_senders))
             .route("/x-nmos/node/:api/senders/:id", get(get_sender))
             .route("/x-nmos/node/:api/sources/", get(get_sources))
             .route("/x-nmos/node/:api/sources/:id", get(get_source))
             .route("/x-nmos/node/:api/flows/", get(get_flows))
             .route("/x-nmos/node/:api/flows/:id", get(get_flow))
+            .route("/x-manifest/", get(|| async { Json(json!(["senders/"])) }))
+            .route("/x-manifest/senders/", get(get_manifest_senders))
+            .route(
+                "/x-manifest/senders/:id/",
+                get(|| async { Json(json!(["manifest"])) }),
+            )
+            .route("/x-manifest/senders/:id/manifest", get(get_manifest))
+            .route(
+                "/x-nmos/connection/",
+                get(|| async { Json(json!(["v1.0/", "v1.1/"])) }),
+            )
+            .route(
+                "/x-nmos/connection/:api/",
+                get(|| async { Json(json!(["single/"])) }),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/",
+                get(|| async { Json(json!(["receivers/", "senders/"])) }),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/senders/",
+                get(connection::get_senders),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/senders/:id/",
+                get(connection::get_sender),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/senders/:id/constraints",
+                get(connection::get_sender_constraints),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/senders/:id/staged",
+                get(connection::get_sender_staged).patch(connection::patch_sender_staged),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/senders/:id/active",
+                get(connection::get_sender_active),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/senders/:id/transportfile",
+                get(connection::get_sender_transportfile),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/senders/:id/transporttype",
+                get(connection::get_sender_transporttype),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/receivers/",
+                get(connection::get_receivers),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/receivers/:id/",
+                get(connection::get_receiver),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/receivers/:id/constraints",
+                get(connection::get_receiver_constraints),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/receivers/:id/staged",
+                get(connection::get_receiver_staged).patch(connection::patch_receiver_staged),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/receivers/:id/active",
+                get(connection::get_receiver_active),
+            )
+            .route(
+                "/x-nmos/connection/:api/single/receivers/:id/transporttype",
+                get(connection::get_receiver_transporttype),
+            )
             .fallback(fallback_handler.into_service())
-            .layer(Extension(model));
+            .layer(Extension(model))
+            .layer(Extension(event_handler));
 
         Self { router }
     }
 }
 
+/// Parse the API version of a request path, rejecting versions not in `supported`.
+pub fn parse_api_version(api: &str, supported: &[APIVersion]) -> Result<APIVersion, ServiceError> {
+    let api = APIVersion::from_str(api)?;
+
+    if !supported.contains(&api) {
+        return Err(ServiceError::new(
+            StatusCode::BAD_REQUEST,
+            Some(format!("Unsupported API: {}", api)),
+        ));
+    }
+
+    Ok(api)
+}
+
 async fn fallback_handler(OriginalUri(uri): OriginalUri) -> ServiceError {
     ServiceError::new(
         StatusCode::NOT_FOUND,
@@ -1,237 +1,259 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use axum::extract::rejection::JsonRejection;
 use axum::extract::Path;
 use axum::http::StatusCode;
 use axum::{Extension, Json};
-use nmos_model::resource::{DeviceJson, FlowJson, NodeJson, ReceiverJson, SenderJson, SourceJson};
-use nmos_model::version::is_04::V1_0;
+use nmos_model::connection::ActivationMode;
+use nmos_model::resource::{Resource, ResourceType};
+use nmos_model::version::is_04::VERSIONS;
 use nmos_model::version::APIVersion;
-use nmos_model::Model;
+use nmos_model::{Error, Model};
+use serde_json::{json, Map, Value};
 use uuid::Uuid;
 
-use super::ServiceError;
+use super::connection::stage_receiver;
+use super::manifest::sender_transport_file;
+use super::{parse_api_version, ServiceError};
+use crate::EventHandler;
+
+fn list_json<R: Resource>(
+    resources: &HashMap<Uuid, R>,
+    api: &APIVersion,
+) -> Result<Json<Vec<Value>>, ServiceError> {
+    // Resources using URNs the version does not allow are left out
+    let resources = resources
+        .values()
+        .map(|r| r.render(api))
+        .filter(|r| !matches!(r, Err(Error::UnsupportedUrn(..))))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Json(resources))
+}
 
-const SUPPORTED_API_VERSIONS: &[APIVersion] = &[V1_0];
+fn resource_json<R: Resource>(
+    resources: &HashMap<Uuid, R>,
+    api: &APIVersion,
+    id: &Uuid,
+) -> Result<Json<Value>, ServiceError> {
+    match resources.get(id) {
+        Some(r) => Ok(Json(r.render(api)?)),
+        None => {
+            let type_ = R::RESOURCE_TYPE.to_string();
+            let (first, rest) = type_.split_at(1);
 
-fn parse_api_version(api: &str) -> Result<APIVersion, ServiceError> {
-    let api = match APIVersion::from_str(api) {
-        Ok(api) => api,
-        Err(_) => {
-            return Err(ServiceError::new(
-                StatusCode::BAD_REQUEST,
-                Some(String::from("API version badly formed")),
+            Err(ServiceError::new(
+                StatusCode::NOT_FOUND,
+                Some(format!(
+                    "{}{} {} does not exist",
+                    first.to_uppercase(),
+                    rest,
+                    id
+                )),
             ))
         }
-    };
-
-    if !SUPPORTED_API_VERSIONS.contains(&api) {
-        return Err(ServiceError::new(
-            StatusCode::BAD_REQUEST,
-            Some(format!("Unsupported API: {}", api)),
-        ));
     }
+}
 
-    Ok(api)
+pub async fn get_versions() -> Json<Value> {
+    let versions: Vec<_> = VERSIONS.iter().map(|api| format!("{}/", api)).collect();
+
+    Json(json!(versions))
+}
+
+pub async fn get_root(Path(api): Path<String>) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    Ok(Json(json!([
+        "devices/",
+        "flows/",
+        "receivers/",
+        "self/",
+        "senders/",
+        "sources/"
+    ])))
 }
 
 pub async fn get_self(
     Path(api): Path<String>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<NodeJson>, ServiceError> {
-    let api = parse_api_version(&api)?;
-
-    let nodes = model.nodes().await;
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
-    let node = nodes
-        .iter()
-        .next()
-        .expect("Missing self resource")
-        .1
-        .to_json(&api);
+    let id = model.self_id().await?;
 
-    Ok(Json(node))
+    Ok(Json(model.render(ResourceType::Node, &api, &id).await?))
 }
 
 pub async fn get_devices(
     Path(api): Path<String>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<Vec<DeviceJson>>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Vec<Value>>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let devices = model.devices().await;
 
-    let devices: Vec<_> = devices
-        .iter()
-        .map(|(_, device)| device.to_json(&api))
-        .collect();
-
-    Ok(Json(devices))
+    list_json(&devices, &api)
 }
 
 pub async fn get_device(
     Path((api, id)): Path<(String, Uuid)>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<DeviceJson>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let devices = model.devices().await;
 
-    let device = match devices.get(&id) {
-        Some(d) => d.to_json(&api),
-
-        None => {
-            return Err(ServiceError::new(
-                StatusCode::NOT_FOUND,
-                Some(format!("Device {} does not exist", id)),
-            ))
-        }
-    };
-
-    Ok(Json(device))
+    resource_json(&devices, &api, &id)
 }
 
 pub async fn get_receivers(
     Path(api): Path<String>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<Vec<ReceiverJson>>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Vec<Value>>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let receivers = model.receivers().await;
 
-    let receivers: Vec<_> = receivers
-        .iter()
-        .map(|(_, receiver)| receiver.to_json(&api))
-        .collect();
-
-    Ok(Json(receivers))
+    list_json(&receivers, &api)
 }
 
 pub async fn get_receiver(
     Path((api, id)): Path<(String, Uuid)>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<ReceiverJson>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let receivers = model.receivers().await;
 
-    let receiver = match receivers.get(&id) {
-        Some(r) => r.to_json(&api),
-        None => {
+    resource_json(&receivers, &api, &id)
+}
+
+pub async fn put_receiver_target(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(event_handler): Extension<Option<Arc<dyn EventHandler>>>,
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<(StatusCode, Json<Value>), ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    let sender = match body {
+        Ok(Json(Value::Object(sender))) => sender,
+        _ => {
             return Err(ServiceError::new(
-                StatusCode::NOT_FOUND,
-                Some(format!("Receiver {} does not exist", id)),
+                StatusCode::BAD_REQUEST,
+                Some(String::from("Target must be a sender or an empty object")),
             ))
         }
     };
 
-    Ok(Json(receiver))
+    // An empty object disconnects the receiver
+    let sender_id = match sender.get("id") {
+        None if sender.is_empty() => None,
+        Some(Value::String(sender_id)) => match Uuid::from_str(sender_id) {
+            Ok(sender_id) => Some(sender_id),
+            Err(_) => {
+                return Err(ServiceError::new(
+                    StatusCode::BAD_REQUEST,
+                    Some(format!("Sender id {} is not a valid UUID", sender_id)),
+                ))
+            }
+        },
+        _ => {
+            return Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                Some(String::from("Target sender is missing an id")),
+            ))
+        }
+    };
+
+    // The target is an immediate IS-05 activation, taking the transport file
+    // of a sender which is one of the node's own
+    let mut patch = Map::new();
+    patch.insert(String::from("sender_id"), json!(sender_id));
+    patch.insert(String::from("master_enable"), json!(sender_id.is_some()));
+    patch.insert(
+        String::from("activation"),
+        json!({ "mode": ActivationMode::ActivateImmediate.to_string() }),
+    );
+    if let Some(sender_id) = sender_id {
+        if let Ok(data) = sender_transport_file(&model, sender_id).await {
+            patch.insert(
+                String::from("transport_file"),
+                json!({ "data": data, "type": "application/sdp" }),
+            );
+        }
+    }
+
+    stage_receiver(model, event_handler, id, &patch).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(Value::Object(sender))))
 }
 
 pub async fn get_senders(
     Path(api): Path<String>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<Vec<SenderJson>>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Vec<Value>>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let senders = model.senders().await;
 
-    let senders: Vec<_> = senders
-        .iter()
-        .map(|(_, sender)| sender.to_json(&api))
-        .collect();
-
-    Ok(Json(senders))
+    list_json(&senders, &api)
 }
 
 pub async fn get_sender(
     Path((api, id)): Path<(String, Uuid)>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<SenderJson>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let senders = model.senders().await;
 
-    let sender = match senders.get(&id) {
-        Some(s) => s.to_json(&api),
-        None => {
-            return Err(ServiceError::new(
-                StatusCode::NOT_FOUND,
-                Some(format!("Sender {} does not exist", id)),
-            ))
-        }
-    };
-
-    Ok(Json(sender))
+    resource_json(&senders, &api, &id)
 }
 
 pub async fn get_sources(
     Path(api): Path<String>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<Vec<SourceJson>>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Vec<Value>>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let sources = model.sources().await;
 
-    let sources: Vec<_> = sources
-        .iter()
-        .map(|(_, source)| source.to_json(&api))
-        .collect();
-
-    Ok(Json(sources))
+    list_json(&sources, &api)
 }
 
 pub async fn get_source(
     Path((api, id)): Path<(String, Uuid)>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<SourceJson>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let sources = model.sources().await;
 
-    let source = match sources.get(&id) {
-        Some(s) => s.to_json(&api),
-        None => {
-            return Err(ServiceError::new(
-                StatusCode::NOT_FOUND,
-                Some(format!("Source {} does not exist", id)),
-            ))
-        }
-    };
-
-    Ok(Json(source))
+    resource_json(&sources, &api, &id)
 }
 
 pub async fn get_flows(
     Path(api): Path<String>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<Vec<FlowJson>>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Vec<Value>>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let flows = model.flows().await;
 
-    let flows: Vec<_> = flows.iter().map(|(_, flow)| flow.to_json(&api)).collect();
-
-    Ok(Json(flows))
+    list_json(&flows, &api)
 }
 
 pub async fn get_flow(
     Path((api, id)): Path<(String, Uuid)>,
     Extension(model): Extension<Arc<Model>>,
-) -> Result<Json<FlowJson>, ServiceError> {
-    let api = parse_api_version(&api)?;
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
 
     let flows = model.flows().await;
 
-    let flow = match flows.get(&id) {
-        Some(f) => f.to_json(&api),
-        None => {
-            return Err(ServiceError::new(
-                StatusCode::NOT_FOUND,
-                Some(format!("Flow {} does not exist", id)),
-            ))
-        }
-    };
-
-    Ok(Json(flow))
+    resource_json(&flows, &api, &id)
 }
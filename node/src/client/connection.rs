@@ -0,0 +1,898 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use nmos_model::connection::{Activation, ActivationMode};
+use nmos_model::resource::ControlType;
+use nmos_model::version::{
+    is_05::{V1_0, V1_1},
+    APIVersion,
+};
+use nmos_schema::is_05;
+use regex::Regex;
+use reqwest::{header, StatusCode, Url};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use super::check_status;
+use super::error::{Error, Result};
+
+// Each response is an enum of the schema types of every IS-05 version, boxed
+// as their sizes differ widely
+macro_rules! connection_response {
+    ($name:ident, $schema:ident) => {
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum $name {
+            V1_0(Box<is_05::v1_0_x::$schema>),
+            V1_1(Box<is_05::v1_1_x::$schema>),
+        }
+
+        impl $name {
+            fn from_json(api: &APIVersion, value: Value) -> Result<Self> {
+                match *api {
+                    V1_0 => Ok(serde_json::from_value(value).map(Self::V1_0)?),
+                    V1_1 => Ok(serde_json::from_value(value).map(Self::V1_1)?),
+                    _ => Err(Error::InvalidResponse(format!(
+                        "Unsupported API version {}",
+                        api
+                    ))),
+                }
+            }
+
+            /// API version of the schema the response was deserialized with.
+            #[must_use]
+            pub fn api_version(&self) -> APIVersion {
+                match self {
+                    Self::V1_0(_) => V1_0,
+                    Self::V1_1(_) => V1_1,
+                }
+            }
+        }
+    };
+}
+
+connection_response!(SenderResponse, SenderResponseSchema);
+connection_response!(ReceiverResponse, ReceiverResponseSchema);
+
+/// Transport file of a sender, such as an SDP file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportFile {
+    pub data: String,
+    pub type_: String,
+}
+
+/// Parameters to stage on a receiver. Parameters left unset are unchanged.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct ReceiverStage {
+    sender_id: Option<Option<Uuid>>,
+    master_enable: Option<bool>,
+    activation: Option<Activation>,
+    transport_file: Option<TransportFile>,
+    transport_params: Option<Vec<Map<String, Value>>>,
+}
+
+impl ReceiverStage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sender_id(mut self, sender_id: Option<Uuid>) -> Self {
+        self.sender_id = Some(sender_id);
+        self
+    }
+
+    pub fn master_enable(mut self, master_enable: bool) -> Self {
+        self.master_enable = Some(master_enable);
+        self
+    }
+
+    pub fn activation(mut self, activation: Activation) -> Self {
+        self.activation = Some(activation);
+        self
+    }
+
+    pub fn transport_file(mut self, transport_file: TransportFile) -> Self {
+        self.transport_file = Some(transport_file);
+        self
+    }
+
+    /// Transport parameters of each leg, such as `destination_port`.
+    pub fn transport_params(mut self, transport_params: Vec<Map<String, Value>>) -> Self {
+        self.transport_params = Some(transport_params);
+        self
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        let mut json = Map::new();
+
+        if let Some(sender_id) = &self.sender_id {
+            json.insert(String::from("sender_id"), json!(sender_id));
+        }
+        if let Some(master_enable) = self.master_enable {
+            json.insert(String::from("master_enable"), json!(master_enable));
+        }
+        if let Some(activation) = &self.activation {
+            json.insert(
+                String::from("activation"),
+                json!({
+                    "mode": activation.mode.map(|mode| mode.to_string()),
+                    "requested_time": activation.requested_time,
+                }),
+            );
+        }
+        if let Some(transport_file) = &self.transport_file {
+            json.insert(
+                String::from("transport_file"),
+                json!({ "data": transport_file.data, "type": transport_file.type_ }),
+            );
+        }
+        if let Some(transport_params) = &self.transport_params {
+            json.insert(String::from("transport_params"), json!(transport_params));
+        }
+
+        Value::Object(json)
+    }
+}
+
+/// A transport parameter outside the constraints of a sender or receiver.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstraintViolation {
+    pub id: Uuid,
+    /// Index of the transport parameter leg.
+    pub leg: usize,
+    pub param: String,
+    pub value: Value,
+    pub reason: String,
+}
+
+impl fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} transport_params[{}].{} = {}: {}",
+            self.id, self.leg, self.param, self.value, self.reason
+        )
+    }
+}
+
+/// Outcome of staging one resource in a bulk request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BulkResult {
+    pub id: Uuid,
+    pub code: StatusCode,
+    pub error: Option<String>,
+    pub debug: Option<String>,
+}
+
+impl BulkResult {
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.code.is_success()
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let id = Uuid::from_str(value.get("id")?.as_str()?).ok()?;
+        let code = value
+            .get("code")?
+            .as_u64()
+            .and_then(|code| u16::try_from(code).ok())
+            .and_then(|code| StatusCode::from_u16(code).ok())?;
+        let text = |key: &str| value.get(key).and_then(Value::as_str).map(String::from);
+
+        Some(Self {
+            id,
+            code,
+            error: text("error"),
+            debug: text("debug"),
+        })
+    }
+}
+
+/// Client of a device's IS-05 Connection API.
+#[derive(Debug, Clone)]
+pub struct ConnectionClient {
+    client: reqwest::Client,
+    url: Url,
+    api: APIVersion,
+}
+
+impl ConnectionClient {
+    /// Create a client of the Connection API at a base URL such as
+    /// `http://device.local/x-nmos/connection/`.
+    #[must_use]
+    pub fn new(url: Url, api: APIVersion) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            api,
+        }
+    }
+
+    /// Create a client from a device control, such as one of type
+    /// `urn:x-nmos:control:sr-ctrl/v1.1` with href
+    /// `http://device.local/x-nmos/connection/v1.1/`.
+    #[must_use]
+    pub fn from_control(type_: &ControlType, href: &str) -> Option<Self> {
+        let api = match type_ {
+            ControlType::ConnectionV1_0 => V1_0,
+            ControlType::ConnectionV1_1 => V1_1,
+            _ => return None,
+        };
+
+        let href = Url::parse(href).ok()?;
+        let url = href.join("../").ok()?;
+
+        Some(Self::new(url, api))
+    }
+
+    #[must_use]
+    pub fn api(&self) -> APIVersion {
+        self.api
+    }
+
+    #[must_use]
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    fn single_url(&self, type_: &str, id: &Uuid, endpoint: &str) -> Result<Url> {
+        let base = self.url.join(&format!("{}/single/{}/", self.api, type_))?;
+        Ok(base.join(&format!("{}/{}", id, endpoint))?)
+    }
+
+    async fn get_json(&self, url: Url) -> Result<Value> {
+        let response = self.client.get(url).send().await?;
+        Ok(check_status(response).await?.json().await?)
+    }
+
+    pub async fn sender_staged(&self, id: &Uuid) -> Result<SenderResponse> {
+        let value = self
+            .get_json(self.single_url("senders", id, "staged")?)
+            .await?;
+        SenderResponse::from_json(&self.api, value)
+    }
+
+    pub async fn sender_active(&self, id: &Uuid) -> Result<SenderResponse> {
+        let value = self
+            .get_json(self.single_url("senders", id, "active")?)
+            .await?;
+        SenderResponse::from_json(&self.api, value)
+    }
+
+    /// Transport file of a sender, if it has one.
+    pub async fn sender_transport_file(&self, id: &Uuid) -> Result<Option<TransportFile>> {
+        let url = self.single_url("senders", id, "transportfile")?;
+
+        let response = self.client.get(url).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            // Unknown senders are reported as errors rather than having no file
+            self.get_json(self.single_url("senders", id, "")?).await?;
+            return Ok(None);
+        }
+        let response = check_status(response).await?;
+
+        let type_ = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|type_| type_.to_str().ok())
+            .and_then(|type_| type_.split(';').next())
+            .unwrap_or("application/sdp")
+            .to_string();
+        let data = response.text().await?;
+
+        Ok(Some(TransportFile { data, type_ }))
+    }
+
+    pub async fn receiver_staged(&self, id: &Uuid) -> Result<ReceiverResponse> {
+        let value = self
+            .get_json(self.single_url("receivers", id, "staged")?)
+            .await?;
+        ReceiverResponse::from_json(&self.api, value)
+    }
+
+    pub async fn receiver_active(&self, id: &Uuid) -> Result<ReceiverResponse> {
+        let value = self
+            .get_json(self.single_url("receivers", id, "active")?)
+            .await?;
+        ReceiverResponse::from_json(&self.api, value)
+    }
+
+    /// Constraints on the transport parameters of each leg of a receiver.
+    pub async fn receiver_constraints(&self, id: &Uuid) -> Result<Vec<Map<String, Value>>> {
+        let value = self
+            .get_json(self.single_url("receivers", id, "constraints")?)
+            .await?;
+
+        match value {
+            Value::Array(legs) => legs
+                .into_iter()
+                .map(|leg| match leg {
+                    Value::Object(leg) => Ok(leg),
+                    _ => Err(Error::InvalidResponse(String::from(
+                        "Constraints must be objects",
+                    ))),
+                })
+                .collect(),
+            _ => Err(Error::InvalidResponse(String::from(
+                "Constraints must be an array",
+            ))),
+        }
+    }
+
+    /// Stage parameters on a receiver, first checking any transport
+    /// parameters against the receiver's constraints.
+    pub async fn patch_receiver_staged(
+        &self,
+        id: &Uuid,
+        stage: &ReceiverStage,
+    ) -> Result<ReceiverResponse> {
+        self.check_receiver_constraints(id, stage).await?;
+
+        let url = self.single_url("receivers", id, "staged")?;
+        let response = self.client.patch(url).json(&stage.to_json()).send().await?;
+        let value = check_status(response).await?.json().await?;
+
+        ReceiverResponse::from_json(&self.api, value)
+    }
+
+    /// Stage parameters on many receivers in one request.
+    ///
+    /// Nothing is staged if any transport parameters violate constraints,
+    /// otherwise the result for each receiver is returned.
+    pub async fn patch_receivers_staged(
+        &self,
+        stages: &[(Uuid, ReceiverStage)],
+    ) -> Result<Vec<BulkResult>> {
+        let mut violations = Vec::new();
+        for (id, stage) in stages {
+            match self.check_receiver_constraints(id, stage).await {
+                Ok(()) => {}
+                Err(Error::Constraints(mut v)) => violations.append(&mut v),
+                Err(err) => return Err(err),
+            }
+        }
+        if !violations.is_empty() {
+            return Err(Error::Constraints(violations));
+        }
+
+        let body: Vec<Value> = stages
+            .iter()
+            .map(|(id, stage)| json!({ "id": id, "params": stage.to_json() }))
+            .collect();
+
+        let url = self.url.join(&format!("{}/bulk/receivers", self.api))?;
+        let response = self.client.post(url).json(&body).send().await?;
+        let results: Vec<Value> = check_status(response).await?.json().await?;
+
+        results
+            .iter()
+            .map(|result| {
+                BulkResult::from_json(result)
+                    .ok_or_else(|| Error::InvalidResponse(String::from("Invalid bulk result")))
+            })
+            .collect()
+    }
+
+    /// Connect a receiver to a sender, which may be on another device, and
+    /// read back the receiver's active parameters.
+    ///
+    /// The receiver is staged with the sender's transport file, or if it has
+    /// none, the sender's active RTP transport parameters. A scheduled
+    /// activation is not yet reflected in the returned parameters.
+    pub async fn connect(
+        &self,
+        receiver_id: &Uuid,
+        sender: &ConnectionClient,
+        sender_id: &Uuid,
+        activation: Activation,
+    ) -> Result<ReceiverResponse> {
+        let stage = Self::connection_stage(sender, sender_id, activation).await?;
+        self.patch_receiver_staged(receiver_id, &stage).await?;
+
+        self.receiver_active(receiver_id).await
+    }
+
+    /// Connect many receivers of this device to senders in one request.
+    pub async fn connect_all(
+        &self,
+        connections: &[(Uuid, &ConnectionClient, Uuid)],
+        activation: Activation,
+    ) -> Result<Vec<BulkResult>> {
+        let mut stages = Vec::new();
+        for (receiver_id, sender, sender_id) in connections {
+            let stage = Self::connection_stage(sender, sender_id, activation.clone()).await?;
+            stages.push((*receiver_id, stage));
+        }
+
+        self.patch_receivers_staged(&stages).await
+    }
+
+    /// Disconnect a receiver from its sender immediately.
+    pub async fn disconnect(&self, receiver_id: &Uuid) -> Result<ReceiverResponse> {
+        let stage = ReceiverStage::new()
+            .sender_id(None)
+            .master_enable(false)
+            .activation(immediate());
+        self.patch_receiver_staged(receiver_id, &stage).await?;
+
+        self.receiver_active(receiver_id).await
+    }
+
+    async fn connection_stage(
+        sender: &ConnectionClient,
+        sender_id: &Uuid,
+        activation: Activation,
+    ) -> Result<ReceiverStage> {
+        let stage = ReceiverStage::new()
+            .sender_id(Some(*sender_id))
+            .master_enable(true)
+            .activation(activation);
+
+        if let Some(transport_file) = sender.sender_transport_file(sender_id).await? {
+            return Ok(stage.transport_file(transport_file));
+        }
+
+        let active = sender
+            .get_json(sender.single_url("senders", sender_id, "active")?)
+            .await?;
+        let legs = match active.get("transport_params").and_then(Value::as_array) {
+            Some(legs) => legs,
+            None => {
+                return Err(Error::InvalidResponse(String::from(
+                    "Sender has no transport_params",
+                )))
+            }
+        };
+
+        Ok(stage.transport_params(legs.iter().map(receiver_leg).collect()))
+    }
+
+    async fn check_receiver_constraints(&self, id: &Uuid, stage: &ReceiverStage) -> Result<()> {
+        let transport_params = match &stage.transport_params {
+            Some(transport_params) => transport_params,
+            None => return Ok(()),
+        };
+
+        let constraints = self.receiver_constraints(id).await?;
+        let violations = check_constraints(id, &constraints, transport_params);
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Constraints(violations))
+        }
+    }
+}
+
+fn immediate() -> Activation {
+    Activation {
+        mode: Some(ActivationMode::ActivateImmediate),
+        ..Activation::default()
+    }
+}
+
+// Receiver parameters for an RTP sender leg
+fn receiver_leg(sender_leg: &Value) -> Map<String, Value> {
+    let mut leg = Map::new();
+
+    let destination_ip = sender_leg
+        .get("destination_ip")
+        .and_then(Value::as_str)
+        .and_then(|ip| IpAddr::from_str(ip).ok());
+    if let Some(ip) = destination_ip.filter(IpAddr::is_multicast) {
+        leg.insert(String::from("multicast_ip"), json!(ip.to_string()));
+    }
+
+    let source_ip = sender_leg
+        .get("source_ip")
+        .and_then(Value::as_str)
+        .filter(|ip| IpAddr::from_str(ip).is_ok());
+    if let Some(ip) = source_ip {
+        leg.insert(String::from("source_ip"), json!(ip));
+    }
+
+    if let Some(port) = sender_leg.get("destination_port").filter(|p| p.is_u64()) {
+        leg.insert(String::from("destination_port"), port.clone());
+    }
+    if let Some(rtp_enabled) = sender_leg.get("rtp_enabled").filter(|e| e.is_boolean()) {
+        leg.insert(String::from("rtp_enabled"), rtp_enabled.clone());
+    }
+
+    leg
+}
+
+// Check transport parameters against IS-05 constraints, which may limit a
+// parameter to an enum, a numeric range or a pattern
+fn check_constraints(
+    id: &Uuid,
+    constraints: &[Map<String, Value>],
+    transport_params: &[Map<String, Value>],
+) -> Vec<ConstraintViolation> {
+    let mut violations = Vec::new();
+
+    for (leg, params) in transport_params.iter().enumerate() {
+        let leg_constraints = constraints.get(leg);
+
+        for (param, value) in params {
+            let mut violation = |reason: String| {
+                violations.push(ConstraintViolation {
+                    id: *id,
+                    leg,
+                    param: param.clone(),
+                    value: value.clone(),
+                    reason,
+                });
+            };
+
+            let constraint = match leg_constraints {
+                Some(leg_constraints) => match leg_constraints.get(param) {
+                    Some(constraint) => constraint,
+                    None => {
+                        violation(String::from("parameter is not supported"));
+                        continue;
+                    }
+                },
+                None => {
+                    violation(format!("receiver has {} legs", constraints.len()));
+                    continue;
+                }
+            };
+
+            // Automatic and unset values are resolved by the receiver
+            if value.is_null() || value.as_str() == Some("auto") {
+                continue;
+            }
+
+            if let Some(allowed) = constraint.get("enum").and_then(Value::as_array) {
+                if !allowed.contains(value) {
+                    violation(format!("must be one of {}", Value::from(allowed.clone())));
+                }
+            }
+            if let (Some(minimum), Some(number)) = (
+                constraint.get("minimum").and_then(Value::as_f64),
+                value.as_f64(),
+            ) {
+                if number < minimum {
+                    violation(format!("is below the minimum {}", constraint["minimum"]));
+                }
+            }
+            if let (Some(maximum), Some(number)) = (
+                constraint.get("maximum").and_then(Value::as_f64),
+                value.as_f64(),
+            ) {
+                if number > maximum {
+                    violation(format!("is above the maximum {}", constraint["maximum"]));
+                }
+            }
+            if let (Some(pattern), Some(text)) = (
+                constraint.get("pattern").and_then(Value::as_str),
+                value.as_str(),
+            ) {
+                if let Ok(regex) = Regex::new(pattern) {
+                    if !regex.is_match(text) {
+                        violation(format!("does not match {}", pattern));
+                    }
+                }
+            }
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use axum::Server;
+    use nmos_model::resource::{
+        AudioEssence, Caps, Flow, Format, Rational, Receiver, ResourceBundle, ResourceType, Sender,
+        Source, Transport, VideoEssence,
+    };
+    use nmos_model::test_support::{device, node, sender_leg};
+    use nmos_model::version::is_04::V1_3;
+    use nmos_model::Model;
+    use tower::make::Shared;
+
+    use super::*;
+    use crate::api::NodeApi;
+
+    struct TestNode {
+        model: Arc<Model>,
+        client: ConnectionClient,
+        sender_id: Uuid,
+        inactive_sender_id: Uuid,
+        audio_sender_id: Uuid,
+        receiver_id: Uuid,
+    }
+
+    // Serve the APIs of a node with a video sender and receiver on a free port
+    async fn serve() -> TestNode {
+        let node = node();
+        let device = device(&node);
+        let source = Source::builder("source", &device, Format::Video).build();
+        let flow = Flow::builder("flow", &source)
+            .grain_rate(Rational::new(50, 1))
+            .video(VideoEssence::new(1920, 1080))
+            .build()
+            .unwrap();
+        let sender = Sender::builder("sender", &device, &flow, Transport::RtpMulticast)
+            .transport_params([sender_leg()])
+            .master_enable(true)
+            .build();
+        let inactive_sender =
+            Sender::builder("inactive", &device, &flow, Transport::RtpMulticast).build();
+        let audio_source = Source::builder("audio", &device, Format::Audio).build();
+        let audio_flow = Flow::builder("audio", &audio_source)
+            .audio(AudioEssence::new(Rational::new(48000, 1), 24, 2))
+            .build()
+            .unwrap();
+        let audio_sender =
+            Sender::builder("audio", &device, &audio_flow, Transport::RtpMulticast).build();
+        let receiver = Receiver::builder("receiver", &device, Format::Video, Transport::Rtp)
+            .caps(Caps::new().media_types(["video/raw"]))
+            .build();
+
+        let sender_id = sender.core.id;
+        let inactive_sender_id = inactive_sender.core.id;
+        let audio_sender_id = audio_sender.core.id;
+        let receiver_id = receiver.core.id;
+
+        let mut resources = ResourceBundle::new();
+        resources.insert_node(node);
+        resources.insert_device(device);
+        resources.insert_source(source);
+        resources.insert_flow(flow);
+        resources.insert_sender(sender);
+        resources.insert_sender(inactive_sender);
+        resources.insert_source(audio_source);
+        resources.insert_flow(audio_flow);
+        resources.insert_sender(audio_sender);
+        resources.insert_receiver(receiver);
+
+        let model = Arc::new(Model::from_resources(resources).unwrap());
+        let api = NodeApi::new(model.clone(), None);
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(Shared::new(api));
+        let url = format!("http://{}/x-nmos/connection/", server.local_addr());
+        tokio::spawn(server);
+
+        TestNode {
+            model,
+            client: ConnectionClient::new(Url::parse(&url).unwrap(), V1_1),
+            sender_id,
+            inactive_sender_id,
+            audio_sender_id,
+            receiver_id,
+        }
+    }
+
+    #[tokio::test]
+    async fn single_endpoints() {
+        let node = serve().await;
+        let client = &node.client;
+
+        let staged = client.sender_staged(&node.sender_id).await.unwrap();
+        assert_eq!(staged.api_version(), V1_1);
+        let active = client.sender_active(&node.sender_id).await.unwrap();
+        assert_eq!(active.api_version(), V1_1);
+
+        let staged = client.receiver_staged(&node.receiver_id).await.unwrap();
+        assert_eq!(staged.api_version(), V1_1);
+        client.receiver_active(&node.receiver_id).await.unwrap();
+
+        let constraints = client
+            .receiver_constraints(&node.receiver_id)
+            .await
+            .unwrap();
+        assert_eq!(constraints.len(), 1);
+        assert!(constraints[0].contains_key("destination_port"));
+
+        assert!(client.sender_staged(&Uuid::new_v4()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn transport_files() {
+        let node = serve().await;
+        let client = &node.client;
+
+        let file = client
+            .sender_transport_file(&node.sender_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(file.type_, "application/sdp");
+        assert!(file.data.contains("239.1.1.1"));
+
+        // An inactive sender has no file, but an unknown sender is an error
+        assert_eq!(
+            client
+                .sender_transport_file(&node.inactive_sender_id)
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(client.sender_transport_file(&Uuid::new_v4()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn connect_and_disconnect() {
+        let node = serve().await;
+        let client = &node.client;
+
+        client
+            .connect(&node.receiver_id, client, &node.sender_id, immediate())
+            .await
+            .unwrap();
+        {
+            let receivers = node.model.receivers().await;
+            let active = &receivers[&node.receiver_id].active;
+            assert_eq!(active.sender_id, Some(node.sender_id));
+            assert!(active.master_enable);
+            assert!(active.transport_file.is_some());
+        }
+
+        client.disconnect(&node.receiver_id).await.unwrap();
+        {
+            let receivers = node.model.receivers().await;
+            let active = &receivers[&node.receiver_id].active;
+            assert_eq!(active.sender_id, None);
+            assert!(!active.master_enable);
+        }
+    }
+
+    #[tokio::test]
+    async fn constraint_violations() {
+        let node = serve().await;
+        let client = &node.client;
+
+        let mut leg = Map::new();
+        leg.insert(String::from("destination_port"), json!(5004));
+        leg.insert(String::from("no_such_param"), json!(1));
+        let stage = ReceiverStage::new().transport_params(vec![leg]);
+
+        match client
+            .patch_receiver_staged(&node.receiver_id, &stage)
+            .await
+        {
+            Err(Error::Constraints(violations)) => {
+                assert_eq!(violations.len(), 1);
+                assert_eq!(violations[0].param, "no_such_param");
+            }
+            other => panic!("Expected a constraint violation, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn incompatible_sender() {
+        let node = serve().await;
+
+        let stage = ReceiverStage::new()
+            .sender_id(Some(node.audio_sender_id))
+            .master_enable(true);
+        match node
+            .client
+            .patch_receiver_staged(&node.receiver_id, &stage)
+            .await
+        {
+            Err(Error::Status(status, _)) => assert_eq!(status, StatusCode::BAD_REQUEST),
+            other => panic!("Expected the sender to be rejected, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn pending_activation_locks_staged() {
+        let node = serve().await;
+        let client = &node.client;
+
+        let scheduled = ReceiverStage::new()
+            .sender_id(Some(node.sender_id))
+            .activation(Activation {
+                mode: Some(ActivationMode::ActivateScheduledRelative),
+                requested_time: Some(String::from("60:0")),
+                activation_time: None,
+            });
+        client
+            .patch_receiver_staged(&node.receiver_id, &scheduled)
+            .await
+            .unwrap();
+
+        let enable = ReceiverStage::new().master_enable(true);
+        match client
+            .patch_receiver_staged(&node.receiver_id, &enable)
+            .await
+        {
+            Err(Error::Status(status, _)) => assert_eq!(status, StatusCode::LOCKED),
+            other => panic!("Expected the staged receiver to be locked, got {:?}", other),
+        }
+
+        // Cancelling the activation unlocks it, and a patch without an
+        // activation schedules nothing
+        let cancel = ReceiverStage::new().activation(Activation::default());
+        client
+            .patch_receiver_staged(&node.receiver_id, &cancel)
+            .await
+            .unwrap();
+        client
+            .patch_receiver_staged(&node.receiver_id, &enable)
+            .await
+            .unwrap();
+
+        let receivers = node.model.receivers().await;
+        let receiver = &receivers[&node.receiver_id];
+        assert_eq!(receiver.staged.activation, Activation::default());
+        assert!(receiver.staged.master_enable);
+        assert_eq!(receiver.active.sender_id, None);
+    }
+
+    #[tokio::test]
+    async fn node_api_target() {
+        let node = serve().await;
+        let target = node
+            .client
+            .url()
+            .join(&format!(
+                "/x-nmos/node/v1.3/receivers/{}/target",
+                node.receiver_id
+            ))
+            .unwrap();
+        let sender = node
+            .model
+            .render(ResourceType::Sender, &V1_3, &node.sender_id)
+            .await
+            .unwrap();
+
+        let response = reqwest::Client::new()
+            .put(target.clone())
+            .json(&sender)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        {
+            // The target is the IS-05 active connection
+            let receivers = node.model.receivers().await;
+            let receiver = &receivers[&node.receiver_id];
+            assert_eq!(receiver.active.sender_id, Some(node.sender_id));
+            assert!(receiver.active.master_enable);
+            assert!(receiver.active.transport_file.is_some());
+            assert_eq!(receiver.subscription.sender_id, Some(node.sender_id));
+        }
+
+        let response = reqwest::Client::new()
+            .put(target)
+            .json(&json!({}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let receivers = node.model.receivers().await;
+        let receiver = &receivers[&node.receiver_id];
+        assert_eq!(receiver.active.sender_id, None);
+        assert!(!receiver.active.master_enable);
+        assert!(!receiver.subscription.active);
+    }
+
+    #[test]
+    fn from_control() {
+        let client = ConnectionClient::from_control(
+            &ControlType::ConnectionV1_0,
+            "http://device.local/x-nmos/connection/v1.0/",
+        )
+        .unwrap();
+        assert_eq!(client.api(), V1_0);
+        assert_eq!(
+            client.url().as_str(),
+            "http://device.local/x-nmos/connection/"
+        );
+
+        assert!(ConnectionClient::from_control(
+            &ControlType::EventsV1_0,
+            "http://device.local/x-nmos/events/v1.0/"
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,80 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::result::Result as StdResult;
+
+use reqwest::StatusCode;
+
+use super::ConstraintViolation;
+
+pub type Result<T> = StdResult<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Http(reqwest::Error),
+    Json(serde_json::Error),
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    Url(url::ParseError),
+    /// The API responded with an error status and optional debug message.
+    Status(StatusCode, Option<String>),
+    /// Staged parameters violate the constraints of a sender or receiver.
+    Constraints(Vec<ConstraintViolation>),
+    /// A response did not have the expected form.
+    InvalidResponse(String),
+    /// No API was discovered, or none offered a supported version.
+    NotFound,
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Error::WebSocket(Box::new(e))
+    }
+}
+
+impl From<url::ParseError> for Error {
+    fn from(e: url::ParseError) -> Self {
+        Error::Url(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => fmt::Display::fmt(&e, f),
+            Error::Json(e) => fmt::Display::fmt(&e, f),
+            Error::WebSocket(e) => fmt::Display::fmt(&e, f),
+            Error::Url(e) => fmt::Display::fmt(&e, f),
+            Error::Status(status, Some(debug)) => write!(f, "{}: {}", status, debug),
+            Error::Status(status, None) => fmt::Display::fmt(&status, f),
+            Error::Constraints(violations) => {
+                let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+                write!(f, "Constraints violated: {}", violations.join("; "))
+            }
+            Error::InvalidResponse(msg) => write!(f, "Invalid response: {}", msg),
+            Error::NotFound => write!(f, "No compatible API found"),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::WebSocket(e) => Some(e),
+            Error::Url(e) => Some(e),
+            _ => None,
+        }
+    }
+}
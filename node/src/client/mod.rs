@@ -0,0 +1,32 @@
+//! Clients of the APIs of other NMOS devices and registries.
+
+mod connection;
+mod error;
+mod query;
+mod resource;
+
+pub use connection::{
+    BulkResult, ConnectionClient, ConstraintViolation, ReceiverResponse, ReceiverStage,
+    SenderResponse, TransportFile,
+};
+pub use error::{Error, Result};
+pub use query::{Page, QueryClient, QueryParams, ResourceEvents};
+pub use resource::{Device, Flow, Node, QueryResource, Receiver, ResourceEvent, Sender, Source};
+
+use serde_json::Value;
+
+// Turn an error response into an error, keeping its debug message
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let debug = response
+        .json::<Value>()
+        .await
+        .ok()
+        .and_then(|error| error.get("debug").and_then(Value::as_str).map(String::from));
+
+    Err(Error::Status(status, debug))
+}
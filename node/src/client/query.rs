@@ -0,0 +1,462 @@
+use std::collections::{BinaryHeap, VecDeque};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use futures::{stream, Stream, StreamExt};
+use nmos_model::index::Order;
+use nmos_model::tai::TaiTime;
+use nmos_model::version::{
+    is_04::{V1_3, VERSIONS},
+    APIVersion,
+};
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+use serde_json::{json, Map, Value};
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+use super::check_status;
+use super::error::{Error, Result};
+use super::resource::{QueryResource, ResourceEvent};
+use crate::api::RegistrationApi;
+use crate::mdns::{MdnsContext, NmosMdnsEvent, NmosMdnsRegistry};
+
+/// Stream of changes to the resources matched by a subscription.
+pub type ResourceEvents<R> = Pin<Box<dyn Stream<Item = Result<ResourceEvent<R>>> + Send>>;
+
+/// Filters and paging of a Query API request.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct QueryParams {
+    filters: Vec<(String, String)>,
+    rql: Option<String>,
+    downgrade: Option<APIVersion>,
+    order: Option<Order>,
+    since: Option<TaiTime>,
+    until: Option<TaiTime>,
+    limit: Option<usize>,
+}
+
+impl QueryParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match resources with a property of this value, such as
+    /// `label` or `tags.location`.
+    pub fn filter<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.filters.push((key.into(), value.into()));
+        self
+    }
+
+    /// Only match resources selected by an RQL expression.
+    pub fn rql<S: Into<String>>(mut self, query: S) -> Self {
+        self.rql = Some(query.into());
+        self
+    }
+
+    /// Also match resources registered at versions down to this one.
+    pub fn downgrade(mut self, api: APIVersion) -> Self {
+        self.downgrade = Some(api);
+        self
+    }
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.order = Some(order);
+        self
+    }
+
+    pub fn since(mut self, since: TaiTime) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: TaiTime) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    // Parameters selecting resources, as also used by subscriptions
+    fn filter_query(&self) -> Vec<(String, String)> {
+        let mut query = self.filters.clone();
+
+        if let Some(rql) = &self.rql {
+            query.push((String::from("query.rql"), rql.clone()));
+        }
+        if let Some(downgrade) = &self.downgrade {
+            query.push((String::from("query.downgrade"), downgrade.to_string()));
+        }
+
+        query
+    }
+
+    fn to_query(&self) -> Vec<(String, String)> {
+        let mut query = self.filter_query();
+
+        if let Some(order) = self.order {
+            let order = match order {
+                Order::Created => "create",
+                Order::Updated => "update",
+            };
+            query.push((String::from("paging.order"), String::from(order)));
+        }
+        if let Some(since) = &self.since {
+            query.push((String::from("paging.since"), since.to_string()));
+        }
+        if let Some(until) = &self.until {
+            query.push((String::from("paging.until"), until.to_string()));
+        }
+        if let Some(limit) = self.limit {
+            query.push((String::from("paging.limit"), limit.to_string()));
+        }
+
+        query
+    }
+}
+
+/// A page of resources listed by the Query API, most recent first.
+#[derive(Debug, Clone)]
+pub struct Page<R> {
+    pub resources: Vec<R>,
+    /// Bounds of the page, unless the API does not support paging.
+    pub since: Option<TaiTime>,
+    pub until: Option<TaiTime>,
+    pub limit: Option<usize>,
+    params: QueryParams,
+}
+
+impl<R> Page<R> {
+    /// Parameters of the page of more recent resources.
+    #[must_use]
+    pub fn next(&self) -> Option<QueryParams> {
+        let mut params = self.params.clone();
+        params.since = Some(self.until?);
+        params.until = None;
+        params.limit = self.limit;
+
+        Some(params)
+    }
+
+    /// Parameters of the page of older resources.
+    #[must_use]
+    pub fn prev(&self) -> Option<QueryParams> {
+        let mut params = self.params.clone();
+        params.since = None;
+        params.until = Some(self.since?);
+        params.limit = self.limit;
+
+        Some(params)
+    }
+}
+
+/// Client of a registry's Query API.
+#[derive(Debug, Clone)]
+pub struct QueryClient {
+    client: reqwest::Client,
+    url: Url,
+    api: APIVersion,
+}
+
+impl QueryClient {
+    /// Create a client of the Query API at a base URL such as
+    /// `http://registry.local/x-nmos/query/`.
+    #[must_use]
+    pub fn new(url: Url, api: APIVersion) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+            api,
+        }
+    }
+
+    /// Create a client using the newest API version the registry offers.
+    #[must_use]
+    pub fn from_registry(registry: &NmosMdnsRegistry) -> Option<Self> {
+        let api = RegistrationApi::negotiate_version(registry)?;
+        Some(Self::new(registry.url.clone(), api))
+    }
+
+    /// Browse for `_nmos-query._tcp` services for the given time, and create a
+    /// client of the highest priority registry with a supported API version.
+    pub async fn discover(timeout: Duration) -> Result<Self> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        // MDNS must run on its own thread
+        thread::spawn(move || {
+            let mut context = MdnsContext::query(tx.clone());
+            let poller = context.start();
+
+            while !tx.is_closed() {
+                poller.poll();
+                thread::sleep(Duration::from_millis(100));
+            }
+        });
+
+        let deadline = Instant::now() + timeout;
+        let mut registries = BinaryHeap::new();
+
+        while let Ok(Some(event)) = time::timeout_at(deadline, rx.recv()).await {
+            if let NmosMdnsEvent::Discovery(_, Ok(discovery)) = event {
+                if let Some(registry) = NmosMdnsRegistry::parse_query(&discovery) {
+                    registries.push(registry);
+                }
+            }
+        }
+
+        while let Some(registry) = registries.pop() {
+            if let Some(client) = Self::from_registry(&registry) {
+                return Ok(client);
+            }
+        }
+
+        Err(Error::NotFound)
+    }
+
+    #[must_use]
+    pub fn api(&self) -> APIVersion {
+        self.api
+    }
+
+    #[must_use]
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    fn resources_url<R: QueryResource>(&self) -> Result<Url> {
+        let base = self.url.join(&format!("{}/", self.api))?;
+        Ok(base.join(&format!("{}s/", R::RESOURCE_TYPE))?)
+    }
+
+    /// List the resources of a type matching the query parameters.
+    pub async fn list<R: QueryResource>(&self, params: &QueryParams) -> Result<Page<R>> {
+        let response = self
+            .client
+            .get(self.resources_url::<R>()?)
+            .query(&params.to_query())
+            .send()
+            .await?;
+        let response = check_status(response).await?;
+
+        let headers = response.headers();
+        let since = paging_header(headers, "x-paging-since");
+        let until = paging_header(headers, "x-paging-until");
+        let limit = paging_header(headers, "x-paging-limit");
+
+        let values: Vec<Value> = response.json().await?;
+        let resources = values
+            .into_iter()
+            .map(|value| self.deserialize(value, params.downgrade))
+            .collect::<Result<_>>()?;
+
+        Ok(Page {
+            resources,
+            since,
+            until,
+            limit,
+            params: params.clone(),
+        })
+    }
+
+    /// Get a single resource, if it exists at the client's version or at
+    /// versions down to `downgrade`.
+    pub async fn get<R: QueryResource>(
+        &self,
+        id: &Uuid,
+        downgrade: Option<APIVersion>,
+    ) -> Result<Option<R>> {
+        let url = self.resources_url::<R>()?.join(&id.to_string())?;
+
+        let mut request = self.client.get(url);
+        if let Some(downgrade) = &downgrade {
+            request = request.query(&[("query.downgrade", downgrade.to_string())]);
+        }
+
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check_status(response).await?;
+
+        let value: Value = response.json().await?;
+        self.deserialize(value, downgrade).map(Some)
+    }
+
+    /// Subscribe to changes in the resources of a type matching the query
+    /// parameters, ignoring any paging.
+    ///
+    /// The stream starts with a [`ResourceEvent::Sync`] for each matching
+    /// resource, and ends when the WebSocket is closed.
+    pub async fn subscribe<R: QueryResource + Send + 'static>(
+        &self,
+        params: &QueryParams,
+        max_update_rate_ms: u64,
+    ) -> Result<ResourceEvents<R>> {
+        let url = self.url.join(&format!("{}/subscriptions", self.api))?;
+
+        let filters: Map<String, Value> = params
+            .filter_query()
+            .into_iter()
+            .map(|(key, value)| (key, Value::String(value)))
+            .collect();
+
+        let mut body = json!({
+            "max_update_rate_ms": max_update_rate_ms,
+            "persist": false,
+            "secure": false,
+            "resource_path": format!("/{}s", R::RESOURCE_TYPE),
+            "params": filters,
+        });
+        if self.api == V1_3 {
+            body["authorization"] = json!(false);
+        }
+
+        let response = self.client.post(url).json(&body).send().await?;
+        let subscription: Value = check_status(response).await?.json().await?;
+
+        let ws_href = match subscription.get("ws_href").and_then(Value::as_str) {
+            Some(ws_href) => ws_href,
+            None => {
+                return Err(Error::InvalidResponse(String::from(
+                    "Subscription is missing ws_href",
+                )))
+            }
+        };
+
+        let (socket, _) = tokio_tungstenite::connect_async(ws_href).await?;
+
+        let client = self.clone();
+        let downgrade = params.downgrade;
+
+        // Each grain may hold many events, which are queued until taken
+        let events = stream::unfold(Some((socket, VecDeque::new())), move |state| {
+            let client = client.clone();
+
+            async move {
+                let (mut socket, mut pending) = state?;
+
+                loop {
+                    if let Some(event) = pending.pop_front() {
+                        return Some((event, Some((socket, pending))));
+                    }
+
+                    match socket.next().await? {
+                        Ok(Message::Text(text)) => {
+                            pending.extend(client.grain_events::<R>(&text, downgrade));
+                        }
+                        Ok(Message::Close(_)) => return None,
+                        Ok(_) => {}
+                        Err(err) => return Some((Err(err.into()), None)),
+                    }
+                }
+            }
+        });
+
+        Ok(Box::pin(events))
+    }
+
+    // Deserialize a resource at the client's version, falling back to older
+    // versions the query may have downgraded it to
+    fn deserialize<R: QueryResource>(
+        &self,
+        value: Value,
+        downgrade: Option<APIVersion>,
+    ) -> Result<R> {
+        let oldest = downgrade.unwrap_or(self.api);
+        let older = VERSIONS
+            .iter()
+            .rev()
+            .filter(|api| **api < self.api && **api >= oldest);
+
+        let mut result = R::from_json(&self.api, value.clone());
+        for api in older {
+            if result.is_ok() {
+                break;
+            }
+            result = R::from_json(api, value.clone());
+        }
+
+        Ok(result?)
+    }
+
+    fn grain_events<R: QueryResource>(
+        &self,
+        text: &str,
+        downgrade: Option<APIVersion>,
+    ) -> Vec<Result<ResourceEvent<R>>> {
+        let grain: Value = match serde_json::from_str(text) {
+            Ok(grain) => grain,
+            Err(err) => return vec![Err(err.into())],
+        };
+
+        let data = match grain.pointer("/grain/data").and_then(Value::as_array) {
+            Some(data) => data,
+            None => {
+                return vec![Err(Error::InvalidResponse(String::from(
+                    "Grain is missing data",
+                )))]
+            }
+        };
+
+        data.iter()
+            .map(|event| self.resource_event(event, downgrade))
+            .collect()
+    }
+
+    fn resource_event<R: QueryResource>(
+        &self,
+        event: &Value,
+        downgrade: Option<APIVersion>,
+    ) -> Result<ResourceEvent<R>> {
+        let id = event
+            .get("path")
+            .and_then(Value::as_str)
+            .and_then(|path| Uuid::from_str(path.trim_start_matches('/')).ok())
+            .ok_or_else(|| Error::InvalidResponse(String::from("Invalid event path")))?;
+
+        let pre = event.get("pre").cloned();
+        let post = event.get("post").cloned();
+
+        let event = match (pre, post) {
+            (Some(pre), Some(post)) if pre == post => ResourceEvent::Sync {
+                id,
+                resource: self.deserialize(post, downgrade)?,
+            },
+            (Some(pre), Some(post)) => ResourceEvent::Modified {
+                id,
+                pre: self.deserialize(pre, downgrade)?,
+                post: self.deserialize(post, downgrade)?,
+            },
+            (None, Some(post)) => ResourceEvent::Added {
+                id,
+                resource: self.deserialize(post, downgrade)?,
+            },
+            (Some(pre), None) => ResourceEvent::Removed {
+                id,
+                resource: self.deserialize(pre, downgrade)?,
+            },
+            (None, None) => {
+                return Err(Error::InvalidResponse(String::from(
+                    "Event has neither pre nor post",
+                )))
+            }
+        };
+
+        Ok(event)
+    }
+}
+
+fn paging_header<T: FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
@@ -0,0 +1,101 @@
+use nmos_model::resource::ResourceType;
+use nmos_model::version::{
+    is_04::{V1_0, V1_1, V1_2, V1_3},
+    APIVersion,
+};
+use nmos_schema::is_04;
+use serde_json::Value;
+use uuid::Uuid;
+
+/// A resource returned by the Query API, typed by the schema of its version.
+pub trait QueryResource: Sized {
+    const RESOURCE_TYPE: ResourceType;
+
+    /// Deserialize a resource of the given API version.
+    fn from_json(api: &APIVersion, value: Value) -> Result<Self, serde_json::Error>;
+
+    /// API version of the schema the resource was deserialized with.
+    fn api_version(&self) -> APIVersion;
+}
+
+// Each resource type is an enum of the schema types of every IS-04 version,
+// boxed as their sizes differ widely
+macro_rules! query_resource {
+    ($name:ident, $resource_type:expr) => {
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum $name {
+            V1_0(Box<is_04::v1_0_x::$name>),
+            V1_1(Box<is_04::v1_1_x::$name>),
+            V1_2(Box<is_04::v1_2_x::$name>),
+            V1_3(Box<is_04::v1_3_x::$name>),
+        }
+
+        impl QueryResource for $name {
+            const RESOURCE_TYPE: ResourceType = $resource_type;
+
+            fn from_json(api: &APIVersion, value: Value) -> Result<Self, serde_json::Error> {
+                match *api {
+                    V1_0 => serde_json::from_value(value).map(Self::V1_0),
+                    V1_1 => serde_json::from_value(value).map(Self::V1_1),
+                    V1_2 => serde_json::from_value(value).map(Self::V1_2),
+                    V1_3 => serde_json::from_value(value).map(Self::V1_3),
+                    _ => Err(serde::de::Error::custom(format!(
+                        "Unsupported API version {}",
+                        api
+                    ))),
+                }
+            }
+
+            fn api_version(&self) -> APIVersion {
+                match self {
+                    Self::V1_0(_) => V1_0,
+                    Self::V1_1(_) => V1_1,
+                    Self::V1_2(_) => V1_2,
+                    Self::V1_3(_) => V1_3,
+                }
+            }
+        }
+    };
+}
+
+query_resource!(Node, ResourceType::Node);
+query_resource!(Device, ResourceType::Device);
+query_resource!(Source, ResourceType::Source);
+query_resource!(Flow, ResourceType::Flow);
+query_resource!(Sender, ResourceType::Sender);
+query_resource!(Receiver, ResourceType::Receiver);
+
+/// A change to a resource reported by a Query API subscription.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceEvent<R> {
+    /// The resource existed when the subscription was connected.
+    Sync {
+        id: Uuid,
+        resource: R,
+    },
+    Added {
+        id: Uuid,
+        resource: R,
+    },
+    Modified {
+        id: Uuid,
+        pre: R,
+        post: R,
+    },
+    Removed {
+        id: Uuid,
+        resource: R,
+    },
+}
+
+impl<R> ResourceEvent<R> {
+    #[must_use]
+    pub fn id(&self) -> Uuid {
+        match self {
+            ResourceEvent::Sync { id, .. }
+            | ResourceEvent::Added { id, .. }
+            | ResourceEvent::Modified { id, .. }
+            | ResourceEvent::Removed { id, .. } => *id,
+        }
+    }
+}
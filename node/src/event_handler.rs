@@ -1,4 +1,10 @@
 use async_trait::async_trait;
+use uuid::Uuid;
 
 #[async_trait]
-pub trait EventHandler: Send + Sync {}
+pub trait EventHandler: Send + Sync {
+    /// A receiver was connected to `sender_id`, or disconnected if `None`.
+    ///
+    /// Called for IS-04 target requests and IS-05 receiver activations.
+    async fn receiver_subscription(&self, _receiver_id: Uuid, _sender_id: Option<Uuid>) {}
+}
@@ -1,33 +1,51 @@
-use std::{collections::BinaryHeap, sync::Arc, thread, time::Duration};
+use std::{collections::BinaryHeap, path::PathBuf, sync::Arc, thread, time::Duration};
 
 use axum::{http::Method, Server};
 pub use event_handler::EventHandler;
-use mdns::MdnsContext;
-use nmos_model::{resource::ResourceBundle, Model};
+use nmos_model::{
+    clock::{self, ClockSource},
+    resource::{Clock, ClockKind, ResourceBundle},
+    Model,
+};
 use tokio::{
     runtime::Runtime,
-    sync::{mpsc, Mutex},
+    sync::{broadcast::error::RecvError, mpsc, Mutex},
 };
 use tower::{make::Shared, ServiceBuilder};
 use tower_http::cors::{self, CorsLayer};
 use tracing::{error, info};
 
 mod api;
+pub mod client;
 mod error;
 mod event_handler;
 mod mdns;
 
+pub use api::{parse_api_version, ServiceError};
 pub use async_trait::async_trait;
 pub use error::Error as NmosError;
+pub use mdns::{
+    MdnsContext, MdnsPoller, NmosMdnsAdvertisement, NmosMdnsConfig, NmosMdnsEvent,
+    NmosMdnsRegistry, NmosMdnsService,
+};
+
+use api::{
+    assign_control_hrefs, assign_manifest_hrefs, NodeApi, RegisteredVersions, RegistrationApi,
+};
 
-use api::{NodeApi, RegistrationApi};
-use mdns::{NmosMdnsConfig, NmosMdnsEvent, NmosMdnsRegistry};
+/// How often the status of the clock source is refreshed.
+const CLOCK_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to gather changes to the model before saving it.
+const PERSIST_DELAY: Duration = Duration::from_millis(100);
 
 #[derive(Default)]
 #[must_use]
 pub struct NodeBuilder {
     model: Model,
     event_handler: Option<Arc<dyn EventHandler>>,
+    clock: Option<(String, Arc<dyn ClockSource>)>,
+    persist: Option<PathBuf>,
 }
 
 impl NodeBuilder {
@@ -35,14 +53,15 @@ impl NodeBuilder {
         Self {
             model,
             event_handler: None,
+            clock: None,
+            persist: None,
         }
     }
 
-    pub fn from_resources(resource_bundle: ResourceBundle) -> Self {
-        Self {
-            model: Model::from_resources(resource_bundle),
-            event_handler: None,
-        }
+    /// Create a builder for a node holding a bundle of resources, failing if
+    /// the parents of a source or flow make it its own ancestor.
+    pub fn from_resources(resource_bundle: ResourceBundle) -> nmos_model::Result<Self> {
+        Ok(Self::new(Model::from_resources(resource_bundle)?))
     }
 
     pub fn event_handler<H: EventHandler + 'static>(mut self, event_handler: H) -> Self {
@@ -50,25 +69,115 @@ impl NodeBuilder {
         self
     }
 
+    /// Take resource versions and activation times from a clock source,
+    /// and report its status as the node clock called `name`.
+    pub fn clock<S: Into<String>, C: ClockSource + 'static>(mut self, name: S, clock: C) -> Self {
+        self.clock = Some((name.into(), Arc::new(clock)));
+        self
+    }
+
+    /// Save the model to a file whenever it changes.
+    ///
+    /// If the file already exists, senders and receivers with the same id as
+    /// a saved one, such as those with ids from
+    /// [`derive_id`](nmos_model::resource::derive_id), resume its active
+    /// connection, and the saved receiver connections are reapplied through
+    /// the event handler. Everything else is taken from the builder's model.
+    pub fn persist<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.persist = Some(path.into());
+        self
+    }
+
     pub fn build(self) -> Node {
+        if let Some((_, source)) = &self.clock {
+            clock::set_clock_source(source.clone());
+        }
+
+        // Resume the connections of the saved model, if there is one
+        let mut model = self.model;
+        let restored = match &self.persist {
+            Some(path) if path.exists() => match Model::load(path) {
+                Ok(saved) => {
+                    model.restore(saved);
+                    true
+                }
+                Err(err) => {
+                    error!("Failed to load model from {}: {}", path.display(), err);
+                    false
+                }
+            },
+            _ => false,
+        };
+
         // Wrap model in Arc
-        let model = Arc::new(self.model);
+        let model = Arc::new(model);
 
         // Make service
-        let service = NodeApi::new(model.clone());
+        let service = NodeApi::new(model.clone(), self.event_handler.clone());
 
         Node {
-            _event_handler: self.event_handler,
+            event_handler: self.event_handler,
             model,
             service,
+            clock: self.clock,
+            persist: self.persist,
+            restored,
         }
     }
 }
 
 pub struct Node {
-    _event_handler: Option<Arc<dyn EventHandler>>,
+    event_handler: Option<Arc<dyn EventHandler>>,
     model: Arc<Model>,
     service: NodeApi,
+    clock: Option<(String, Arc<dyn ClockSource>)>,
+    persist: Option<PathBuf>,
+    // Whether connections were restored from the persist file
+    restored: bool,
+}
+
+// Set the kind of a node clock, adding the clock if it is missing
+async fn update_clock(model: &Model, name: &str, kind: ClockKind) {
+    let id = match model.self_id().await {
+        Ok(id) => id,
+        Err(_) => return,
+    };
+
+    let changed = match model.nodes().await.get(&id) {
+        Some(node) => node.clock(name).map(|clock| &clock.kind) != Some(&kind),
+        None => false,
+    };
+
+    if changed {
+        model
+            .update_node(&id, |node| {
+                match node.clocks.iter_mut().find(|clock| clock.name == name) {
+                    Some(clock) => clock.kind = kind,
+                    None => node.clocks.push(Clock {
+                        name: name.to_owned(),
+                        kind,
+                    }),
+                }
+            })
+            .await;
+    }
+}
+
+// Tell the event handler about every enabled receiver connection
+async fn reapply_connections(model: &Model, event_handler: &dyn EventHandler) {
+    let connected: Vec<_> = model
+        .receivers()
+        .await
+        .values()
+        .filter(|receiver| receiver.active.master_enable)
+        .map(|receiver| (receiver.core.id, receiver.active.sender_id))
+        .collect();
+
+    for (receiver_id, sender_id) in connected {
+        event_handler
+            .receiver_subscription(receiver_id, sender_id)
+            .await;
+    }
 }
 
 impl Node {
@@ -76,7 +185,9 @@ impl Node {
         NodeBuilder::new(model)
     }
 
-    pub fn builder_from_resources(resource_bundle: ResourceBundle) -> NodeBuilder {
+    pub fn builder_from_resources(
+        resource_bundle: ResourceBundle,
+    ) -> nmos_model::Result<NodeBuilder> {
         NodeBuilder::from_resources(resource_bundle)
     }
 
@@ -88,6 +199,17 @@ impl Node {
     pub async fn start(self) -> error::Result<()> {
         info!("Starting nmos-rs node");
 
+        // Senders without a manifest are described by the node itself
+        assign_manifest_hrefs(&self.model).await;
+
+        // Devices are controlled through the APIs served by the node
+        assign_control_hrefs(&self.model).await;
+
+        // Connections of a saved model are only known to the model so far
+        if let Some(event_handler) = self.event_handler.as_ref().filter(|_| self.restored) {
+            reapply_connections(&self.model, event_handler.as_ref()).await;
+        }
+
         // Channel for receiving MDNS events
         let (tx, mut rx) = mpsc::unbounded_channel();
 
@@ -130,7 +252,7 @@ impl Node {
         let app = ServiceBuilder::new()
             .layer(
                 CorsLayer::new()
-                    .allow_methods([Method::GET, Method::POST])
+                    .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH])
                     .allow_origin(cors::Any),
             )
             .service(self.service);
@@ -156,9 +278,25 @@ impl Node {
                     }
                 };
 
+                // Register at the newest version the registry supports
+                let api = match RegistrationApi::negotiate_version(&registry) {
+                    Some(api) => api,
+                    None => {
+                        error!("No supported API version offered by {}", registry.url);
+                        continue;
+                    }
+                };
+
                 // Attempt to register
-                match RegistrationApi::register_resources(&client, self.model.clone(), &registry)
-                    .await
+                let mut registered = RegisteredVersions::new();
+                match RegistrationApi::register_resources(
+                    &client,
+                    self.model.clone(),
+                    &registry,
+                    &api,
+                    &mut registered,
+                )
+                .await
                 {
                     Ok(_) => info!("Registration successful"),
                     Err(err) => {
@@ -169,10 +307,15 @@ impl Node {
 
                 // Get heartbeat endpoint from node id
                 let heartbeat_url = {
-                    let nodes = self.model.nodes().await;
-                    let node_id = *nodes.iter().next().unwrap().0;
+                    let node_id = match self.model.self_id().await {
+                        Ok(node_id) => node_id,
+                        Err(err) => {
+                            error!("Failed to find node to heartbeat: {}", err);
+                            continue;
+                        }
+                    };
 
-                    let base = &registry.url.join("v1.0/").unwrap();
+                    let base = &registry.url.join(&format!("{}/", api)).unwrap();
                     base.join(&format!("health/nodes/{}", node_id)).unwrap()
                 };
 
@@ -190,15 +333,75 @@ impl Node {
                             break;
                         }
                     }
+
+                    // Keep the registry in step with clock and connection changes
+                    if let Err(err) = RegistrationApi::update_resources(
+                        &client,
+                        self.model.clone(),
+                        &registry,
+                        &api,
+                        &mut registered,
+                    )
+                    .await
+                    {
+                        error!("Failed to update registry: {}", err);
+                        break;
+                    }
+
                     tokio::time::sleep(Duration::from_secs(5)).await;
                 }
             }
         };
 
+        // Report the status of the clock source in the node
+        let clock_monitor = async {
+            let (name, source) = match &self.clock {
+                Some(clock) => clock,
+                None => return std::future::pending().await,
+            };
+
+            loop {
+                let refresh = source.clone();
+                match tokio::task::spawn_blocking(move || refresh.refresh()).await {
+                    Ok(Err(err)) => error!("Failed to refresh clock status: {}", err),
+                    Err(err) => error!("Clock refresh task failed: {}", err),
+                    Ok(Ok(())) => {}
+                }
+
+                update_clock(&self.model, name, source.kind()).await;
+
+                tokio::time::sleep(CLOCK_REFRESH_INTERVAL).await;
+            }
+        };
+
+        // Save the model after every burst of changes
+        let persistence = async {
+            let path = match &self.persist {
+                Some(path) => path,
+                None => return std::future::pending().await,
+            };
+
+            let mut events = self.model.subscribe();
+            loop {
+                if let Err(err) = self.model.save(path).await {
+                    error!("Failed to save model to {}: {}", path.display(), err);
+                }
+
+                match events.recv().await {
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+                tokio::time::sleep(PERSIST_DELAY).await;
+                while events.try_recv().is_ok() {}
+            }
+        };
+
         tokio::select! {
             _ = mdns_receiver => {}
             _ = http_server => {}
             _ = registration => {}
+            _ = clock_monitor => {}
+            _ = persistence => {}
         };
 
         Ok(())
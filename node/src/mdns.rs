@@ -7,7 +7,7 @@ use std::{
     time::Duration,
 };
 
-use nmos_model::version::APIVersion;
+use nmos_model::version::{is_04::V1_3, APIVersion};
 use reqwest::Url;
 use tokio::sync::mpsc::{self, UnboundedSender};
 use tracing::{error, info};
@@ -29,7 +29,17 @@ pub struct NmosMdnsRegistry {
 }
 
 impl NmosMdnsRegistry {
+    /// Parse a discovered Registration API.
     pub fn parse(discovery: &ServiceDiscovery) -> Option<Self> {
+        Self::parse_api(discovery, "registration")
+    }
+
+    /// Parse a discovered Query API.
+    pub fn parse_query(discovery: &ServiceDiscovery) -> Option<Self> {
+        Self::parse_api(discovery, "query")
+    }
+
+    fn parse_api(discovery: &ServiceDiscovery, api: &str) -> Option<Self> {
         // TXT record required
         let txt = match discovery.txt() {
             Some(txt) => txt,
@@ -57,7 +67,7 @@ impl NmosMdnsRegistry {
             let authority = socket.to_string();
 
             // Build URL
-            let base = format!("{}://{}/x-nmos/registration/", api_proto, authority);
+            let base = format!("{}://{}/x-nmos/{}/", api_proto, authority, api);
 
             let url = match Url::parse(&base) {
                 Ok(url) => url,
@@ -96,6 +106,49 @@ impl NmosMdnsRegistry {
     }
 }
 
+/// TXT records and port of registry services to advertise.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NmosMdnsAdvertisement {
+    pub port: u16,
+    pub api_proto: String,
+    pub api_ver: Vec<APIVersion>,
+    pub api_auth: bool,
+    pub pri: u8,
+}
+
+impl NmosMdnsAdvertisement {
+    fn txt_record(&self) -> TxtRecord {
+        let api_ver: Vec<String> = self.api_ver.iter().map(ToString::to_string).collect();
+
+        let mut txt_record = TxtRecord::new();
+        txt_record.insert("api_proto", &self.api_proto).unwrap();
+        txt_record.insert("api_ver", &api_ver.join(",")).unwrap();
+        txt_record
+            .insert("api_auth", &self.api_auth.to_string())
+            .unwrap();
+        txt_record.insert("pri", &self.pri.to_string()).unwrap();
+
+        txt_record
+    }
+
+    fn service(
+        &self,
+        name: &str,
+        service: NmosMdnsService,
+        tx: &UnboundedSender<NmosMdnsEvent>,
+    ) -> MdnsService {
+        let mut mdns_service = MdnsService::new(ServiceType::new(name, "tcp").unwrap(), self.port);
+
+        mdns_service.set_txt_record(self.txt_record());
+        mdns_service.set_context(Box::new(tx.clone()));
+        mdns_service.set_registered_callback(Box::new(move |r, c| {
+            MdnsContext::register_callback(service, r, &c);
+        }));
+
+        mdns_service
+    }
+}
+
 impl Ord for NmosMdnsRegistry {
     fn cmp(&self, other: &Self) -> Ordering {
         // Order entries by smallest priority
@@ -112,15 +165,17 @@ impl PartialOrd for NmosMdnsRegistry {
 pub struct MdnsContext {
     // Browsers and services
     register_browser: Option<MdnsBrowser>,
+    query_browser: Option<MdnsBrowser>,
     node_service: Option<MdnsService>,
-    _query_service: Option<MdnsService>,
+    registration_services: Vec<MdnsService>,
+    query_service: Option<MdnsService>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum NmosMdnsService {
     Node,
     Registration,
-    _Query,
+    Query,
 }
 
 #[derive(Debug)]
@@ -151,8 +206,8 @@ impl MdnsContext {
             .downcast_ref::<UnboundedSender<NmosMdnsEvent>>()
             .unwrap();
 
-        tx.send(NmosMdnsEvent::Discovery(service, result))
-            .expect("Unable to send MDNS event");
+        // Nobody may be listening any more, in which case polling stops
+        let _ = tx.send(NmosMdnsEvent::Discovery(service, result));
     }
 
     fn register_callback(
@@ -172,8 +227,8 @@ impl MdnsContext {
             .downcast_ref::<UnboundedSender<NmosMdnsEvent>>()
             .unwrap();
 
-        tx.send(NmosMdnsEvent::Registration(service, result))
-            .expect("Unable to send MDNS event");
+        // Nobody may be listening any more, in which case polling stops
+        let _ = tx.send(NmosMdnsEvent::Registration(service, result));
     }
 
     pub fn new(_config: &NmosMdnsConfig, tx: mpsc::UnboundedSender<NmosMdnsEvent>) -> MdnsContext {
@@ -199,8 +254,66 @@ impl MdnsContext {
 
         MdnsContext {
             register_browser: Some(register_browser),
+            query_browser: None,
             node_service: Some(node_service),
-            _query_service: None,
+            registration_services: Vec::new(),
+            query_service: None,
+        }
+    }
+
+    /// Advertise the Registration and Query APIs of a registry.
+    ///
+    /// Registries offering v1.3 are advertised as `_nmos-register._tcp`, and
+    /// those offering earlier versions also under the older
+    /// `_nmos-registration._tcp` name.
+    pub fn registry(
+        advertisement: &NmosMdnsAdvertisement,
+        tx: mpsc::UnboundedSender<NmosMdnsEvent>,
+    ) -> MdnsContext {
+        let mut registration_services = Vec::new();
+
+        if advertisement.api_ver.iter().any(|api| *api >= V1_3) {
+            registration_services.push(advertisement.service(
+                "nmos-register",
+                NmosMdnsService::Registration,
+                &tx,
+            ));
+        }
+
+        if advertisement.api_ver.iter().any(|api| *api < V1_3) {
+            registration_services.push(advertisement.service(
+                "nmos-registration",
+                NmosMdnsService::Registration,
+                &tx,
+            ));
+        }
+
+        let query_service = advertisement.service("nmos-query", NmosMdnsService::Query, &tx);
+
+        MdnsContext {
+            register_browser: None,
+            query_browser: None,
+            node_service: None,
+            registration_services,
+            query_service: Some(query_service),
+        }
+    }
+
+    /// Browse for the Query APIs of registries.
+    pub fn query(tx: mpsc::UnboundedSender<NmosMdnsEvent>) -> MdnsContext {
+        let mut query_browser = MdnsBrowser::new(ServiceType::new("nmos-query", "tcp").unwrap());
+
+        query_browser.set_context(Box::new(tx));
+        query_browser.set_service_discovered_callback(Box::new(|r, c| {
+            Self::on_service_discovered(NmosMdnsService::Query, r, &c);
+        }));
+
+        MdnsContext {
+            register_browser: None,
+            query_browser: Some(query_browser),
+            node_service: None,
+            registration_services: Vec::new(),
+            query_service: None,
         }
     }
 
@@ -215,10 +328,26 @@ impl MdnsContext {
             );
         }
 
+        if let Some(query_browser) = &mut self.query_browser {
+            event_loops.push(
+                query_browser
+                    .browse_services()
+                    .expect("Query event handler"),
+            );
+        }
+
         if let Some(node_service) = &mut self.node_service {
             event_loops.push(node_service.register().unwrap());
         }
 
+        for registration_service in &mut self.registration_services {
+            event_loops.push(registration_service.register().unwrap());
+        }
+
+        if let Some(query_service) = &mut self.query_service {
+            event_loops.push(query_service.register().unwrap());
+        }
+
         MdnsPoller { event_loops }
     }
 }
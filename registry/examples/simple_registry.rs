@@ -0,0 +1,19 @@
+use nmos_registry::Registry;
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+#[tokio::main]
+async fn main() {
+    // Set up logging output
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("Set default subscriber");
+
+    // Create registry
+    let registry = Registry::builder().build();
+
+    if let Err(e) = registry.start().await {
+        println!("Registry error: {:?}", e);
+    }
+}
@@ -0,0 +1,165 @@
+mod paging;
+mod query;
+mod registration;
+mod subscription;
+
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
+use axum::{
+    body::Body, extract::OriginalUri, handler::Handler, http::Request, http::StatusCode,
+    response::Response, routing::get, routing::post, Extension, Json, Router,
+};
+use futures::Future;
+use nmos_model::Model;
+use nmos_node::ServiceError;
+use serde_json::json;
+use tower::Service;
+
+use crate::registrations::Registrations;
+use crate::subscriptions::Subscriptions;
+
+use self::registration::{
+    delete_resource, get_health, get_resource, get_root, get_versions, post_health, post_resource,
+};
+
+pub use query::{matches_query, QueryFilter, QueryParams};
+
+#[derive(Debug, Clone)]
+pub struct RegistryApi {
+    router: Router,
+}
+
+impl RegistryApi {
+    pub fn new(model: Arc<Model>, registrations: Arc<Registrations>) -> Self {
+        let router = Router::new()
+            .route("/", get(|| async { Json(json!(["x-nmos/"])) }))
+            .route(
+                "/x-nmos/",
+                get(|| async { Json(json!(["query/", "registration/"])) }),
+            )
+            .route("/x-nmos/registration/", get(get_versions))
+            .route("/x-nmos/registration/:api/", get(get_root))
+            .route("/x-nmos/registration/:api/resource", post(post_resource))
+            .route(
+                "/x-nmos/registration/:api/resource/:type/:id",
+                get(get_resource).delete(delete_resource),
+            )
+            .route(
+                "/x-nmos/registration/:api/health/nodes/:id",
+                get(get_health).post(post_health),
+            )
+            .merge(query_routes(Some(registrations.clone())))
+            .fallback(fallback_handler.into_service())
+            .layer(Extension(model))
+            .layer(Extension(registrations));
+
+        Self { router }
+    }
+}
+
+/// IS-04 Query API over a model, such as one populated by a registry.
+#[derive(Debug, Clone)]
+pub struct QueryApi {
+    router: Router,
+}
+
+impl QueryApi {
+    /// Query API which renders every resource at the requested version.
+    pub fn new(model: Arc<Model>) -> Self {
+        Self::build(model, None)
+    }
+
+    /// Query API which applies the downgrade rules to the versions resources
+    /// were registered at.
+    pub fn with_registrations(model: Arc<Model>, registrations: Arc<Registrations>) -> Self {
+        Self::build(model, Some(registrations))
+    }
+
+    fn build(model: Arc<Model>, registrations: Option<Arc<Registrations>>) -> Self {
+        let router = Router::new()
+            .route("/", get(|| async { Json(json!(["x-nmos/"])) }))
+            .route("/x-nmos/", get(|| async { Json(json!(["query/"])) }))
+            .merge(query_routes(registrations))
+            .fallback(fallback_handler.into_service())
+            .layer(Extension(model));
+
+        Self { router }
+    }
+}
+
+fn query_routes(registrations: Option<Arc<Registrations>>) -> Router {
+    let subscriptions = Arc::new(Subscriptions::default());
+
+    Router::new()
+        .route("/x-nmos/query/", get(query::get_versions))
+        .route("/x-nmos/query/:api/", get(query::get_root))
+        .route("/x-nmos/query/:api/nodes/", get(query::get_nodes))
+        .route("/x-nmos/query/:api/nodes/:id", get(query::get_node))
+        .route("/x-nmos/query/:api/devices/", get(query::get_devices))
+        .route("/x-nmos/query/:api/devices/:id", get(query::get_device))
+        .route("/x-nmos/query/:api/sources/", get(query::get_sources))
+        .route("/x-nmos/query/:api/sources/:id", get(query::get_source))
+        .route("/x-nmos/query/:api/flows/", get(query::get_flows))
+        .route("/x-nmos/query/:api/flows/:id", get(query::get_flow))
+        .route("/x-nmos/query/:api/senders/", get(query::get_senders))
+        .route("/x-nmos/query/:api/senders/:id", get(query::get_sender))
+        .route("/x-nmos/query/:api/receivers/", get(query::get_receivers))
+        .route("/x-nmos/query/:api/receivers/:id", get(query::get_receiver))
+        .route(
+            "/x-nmos/query/:api/subscriptions",
+            post(subscription::post_subscription),
+        )
+        .route(
+            "/x-nmos/query/:api/subscriptions/",
+            get(subscription::get_subscriptions).post(subscription::post_subscription),
+        )
+        .route(
+            "/x-nmos/query/:api/subscriptions/:id",
+            get(subscription::get_subscription).delete(subscription::delete_subscription),
+        )
+        .route(
+            "/x-nmos/query/:api/subscriptions/:id/ws",
+            get(subscription::get_subscription_ws),
+        )
+        .layer(Extension(subscriptions))
+        .layer(Extension(registrations))
+}
+
+async fn fallback_handler(OriginalUri(uri): OriginalUri) -> ServiceError {
+    ServiceError::new(
+        StatusCode::NOT_FOUND,
+        Some(format!("No such path: {}", uri)),
+    )
+}
+
+impl Service<Request<Body>> for RegistryApi {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.router.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        Box::pin(self.router.call(req))
+    }
+}
+
+impl Service<Request<Body>> for QueryApi {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.router.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        Box::pin(self.router.call(req))
+    }
+}
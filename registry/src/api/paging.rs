@@ -0,0 +1,372 @@
+use std::str::FromStr;
+
+use axum::http::{HeaderMap, HeaderValue, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use nmos_model::index::{Order, TimeIndex};
+use nmos_model::tai::TaiTime;
+use nmos_node::ServiceError;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::query::QueryFilter;
+
+/// Number of results in a page unless the client asks for another limit.
+pub const DEFAULT_LIMIT: usize = 10;
+
+/// Largest page the Query API will return.
+pub const MAX_LIMIT: usize = 100;
+
+/// Paging parameters of a Query API request.
+#[derive(Debug, Clone, Copy)]
+pub struct Paging {
+    pub order: Order,
+    /// Only return resources with a later timestamp.
+    pub since: Option<TaiTime>,
+    /// Only return resources with this or an earlier timestamp.
+    pub until: Option<TaiTime>,
+    pub limit: usize,
+}
+
+impl Paging {
+    pub fn from_query(query: &[(String, String)]) -> Result<Self, ServiceError> {
+        let mut paging = Paging {
+            order: Order::Updated,
+            since: None,
+            until: None,
+            limit: DEFAULT_LIMIT,
+        };
+
+        for (key, value) in query {
+            match key.as_str() {
+                "paging.order" => {
+                    paging.order = match value.as_str() {
+                        "create" => Order::Created,
+                        "update" => Order::Updated,
+                        _ => return Err(bad_paging(key, value)),
+                    }
+                }
+                "paging.since" => {
+                    let since = TaiTime::from_str(value).map_err(|_| bad_paging(key, value))?;
+                    paging.since = Some(since);
+                }
+                "paging.until" => {
+                    let until = TaiTime::from_str(value).map_err(|_| bad_paging(key, value))?;
+                    paging.until = Some(until);
+                }
+                "paging.limit" => {
+                    paging.limit = match value.parse() {
+                        Ok(limit) if limit > 0 => usize::min(limit, MAX_LIMIT),
+                        _ => return Err(bad_paging(key, value)),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match (paging.since, paging.until) {
+            (Some(since), Some(until)) if since > until => Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                Some(String::from("paging.since is after paging.until")),
+            )),
+            _ => Ok(paging),
+        }
+    }
+}
+
+fn bad_paging(key: &str, value: &str) -> ServiceError {
+    ServiceError::new(
+        StatusCode::BAD_REQUEST,
+        Some(format!("Invalid value \"{}\" for {}", value, key)),
+    )
+}
+
+/// A page of query results, most recent first.
+#[derive(Debug)]
+pub struct Page {
+    pub resources: Vec<Value>,
+    pub since: TaiTime,
+    pub until: TaiTime,
+    pub limit: usize,
+}
+
+impl Page {
+    /// Select the resources of an index which match the filter and fall
+    /// within the paging bounds.
+    ///
+    /// Without a `paging.until` but with a `paging.since` the page holds the
+    /// oldest matches after `since`, otherwise it holds the most recent
+    /// matches up to `until`. The bounds of the page are narrowed to the
+    /// resources returned so that the next and previous pages follow on.
+    pub fn select<F>(index: &TimeIndex, paging: &Paging, filter: &QueryFilter, render: F) -> Self
+    where
+        F: Fn(&Uuid) -> Option<Value>,
+    {
+        let now = match index.latest() {
+            Some(latest) => latest.max(TaiTime::now()),
+            None => TaiTime::now(),
+        };
+
+        let range = index.range(paging.order, paging.since, paging.until);
+        let matching = |(time, id): (TaiTime, Uuid)| {
+            render(&id)
+                .filter(|resource| filter.matches(resource))
+                .map(|resource| (time, resource))
+        };
+
+        match (paging.since, paging.until) {
+            (Some(since), None) => {
+                let mut matches = range.filter_map(matching).take(paging.limit + 1);
+                let mut page: Vec<(TaiTime, Value)> = matches.by_ref().take(paging.limit).collect();
+
+                // A truncated page ends at its most recent resource
+                let until = match (matches.next(), page.last()) {
+                    (Some(_), Some((time, _))) => *time,
+                    _ => now,
+                };
+                page.reverse();
+
+                Page {
+                    resources: page.into_iter().map(|(_, resource)| resource).collect(),
+                    since,
+                    until,
+                    limit: paging.limit,
+                }
+            }
+            (since, until) => {
+                let mut matches = range.rev().filter_map(matching).take(paging.limit + 1);
+                let page: Vec<(TaiTime, Value)> = matches.by_ref().take(paging.limit).collect();
+
+                // A truncated page starts after the first resource left out
+                let since = match matches.next() {
+                    Some((time, _)) => time,
+                    None => since.unwrap_or_else(|| TaiTime::new(0, 0)),
+                };
+
+                Page {
+                    resources: page.into_iter().map(|(_, resource)| resource).collect(),
+                    since,
+                    until: until.unwrap_or(now),
+                    limit: paging.limit,
+                }
+            }
+        }
+    }
+
+    /// Respond with the page, its bounds and links to neighbouring pages.
+    pub fn into_response(self, uri: &Uri) -> Response {
+        // Keep all parameters other than the paging bounds in links
+        let base: Vec<&str> = uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|param| {
+                let key = param.split('=').next().unwrap_or_default();
+                !param.is_empty()
+                    && !matches!(key, "paging.since" | "paging.until" | "paging.limit")
+            })
+            .collect();
+
+        let link = |bounds: &[String], rel: &str| {
+            let mut params = base.clone();
+            let limit = format!("paging.limit={}", self.limit);
+            params.extend(bounds.iter().map(String::as_str));
+            params.push(&limit);
+
+            format!("<{}?{}>; rel=\"{}\"", uri.path(), params.join("&"), rel)
+        };
+
+        let links = [
+            link(&[format!("paging.since={}", self.until)], "next"),
+            link(&[format!("paging.until={}", self.since)], "prev"),
+            link(&[String::from("paging.since=0:0")], "first"),
+            link(&[], "last"),
+        ];
+
+        let mut headers = HeaderMap::new();
+        let values = [
+            ("link", links.join(", ")),
+            ("x-paging-limit", self.limit.to_string()),
+            ("x-paging-since", self.since.to_string()),
+            ("x-paging-until", self.until.to_string()),
+        ];
+        for (name, value) in values {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+
+        (headers, Json(self.resources)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nmos_model::resource::ResourceType;
+    use nmos_model::test_support::node;
+    use nmos_model::Model;
+    use serde_json::json;
+
+    use super::*;
+
+    fn paging(query: &[(&str, &str)]) -> Result<Paging, ServiceError> {
+        let query: Vec<_> = query
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Paging::from_query(&query)
+    }
+
+    fn status(query: &[(&str, &str)]) -> StatusCode {
+        paging(query).unwrap_err().into_response().status()
+    }
+
+    // A model holding nodes inserted one after another, oldest first
+    async fn nodes(count: usize) -> (Model, Vec<Uuid>) {
+        let model = Model::new();
+        let mut ids = Vec::new();
+        for _ in 0..count {
+            let node = node();
+            ids.push(node.core.id);
+            model.insert_node(node).await.unwrap();
+        }
+
+        (model, ids)
+    }
+
+    // Select a page of the nodes, returning the ids it holds
+    async fn select(model: &Model, paging: &Paging) -> (Vec<Uuid>, Page) {
+        let index = model.index(ResourceType::Node).await;
+        let page = Page::select(&index, paging, &QueryFilter::default(), |id| {
+            Some(json!({ "id": id }))
+        });
+        let ids = page
+            .resources
+            .iter()
+            .map(|resource| Uuid::from_str(resource["id"].as_str().unwrap()).unwrap())
+            .collect();
+
+        (ids, page)
+    }
+
+    #[test]
+    fn from_query() {
+        let defaults = paging(&[]).unwrap();
+        assert_eq!(defaults.order, Order::Updated);
+        assert_eq!(defaults.since, None);
+        assert_eq!(defaults.until, None);
+        assert_eq!(defaults.limit, DEFAULT_LIMIT);
+
+        let bounded = paging(&[
+            ("paging.order", "create"),
+            ("paging.since", "10:0"),
+            ("paging.until", "10:0"),
+            ("paging.limit", "1000"),
+        ])
+        .unwrap();
+        assert_eq!(bounded.order, Order::Created);
+        assert_eq!(bounded.since, Some(TaiTime::new(10, 0)));
+        assert_eq!(bounded.until, Some(TaiTime::new(10, 0)));
+        assert_eq!(bounded.limit, MAX_LIMIT);
+
+        assert_eq!(
+            status(&[("paging.since", "20:0"), ("paging.until", "10:0")]),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(status(&[("paging.limit", "0")]), StatusCode::BAD_REQUEST);
+        assert_eq!(status(&[("paging.order", "name")]), StatusCode::BAD_REQUEST);
+        assert_eq!(status(&[("paging.since", "soon")]), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn until_bounded_pages() {
+        let (model, ids) = nodes(5).await;
+        let times: Vec<_> = {
+            let index = model.index(ResourceType::Node).await;
+            ids.iter().map(|id| index.updated(id).unwrap()).collect()
+        };
+
+        // The most recent nodes, starting after the first one left out
+        let (page, bounds) = select(&model, &paging(&[("paging.limit", "2")]).unwrap()).await;
+        assert_eq!(page, [ids[4], ids[3]]);
+        assert_eq!(bounds.since, times[2]);
+        assert!(bounds.until >= times[4]);
+
+        let until = times[3].to_string();
+        let since = times[0].to_string();
+        let query = [
+            ("paging.since", since.as_str()),
+            ("paging.until", until.as_str()),
+            ("paging.limit", "2"),
+        ];
+        let (page, bounds) = select(&model, &paging(&query).unwrap()).await;
+        assert_eq!(page, [ids[3], ids[2]]);
+        assert_eq!(bounds.since, times[1]);
+        assert_eq!(bounds.until, times[3]);
+
+        // A limit the matches exactly fill leaves the bounds as requested
+        let (page, bounds) = select(&model, &paging(&[("paging.limit", "5")]).unwrap()).await;
+        assert_eq!(page, [ids[4], ids[3], ids[2], ids[1], ids[0]]);
+        assert_eq!(bounds.since, TaiTime::new(0, 0));
+    }
+
+    #[tokio::test]
+    async fn since_only_pages() {
+        let (model, ids) = nodes(5).await;
+        let times: Vec<_> = {
+            let index = model.index(ResourceType::Node).await;
+            ids.iter().map(|id| index.updated(id).unwrap()).collect()
+        };
+
+        // The oldest nodes after since, ending at the last one returned
+        let since = times[0].to_string();
+        let query = [("paging.since", since.as_str()), ("paging.limit", "2")];
+        let (page, bounds) = select(&model, &paging(&query).unwrap()).await;
+        assert_eq!(page, [ids[2], ids[1]]);
+        assert_eq!(bounds.since, times[0]);
+        assert_eq!(bounds.until, times[2]);
+
+        // The next page follows on from the until of the last
+        let until = bounds.until.to_string();
+        let query = [("paging.since", until.as_str()), ("paging.limit", "2")];
+        let (page, bounds) = select(&model, &paging(&query).unwrap()).await;
+        assert_eq!(page, [ids[4], ids[3]]);
+        assert!(bounds.until >= times[4]);
+
+        // A page which is not truncated ends now
+        let since = times[2].to_string();
+        let query = [("paging.since", since.as_str()), ("paging.limit", "10")];
+        let (page, bounds) = select(&model, &paging(&query).unwrap()).await;
+        assert_eq!(page, [ids[4], ids[3]]);
+        assert!(bounds.until >= times[4]);
+    }
+
+    #[test]
+    fn headers() {
+        let page = Page {
+            resources: Vec::new(),
+            since: TaiTime::new(10, 0),
+            until: TaiTime::new(20, 500),
+            limit: 2,
+        };
+        let uri: Uri = "/x-nmos/query/v1.3/nodes?label=node&paging.limit=5&paging.since=1:0"
+            .parse()
+            .unwrap();
+        let response = page.into_response(&uri);
+        let header = |name| response.headers()[name].to_str().unwrap();
+
+        assert_eq!(header("x-paging-limit"), "2");
+        assert_eq!(header("x-paging-since"), "10:0");
+        assert_eq!(header("x-paging-until"), "20:500");
+        assert_eq!(
+            header("link"),
+            "</x-nmos/query/v1.3/nodes?label=node&paging.since=20:500&paging.limit=2>; \
+             rel=\"next\", \
+             </x-nmos/query/v1.3/nodes?label=node&paging.until=10:0&paging.limit=2>; \
+             rel=\"prev\", \
+             </x-nmos/query/v1.3/nodes?label=node&paging.since=0:0&paging.limit=2>; \
+             rel=\"first\", \
+             </x-nmos/query/v1.3/nodes?label=node&paging.limit=2>; rel=\"last\""
+        );
+    }
+}
@@ -0,0 +1,579 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::extract::{OriginalUri, Path, Query};
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use nmos_model::index::Order;
+use nmos_model::resource::{Resource, ResourceType};
+use nmos_model::rql::{self, RqlError};
+use nmos_model::version::is_04::{V1_0, VERSIONS};
+use nmos_model::version::APIVersion;
+use nmos_model::{Error, Model};
+use nmos_node::{parse_api_version, ServiceError};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::paging::{Page, Paging};
+use crate::registrations::Registrations;
+
+/// Query string parameters, in request order.
+pub type QueryParams = Vec<(String, String)>;
+
+/// Whether a resource matches every basic query filter.
+///
+/// Each filter names an attribute, using dots for nested attributes such as
+/// `caps.media_types`, and matches if the attribute equals the given value.
+/// An array matches if any of its elements does. Parameters reserved for
+/// paging and advanced queries are ignored.
+pub fn matches_query(resource: &Value, query: &[(String, String)]) -> bool {
+    query
+        .iter()
+        .filter(|(key, _)| !key.starts_with("paging.") && !key.starts_with("query."))
+        .all(|(key, expected)| {
+            let path: Vec<&str> = key.split('.').collect();
+            matches_path(resource, &path, expected)
+        })
+}
+
+/// Basic query filters of a request together with any RQL query given by
+/// `query.rql` and the lowest version allowed by `query.downgrade`.
+#[derive(Debug, Clone, Default)]
+pub struct QueryFilter {
+    params: QueryParams,
+    rql: Option<rql::Query>,
+    downgrade: Option<APIVersion>,
+}
+
+impl QueryFilter {
+    pub fn from_query(api: &APIVersion, query: &[(String, String)]) -> Result<Self, ServiceError> {
+        let downgrade = match query.iter().find(|(key, _)| key == "query.downgrade") {
+            Some((_, downgrade)) => {
+                match VERSIONS
+                    .iter()
+                    .find(|version| version.to_string() == *downgrade)
+                {
+                    Some(version) if version <= api => Some(*version),
+                    _ => {
+                        return Err(ServiceError::new(
+                            StatusCode::BAD_REQUEST,
+                            Some(format!("Cannot downgrade {} queries to {}", api, downgrade)),
+                        ))
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let rql = match query.iter().find(|(key, _)| key == "query.rql") {
+            Some((_, rql)) => match rql::parse(rql) {
+                Ok(rql) => Some(rql),
+                Err(err @ RqlError::UnsupportedOperator(_)) => {
+                    return Err(ServiceError::new(
+                        StatusCode::NOT_IMPLEMENTED,
+                        Some(err.to_string()),
+                    ))
+                }
+                Err(err) => {
+                    return Err(ServiceError::new(
+                        StatusCode::BAD_REQUEST,
+                        Some(err.to_string()),
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        Ok(Self {
+            params: query.to_vec(),
+            rql,
+            downgrade,
+        })
+    }
+
+    /// Version to render a resource registered at `registered` in a query at
+    /// `api`, or `None` if the resource is excluded from the query.
+    ///
+    /// Resources registered at a later version are downgraded to the query
+    /// version, which drops the attributes it does not define. Resources
+    /// registered at an earlier version keep their own rendering, and are only
+    /// returned down to the version given by `query.downgrade`. Resources
+    /// which were not registered, such as those of a node, are always
+    /// rendered at the query version.
+    #[must_use]
+    pub fn version(&self, api: &APIVersion, registered: Option<APIVersion>) -> Option<APIVersion> {
+        match registered {
+            None => Some(*api),
+            Some(registered) if registered.major != api.major => None,
+            Some(registered) if registered >= *api => Some(*api),
+            Some(registered) => match self.downgrade {
+                Some(downgrade) if downgrade <= registered => Some(registered),
+                _ => None,
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn matches(&self, resource: &Value) -> bool {
+        matches_query(resource, &self.params)
+            && self.rql.as_ref().map_or(true, |rql| rql.matches(resource))
+    }
+}
+
+fn matches_path(value: &Value, path: &[&str], expected: &str) -> bool {
+    match (value, path.split_first()) {
+        (Value::Array(values), _) => values
+            .iter()
+            .any(|value| matches_path(value, path, expected)),
+        (Value::Object(object), Some((key, rest))) => object
+            .get(*key)
+            .map_or(false, |value| matches_path(value, rest, expected)),
+        (Value::String(s), None) => s == expected,
+        (Value::Number(n), None) => n.to_string() == expected,
+        (Value::Bool(b), None) => b.to_string() == expected,
+        (Value::Null, None) => expected == "null",
+        _ => false,
+    }
+}
+
+/// Version a resource was registered at, if it is registered.
+pub(super) async fn registered_version(
+    registrations: Option<&Registrations>,
+    id: &Uuid,
+) -> Option<APIVersion> {
+    match registrations {
+        Some(registrations) => registrations.get(id).await.map(|r| r.api),
+        None => None,
+    }
+}
+
+// List the matching resources of a type, paged from v1.1 onwards
+async fn list<R: Resource>(
+    resources: &HashMap<Uuid, R>,
+    model: &Model,
+    registrations: Option<&Registrations>,
+    api: &APIVersion,
+    query: &[(String, String)],
+    uri: &Uri,
+) -> Result<Response, ServiceError> {
+    let filter = QueryFilter::from_query(api, query)?;
+
+    let index = model.index(R::RESOURCE_TYPE).await;
+    let registered = match registrations {
+        Some(registrations) => Some(registrations.resources().await),
+        None => None,
+    };
+
+    let render = |id: &Uuid| {
+        let registered = registered
+            .as_ref()
+            .and_then(|registered| registered.get(id))
+            .map(|r| r.api);
+
+        resources
+            .get(id)?
+            .render(&filter.version(api, registered)?)
+            .ok()
+    };
+
+    if *api == V1_0 {
+        let resources: Vec<Value> = index
+            .range(Order::Updated, None, None)
+            .rev()
+            .filter_map(|(_, id)| render(&id))
+            .filter(|resource| filter.matches(resource))
+            .collect();
+
+        return Ok(Json(resources).into_response());
+    }
+
+    let paging = Paging::from_query(query)?;
+
+    Ok(Page::select(&index, &paging, &filter, render).into_response(uri))
+}
+
+// A single resource, if the downgrade rules allow it at the query version
+async fn get_resource(
+    model: &Model,
+    registrations: Option<&Registrations>,
+    type_: ResourceType,
+    api: &APIVersion,
+    query: &[(String, String)],
+    id: &Uuid,
+) -> Result<Json<Value>, ServiceError> {
+    let filter = QueryFilter::from_query(api, query)?;
+
+    let registered = registered_version(registrations, id).await;
+    match filter.version(api, registered) {
+        Some(version) => Ok(Json(model.render(type_, &version, id).await?)),
+        None => Err(Error::NotFound(type_, *id).into()),
+    }
+}
+
+pub async fn get_versions() -> Json<Value> {
+    let versions: Vec<_> = VERSIONS.iter().map(|api| format!("{}/", api)).collect();
+
+    Json(json!(versions))
+}
+
+pub async fn get_root(Path(api): Path<String>) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    Ok(Json(json!([
+        "devices/",
+        "flows/",
+        "nodes/",
+        "receivers/",
+        "senders/",
+        "sources/",
+        "subscriptions/"
+    ])))
+}
+
+pub async fn get_nodes(
+    Path(api): Path<String>,
+    Query(query): Query<QueryParams>,
+    OriginalUri(uri): OriginalUri,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Response, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    let nodes = model.nodes().await;
+
+    list(
+        &*nodes,
+        &model,
+        registrations.as_deref(),
+        &api,
+        &query,
+        &uri,
+    )
+    .await
+}
+
+pub async fn get_node(
+    Path((api, id)): Path<(String, Uuid)>,
+    Query(query): Query<QueryParams>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    get_resource(
+        &model,
+        registrations.as_deref(),
+        ResourceType::Node,
+        &api,
+        &query,
+        &id,
+    )
+    .await
+}
+
+pub async fn get_devices(
+    Path(api): Path<String>,
+    Query(query): Query<QueryParams>,
+    OriginalUri(uri): OriginalUri,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Response, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    let devices = model.devices().await;
+
+    list(
+        &*devices,
+        &model,
+        registrations.as_deref(),
+        &api,
+        &query,
+        &uri,
+    )
+    .await
+}
+
+pub async fn get_device(
+    Path((api, id)): Path<(String, Uuid)>,
+    Query(query): Query<QueryParams>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    get_resource(
+        &model,
+        registrations.as_deref(),
+        ResourceType::Device,
+        &api,
+        &query,
+        &id,
+    )
+    .await
+}
+
+pub async fn get_sources(
+    Path(api): Path<String>,
+    Query(query): Query<QueryParams>,
+    OriginalUri(uri): OriginalUri,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Response, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    let sources = model.sources().await;
+
+    list(
+        &*sources,
+        &model,
+        registrations.as_deref(),
+        &api,
+        &query,
+        &uri,
+    )
+    .await
+}
+
+pub async fn get_source(
+    Path((api, id)): Path<(String, Uuid)>,
+    Query(query): Query<QueryParams>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    get_resource(
+        &model,
+        registrations.as_deref(),
+        ResourceType::Source,
+        &api,
+        &query,
+        &id,
+    )
+    .await
+}
+
+pub async fn get_flows(
+    Path(api): Path<String>,
+    Query(query): Query<QueryParams>,
+    OriginalUri(uri): OriginalUri,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Response, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    let flows = model.flows().await;
+
+    list(
+        &*flows,
+        &model,
+        registrations.as_deref(),
+        &api,
+        &query,
+        &uri,
+    )
+    .await
+}
+
+pub async fn get_flow(
+    Path((api, id)): Path<(String, Uuid)>,
+    Query(query): Query<QueryParams>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    get_resource(
+        &model,
+        registrations.as_deref(),
+        ResourceType::Flow,
+        &api,
+        &query,
+        &id,
+    )
+    .await
+}
+
+pub async fn get_senders(
+    Path(api): Path<String>,
+    Query(query): Query<QueryParams>,
+    OriginalUri(uri): OriginalUri,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Response, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    let senders = model.senders().await;
+
+    list(
+        &*senders,
+        &model,
+        registrations.as_deref(),
+        &api,
+        &query,
+        &uri,
+    )
+    .await
+}
+
+pub async fn get_sender(
+    Path((api, id)): Path<(String, Uuid)>,
+    Query(query): Query<QueryParams>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    get_resource(
+        &model,
+        registrations.as_deref(),
+        ResourceType::Sender,
+        &api,
+        &query,
+        &id,
+    )
+    .await
+}
+
+pub async fn get_receivers(
+    Path(api): Path<String>,
+    Query(query): Query<QueryParams>,
+    OriginalUri(uri): OriginalUri,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Response, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    let receivers = model.receivers().await;
+
+    list(
+        &*receivers,
+        &model,
+        registrations.as_deref(),
+        &api,
+        &query,
+        &uri,
+    )
+    .await
+}
+
+pub async fn get_receiver(
+    Path((api, id)): Path<(String, Uuid)>,
+    Query(query): Query<QueryParams>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Json<Value>, ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    get_resource(
+        &model,
+        registrations.as_deref(),
+        ResourceType::Receiver,
+        &api,
+        &query,
+        &id,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use nmos_model::resource::{Device, ResourceBundle};
+    use nmos_model::test_support::node;
+    use nmos_model::version::is_04::{V1_2, V1_3};
+
+    use super::*;
+
+    fn filter(query: &[(&str, &str)]) -> Result<QueryFilter, ServiceError> {
+        let query: QueryParams = query
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        QueryFilter::from_query(&V1_3, &query)
+    }
+
+    fn status(query: &[(&str, &str)]) -> StatusCode {
+        match filter(query) {
+            Ok(_) => StatusCode::OK,
+            Err(err) => err.into_response().status(),
+        }
+    }
+
+    #[test]
+    fn rql_and_basic_queries() {
+        let resource = json!({
+            "label": "Camera 1",
+            "format": "urn:x-nmos:format:video",
+            "caps": { "media_types": ["video/raw"] },
+        });
+
+        let filter = filter(&[
+            ("label", "Camera 1"),
+            (
+                "query.rql",
+                "or(eq(format,urn:x-nmos:format:audio),eq(caps.media_types,video/raw))",
+            ),
+        ])
+        .unwrap();
+        assert!(filter.matches(&resource));
+        assert!(
+            !filter.matches(&json!({ "label": "Camera 2", "format": "urn:x-nmos:format:video" }))
+        );
+    }
+
+    #[test]
+    fn malformed_queries() {
+        assert_eq!(
+            status(&[("query.rql", "eq(label,Camera 1)")]),
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(&[("query.rql", "eq(label")]),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(&[("query.rql", "eq(label)")]),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(&[("query.rql", "matches(label,()")]),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(&[("query.rql", "sort(label)")]),
+            StatusCode::NOT_IMPLEMENTED
+        );
+        assert_eq!(
+            status(&[("query.downgrade", "v1.4")]),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn resource_not_allowed_in_version() {
+        let node = node();
+        let device_type = "urn:x-acme:device:widget".parse().unwrap();
+        let device = Device::builder("device", &node, device_type).build();
+        let id = device.core.id;
+
+        let mut resources = ResourceBundle::new();
+        resources.insert_node(node);
+        resources.insert_device(device);
+        let model = Model::from_resources(resources).unwrap();
+
+        let get = |api| get_resource(&model, None, ResourceType::Device, api, &[], &id);
+        assert!(get(&V1_3).await.is_ok());
+
+        let status = get(&V1_0).await.unwrap_err().into_response().status();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn downgrade() {
+        let filter = filter(&[("query.downgrade", "v1.2")]).unwrap();
+        assert_eq!(filter.version(&V1_3, Some(V1_2)), Some(V1_2));
+        assert_eq!(filter.version(&V1_3, Some(V1_0)), None);
+        assert_eq!(filter.version(&V1_2, Some(V1_3)), Some(V1_2));
+    }
+}
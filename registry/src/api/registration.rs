@@ -0,0 +1,287 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::Path;
+use axum::http::{header, StatusCode};
+use axum::{Extension, Json};
+use nmos_model::resource::{
+    Device, Flow, JsonError, Node, Receiver, Resource, ResourceType, Sender, Source,
+};
+use nmos_model::version::is_04::VERSIONS;
+use nmos_model::version::APIVersion;
+use nmos_model::{Error, Model};
+use nmos_node::{parse_api_version, ServiceError};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::registrations::{Registration, Registrations};
+
+fn bad_request<E: std::error::Error>(err: E) -> ServiceError {
+    ServiceError::new(StatusCode::BAD_REQUEST, Some(err.to_string()))
+}
+
+pub async fn get_versions() -> Json<Value> {
+    let versions: Vec<_> = VERSIONS.iter().map(|api| format!("{}/", api)).collect();
+
+    Json(json!(versions))
+}
+
+pub async fn get_root(Path(api): Path<String>) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    Ok(Json(json!(["health/", "resource/"])))
+}
+
+pub async fn post_resource(
+    Path(api): Path<String>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Arc<Registrations>>,
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<(StatusCode, [(header::HeaderName, String); 1], Json<Value>), ServiceError> {
+    let api = parse_api_version(&api, VERSIONS)?;
+
+    let body = match body {
+        Ok(Json(body)) => body,
+        Err(err) => {
+            return Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                Some(err.to_string()),
+            ))
+        }
+    };
+
+    let type_ = match body
+        .get("type")
+        .and_then(Value::as_str)
+        .and_then(ResourceType::from_name)
+    {
+        Some(type_) => type_,
+        None => {
+            return Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                Some(String::from("Registration has no valid resource type")),
+            ))
+        }
+    };
+
+    let data = match body.get("data") {
+        Some(data) if data.is_object() => data.clone(),
+        _ => {
+            return Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                Some(String::from("Registration is missing resource data")),
+            ))
+        }
+    };
+
+    let id = match data.get("id").and_then(Value::as_str).map(Uuid::from_str) {
+        Some(Ok(id)) => id,
+        _ => {
+            return Err(ServiceError::new(
+                StatusCode::BAD_REQUEST,
+                Some(String::from("Resource is missing a valid id")),
+            ))
+        }
+    };
+
+    // A resource cannot change type or API version while registered
+    let created = match registrations.get(&id).await {
+        Some(registration) if registration.type_ != type_ || registration.api != api => {
+            return Err(ServiceError::new(
+                StatusCode::CONFLICT,
+                Some(format!(
+                    "Resource {} is already registered as a {} {}",
+                    id, registration.api, registration.type_
+                )),
+            ))
+        }
+        Some(_) => false,
+        None => true,
+    };
+
+    // Register first so that the resource is never seen at the wrong version,
+    // and forget it again if it cannot be inserted
+    if created {
+        registrations.insert(id, Registration { type_, api }).await;
+    }
+    if let Err(err) = insert_resource(&model, type_, &api, &data).await {
+        if created {
+            registrations.remove(&id).await;
+        }
+        return Err(err);
+    }
+    if !created && type_ == ResourceType::Node {
+        registrations.heartbeat(&id).await;
+    }
+
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    let location = format!("/x-nmos/registration/{}/resource/{}s/{}", api, type_, id);
+
+    Ok((status, [(header::LOCATION, location)], Json(data)))
+}
+
+// Parse a resource which must be valid at the API version it is registered with
+fn parse<R: Resource>(
+    from_json: fn(&Value, &APIVersion) -> Result<R, JsonError>,
+    api: &APIVersion,
+    data: &Value,
+) -> Result<R, ServiceError> {
+    let resource = from_json(data, api).map_err(bad_request)?;
+    resource.render(api).map_err(bad_request)?;
+
+    Ok(resource)
+}
+
+// Insert a resource whose parent must already be registered
+async fn insert_resource(
+    model: &Model,
+    type_: ResourceType,
+    api: &APIVersion,
+    data: &Value,
+) -> Result<(), ServiceError> {
+    let inserted = match type_ {
+        ResourceType::Node => {
+            let node = parse(Node::from_json, api, data)?;
+            model.insert_node(node).await
+        }
+        ResourceType::Device => {
+            let device = parse(Device::from_json, api, data)?;
+            model.insert_device(device).await
+        }
+        ResourceType::Source => {
+            let source = parse(Source::from_json, api, data)?;
+            model.insert_source(source).await
+        }
+        ResourceType::Flow => {
+            let mut flow = parse(Flow::from_json, api, data)?;
+
+            // v1.0 flows are placed on the device of their source
+            if flow.device_id.is_nil() {
+                let device_id = model
+                    .sources()
+                    .await
+                    .get(&flow.source_id)
+                    .map(|source| source.device_id);
+
+                flow.device_id = device_id.unwrap_or_default();
+            }
+
+            model.insert_flow(flow).await
+        }
+        ResourceType::Sender => {
+            let sender = parse(Sender::from_json, api, data)?;
+            model.insert_sender(sender).await
+        }
+        ResourceType::Receiver => {
+            let receiver = parse(Receiver::from_json, api, data)?;
+            model.insert_receiver(receiver).await
+        }
+    };
+
+    inserted.map_err(ServiceError::from)
+}
+
+pub async fn get_resource(
+    Path((api, type_, id)): Path<(String, String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Arc<Registrations>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    let registration = lookup(&registrations, &type_, &id).await?;
+
+    let rendered = model
+        .render(registration.type_, &registration.api, &id)
+        .await;
+
+    match rendered {
+        Ok(json) => Ok(Json(json)),
+        Err(Error::NotFound(..)) => Err(not_found(&type_, &id)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub async fn delete_resource(
+    Path((api, type_, id)): Path<(String, String, Uuid)>,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(registrations): Extension<Arc<Registrations>>,
+) -> Result<StatusCode, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    let registration = lookup(&registrations, &type_, &id).await?;
+
+    // Children of the resource are removed with it
+    let removed = match registration.type_ {
+        ResourceType::Node => model.remove_node(&id).await,
+        ResourceType::Device => model.remove_device(&id).await,
+        ResourceType::Source => model.remove_source(&id).await,
+        ResourceType::Flow => model.remove_flow(&id).await,
+        ResourceType::Sender => model.remove_sender(&id).await,
+        ResourceType::Receiver => model.remove_receiver(&id).await,
+    };
+    registrations.prune(&model).await;
+
+    match removed {
+        Some(()) => Ok(StatusCode::NO_CONTENT),
+        None => Err(not_found(&type_, &id)),
+    }
+}
+
+pub async fn post_health(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(registrations): Extension<Arc<Registrations>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    lookup(&registrations, "nodes", &id).await?;
+    registrations.heartbeat(&id).await;
+
+    Ok(Json(health_json()))
+}
+
+pub async fn get_health(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(registrations): Extension<Arc<Registrations>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    lookup(&registrations, "nodes", &id).await?;
+
+    Ok(Json(health_json()))
+}
+
+fn health_json() -> Value {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before Unix epoch");
+
+    json!({ "health": now.as_secs().to_string() })
+}
+
+// Registration of a resource, which must have the type in the request path
+async fn lookup(
+    registrations: &Registrations,
+    type_: &str,
+    id: &Uuid,
+) -> Result<Registration, ServiceError> {
+    match (
+        ResourceType::from_plural(type_),
+        registrations.get(id).await,
+    ) {
+        (Some(expected), Some(registration)) if registration.type_ == expected => Ok(registration),
+        _ => Err(not_found(type_, id)),
+    }
+}
+
+fn not_found(type_: &str, id: &Uuid) -> ServiceError {
+    ServiceError::new(
+        StatusCode::NOT_FOUND,
+        Some(format!("No {} registered with id {}", type_, id)),
+    )
+}
@@ -0,0 +1,379 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::Path;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::{Extension, Json};
+use nmos_model::index::Order;
+use nmos_model::resource::ResourceType;
+use nmos_model::tai::TaiTime;
+use nmos_model::version::is_04::VERSIONS;
+use nmos_model::Model;
+use nmos_node::{parse_api_version, ServiceError};
+use serde_json::{json, Value};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{self, Instant};
+use uuid::Uuid;
+
+use super::query::{registered_version, QueryFilter};
+use crate::registrations::Registrations;
+use crate::subscriptions::{Subscription, Subscriptions, DEFAULT_MAX_UPDATE_RATE_MS};
+
+fn bad_request<S: Into<String>>(msg: S) -> ServiceError {
+    ServiceError::new(StatusCode::BAD_REQUEST, Some(msg.into()))
+}
+
+fn not_found(id: &Uuid) -> ServiceError {
+    ServiceError::new(
+        StatusCode::NOT_FOUND,
+        Some(format!("Subscription {} does not exist", id)),
+    )
+}
+
+pub async fn post_subscription(
+    Path(api_str): Path<String>,
+    headers: HeaderMap,
+    Extension(subscriptions): Extension<Arc<Subscriptions>>,
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<(StatusCode, [(header::HeaderName, String); 1], Json<Value>), ServiceError> {
+    let api = parse_api_version(&api_str, VERSIONS)?;
+
+    let body = match body {
+        Ok(Json(body)) => body,
+        Err(err) => return Err(bad_request(err.to_string())),
+    };
+
+    let resource_type = match body.get("resource_path").and_then(Value::as_str) {
+        Some(path) => match path.strip_prefix('/').and_then(ResourceType::from_plural) {
+            Some(resource_type) => resource_type,
+            None => return Err(bad_request(format!("Unsupported resource_path {}", path))),
+        },
+        None => return Err(bad_request("Subscription is missing resource_path")),
+    };
+
+    let params = match body.get("params") {
+        Some(Value::Object(params)) => params
+            .iter()
+            .map(|(key, value)| match value {
+                Value::String(value) => (key.clone(), value.clone()),
+                value => (key.clone(), value.to_string()),
+            })
+            .collect(),
+        Some(_) => return Err(bad_request("Subscription params must be an object")),
+        None => Vec::new(),
+    };
+
+    let max_update_rate_ms = match body.get("max_update_rate_ms") {
+        Some(rate) => match rate.as_u64() {
+            Some(rate) => rate,
+            None => return Err(bad_request("Invalid max_update_rate_ms")),
+        },
+        None => DEFAULT_MAX_UPDATE_RATE_MS,
+    };
+
+    // Reject filters the WebSocket would be unable to apply
+    QueryFilter::from_query(&api, &params)?;
+
+    let flag = |name: &str| match body.get(name) {
+        Some(value) => value
+            .as_bool()
+            .ok_or_else(|| bad_request(format!("Invalid {}", name))),
+        None => Ok(false),
+    };
+    let persist = flag("persist")?;
+    let secure = flag("secure")?;
+    let authorization = flag("authorization")?;
+
+    if secure || authorization {
+        return Err(bad_request(
+            "Secure and authorized subscriptions are not supported",
+        ));
+    }
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost");
+
+    let id = Uuid::new_v4();
+    let subscription = Subscription {
+        id,
+        api,
+        resource_type,
+        params,
+        max_update_rate_ms,
+        persist,
+        secure,
+        authorization,
+        ws_href: format!("ws://{}/x-nmos/query/{}/subscriptions/{}/ws", host, api, id),
+    };
+
+    subscriptions.prune().await;
+    let (subscription, created) = subscriptions.insert(subscription).await;
+
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    let location = format!("/x-nmos/query/{}/subscriptions/{}", api, subscription.id);
+
+    Ok((
+        status,
+        [(header::LOCATION, location)],
+        Json(subscription.to_json()),
+    ))
+}
+
+pub async fn get_subscriptions(
+    Path(api): Path<String>,
+    Extension(subscriptions): Extension<Arc<Subscriptions>>,
+) -> Result<Json<Vec<Value>>, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    subscriptions.prune().await;
+    let subscriptions = subscriptions.list().await;
+
+    Ok(Json(
+        subscriptions.iter().map(Subscription::to_json).collect(),
+    ))
+}
+
+pub async fn get_subscription(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(subscriptions): Extension<Arc<Subscriptions>>,
+) -> Result<Json<Value>, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    match subscriptions.get(&id).await {
+        Some(subscription) => Ok(Json(subscription.to_json())),
+        None => Err(not_found(&id)),
+    }
+}
+
+pub async fn delete_subscription(
+    Path((api, id)): Path<(String, Uuid)>,
+    Extension(subscriptions): Extension<Arc<Subscriptions>>,
+) -> Result<StatusCode, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    // Non-persistent subscriptions go away with their last client
+    match subscriptions.get(&id).await {
+        Some(subscription) if subscription.persist => {
+            subscriptions.remove(&id).await;
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Some(_) => Err(ServiceError::new(
+            StatusCode::FORBIDDEN,
+            Some(String::from(
+                "Non-persistent subscriptions cannot be deleted",
+            )),
+        )),
+        None => Err(not_found(&id)),
+    }
+}
+
+pub async fn get_subscription_ws(
+    Path((api, id)): Path<(String, Uuid)>,
+    ws: WebSocketUpgrade,
+    Extension(model): Extension<Arc<Model>>,
+    Extension(subscriptions): Extension<Arc<Subscriptions>>,
+    Extension(registrations): Extension<Option<Arc<Registrations>>>,
+) -> Result<Response, ServiceError> {
+    parse_api_version(&api, VERSIONS)?;
+
+    let subscription = match subscriptions.get(&id).await {
+        Some(subscription) => subscription,
+        None => return Err(not_found(&id)),
+    };
+
+    Ok(ws.on_upgrade(move |socket| async move {
+        if subscriptions.connect(&id).await.is_some() {
+            let source_id = subscriptions.source_id();
+            let registrations = registrations.as_deref();
+            stream_grains(socket, &subscription, &model, registrations, source_id).await;
+            subscriptions.disconnect(&id).await;
+        }
+    }))
+}
+
+// Send a sync grain of all matching resources, then a data grain for each
+// batch of changes, no more often than the subscription allows
+async fn stream_grains(
+    mut socket: WebSocket,
+    subscription: &Subscription,
+    model: &Model,
+    registrations: Option<&Registrations>,
+    source_id: Uuid,
+) {
+    let filter = match QueryFilter::from_query(&subscription.api, &subscription.params) {
+        Ok(filter) => filter,
+        Err(_) => return,
+    };
+
+    // Subscribe before the snapshot so that no change is missed
+    let mut events = model.subscribe();
+
+    let mut sent = current_resources(subscription, &filter, model, registrations).await;
+    let sync: Vec<Value> = sent
+        .iter()
+        .map(|(id, resource)| json!({ "path": id.to_string(), "pre": resource, "post": resource }))
+        .collect();
+
+    if send_grain(&mut socket, subscription, source_id, sync)
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let rate = Duration::from_millis(subscription.max_update_rate_ms);
+    let mut next_grain = Instant::now();
+    let mut pending = HashSet::new();
+    let mut resync = false;
+
+    loop {
+        let ready = resync || !pending.is_empty();
+
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            event = events.recv() => match event {
+                Ok(event) if event.resource_type() == subscription.resource_type => {
+                    pending.insert(event.id());
+                }
+                Ok(_) => {}
+                // Changes were dropped, so compare everything
+                Err(RecvError::Lagged(_)) => resync = true,
+                Err(RecvError::Closed) => break,
+            },
+            _ = time::sleep_until(next_grain), if ready => {
+                let data = if resync {
+                    let current = current_resources(subscription, &filter, model, registrations).await;
+                    let ids: HashSet<Uuid> = sent.keys().chain(current.keys()).copied().collect();
+
+                    let mut current = current;
+                    ids.into_iter()
+                        .filter_map(|id| diff(&mut sent, id, current.remove(&id)))
+                        .collect()
+                } else {
+                    let mut data = Vec::new();
+                    for id in pending.iter() {
+                        let current = render(subscription, &filter, model, registrations, id).await;
+
+                        data.extend(diff(&mut sent, *id, current));
+                    }
+                    data
+                };
+
+                pending.clear();
+                resync = false;
+                next_grain = Instant::now() + rate;
+
+                if !data.is_empty()
+                    && send_grain(&mut socket, subscription, source_id, data)
+                        .await
+                        .is_err()
+                {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn current_resources(
+    subscription: &Subscription,
+    filter: &QueryFilter,
+    model: &Model,
+    registrations: Option<&Registrations>,
+) -> HashMap<Uuid, Value> {
+    let ids: Vec<Uuid> = model
+        .index(subscription.resource_type)
+        .await
+        .range(Order::Updated, None, None)
+        .map(|(_, id)| id)
+        .collect();
+
+    let mut resources = HashMap::new();
+    for id in ids {
+        if let Some(resource) = render(subscription, filter, model, registrations, &id).await {
+            resources.insert(id, resource);
+        }
+    }
+
+    resources
+}
+
+// Render a resource as the subscription sees it, if it matches
+async fn render(
+    subscription: &Subscription,
+    filter: &QueryFilter,
+    model: &Model,
+    registrations: Option<&Registrations>,
+    id: &Uuid,
+) -> Option<Value> {
+    let registered = registered_version(registrations, id).await;
+    let version = filter.version(&subscription.api, registered)?;
+
+    model
+        .render(subscription.resource_type, &version, id)
+        .await
+        .ok()
+        .filter(|resource| filter.matches(resource))
+}
+
+// Event for a resource whose state differs from what the client last saw
+fn diff(sent: &mut HashMap<Uuid, Value>, id: Uuid, current: Option<Value>) -> Option<Value> {
+    let pre = sent.remove(&id);
+    if pre == current {
+        if let Some(resource) = pre {
+            sent.insert(id, resource);
+        }
+        return None;
+    }
+
+    let mut event = json!({ "path": id.to_string() });
+    if let Some(pre) = pre {
+        event["pre"] = pre;
+    }
+    if let Some(post) = current {
+        event["post"] = post.clone();
+        sent.insert(id, post);
+    }
+
+    Some(event)
+}
+
+async fn send_grain(
+    socket: &mut WebSocket,
+    subscription: &Subscription,
+    source_id: Uuid,
+    data: Vec<Value>,
+) -> Result<(), axum::Error> {
+    let now = TaiTime::now().to_string();
+
+    let grain = json!({
+        "grain_type": "event",
+        "source_id": source_id.to_string(),
+        "flow_id": subscription.id.to_string(),
+        "origin_timestamp": now,
+        "sync_timestamp": now,
+        "creation_timestamp": now,
+        "rate": { "numerator": 0, "denominator": 1 },
+        "duration": { "numerator": 0, "denominator": 1 },
+        "grain": {
+            "type": "urn:x-nmos:format:data.event",
+            "topic": format!("{}/", subscription.resource_path()),
+            "data": data,
+        },
+    });
+
+    socket.send(Message::Text(grain.to_string())).await
+}
@@ -0,0 +1,190 @@
+use std::{sync::Arc, thread, time::Duration};
+
+use axum::{
+    http::{header, HeaderName, Method},
+    Server,
+};
+use nmos_model::{version::is_04::VERSIONS, Model};
+use nmos_node::{MdnsContext, NmosError, NmosMdnsAdvertisement};
+use tokio::{runtime::Runtime, sync::mpsc};
+use tower::{make::Shared, ServiceBuilder};
+use tower_http::cors::{self, CorsLayer};
+use tracing::{error, info};
+
+mod api;
+mod registrations;
+mod subscriptions;
+
+pub use registrations::{Registration, Registrations, HEALTH_TIMEOUT};
+
+pub use api::{matches_query, QueryApi, QueryFilter, QueryParams};
+pub use subscriptions::{
+    Subscription, Subscriptions, DEFAULT_MAX_UPDATE_RATE_MS, SUBSCRIPTION_TIMEOUT,
+};
+
+use api::RegistryApi;
+
+/// Port of the Registration API unless configured otherwise.
+pub const DEFAULT_PORT: u16 = 8010;
+
+/// mDNS priority unless configured otherwise, which is the highest priority
+/// reserved for development registries.
+pub const DEFAULT_PRIORITY: u8 = 100;
+
+#[must_use]
+pub struct RegistryBuilder {
+    model: Model,
+    port: u16,
+    priority: u8,
+}
+
+impl Default for RegistryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegistryBuilder {
+    pub fn new() -> Self {
+        Self {
+            model: Model::new(),
+            port: DEFAULT_PORT,
+            priority: DEFAULT_PRIORITY,
+        }
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// mDNS priority, where lower values are preferred by nodes.
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn build(self) -> Registry {
+        // Wrap model in Arc
+        let model = Arc::new(self.model);
+        let registrations = Arc::new(Registrations::default());
+
+        // Make service
+        let service = RegistryApi::new(model.clone(), registrations.clone());
+
+        Registry {
+            model,
+            registrations,
+            service,
+            port: self.port,
+            priority: self.priority,
+        }
+    }
+}
+
+pub struct Registry {
+    model: Arc<Model>,
+    registrations: Arc<Registrations>,
+    service: RegistryApi,
+    port: u16,
+    priority: u8,
+}
+
+impl Registry {
+    pub fn builder() -> RegistryBuilder {
+        RegistryBuilder::new()
+    }
+
+    /// Resources currently registered.
+    #[must_use]
+    pub fn model(&self) -> Arc<Model> {
+        self.model.clone()
+    }
+
+    pub async fn start(self) -> Result<(), NmosError> {
+        info!("Starting nmos-rs registry");
+
+        // Channel for receiving MDNS events
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let advertisement = NmosMdnsAdvertisement {
+            port: self.port,
+            api_proto: String::from("http"),
+            api_ver: VERSIONS.to_vec(),
+            api_auth: false,
+            pri: self.priority,
+        };
+
+        // MDNS must run on its own thread
+        thread::spawn(move || {
+            let mut context = MdnsContext::registry(&advertisement, tx.clone());
+            let poller = context.start();
+
+            loop {
+                // Check event channel is still valid
+                if tx.is_closed() {
+                    break;
+                }
+
+                // Poll every 100 ms
+                poller.poll();
+                thread::sleep(Duration::from_millis(100));
+            }
+        });
+
+        // Registrations of the services are logged as they happen. The channel
+        // only closes if the MDNS thread stops, after which the APIs are still
+        // served to clients which know where to find them.
+        let mdns_receiver = async {
+            while rx.recv().await.is_some() {}
+
+            error!("MDNS advertisement stopped, the registry can no longer be discovered");
+            std::future::pending::<()>().await
+        };
+
+        // Create HTTP service
+        let app = ServiceBuilder::new()
+            .layer(
+                CorsLayer::new()
+                    .allow_methods([Method::GET, Method::POST, Method::DELETE])
+                    .allow_origin(cors::Any)
+                    .expose_headers([
+                        header::LINK,
+                        HeaderName::from_static("x-paging-limit"),
+                        HeaderName::from_static("x-paging-since"),
+                        HeaderName::from_static("x-paging-until"),
+                    ]),
+            )
+            .service(self.service);
+
+        let addr = ([0, 0, 0, 0], self.port).into();
+        let http_server = Server::bind(&addr).serve(Shared::new(app));
+
+        // Remove nodes which stopped sending heartbeats
+        let garbage_collection = async {
+            loop {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+
+                for id in self.registrations.expired().await {
+                    info!("Node {} missed its heartbeats, removing", id);
+                    self.model.remove_node(&id).await;
+                }
+
+                self.registrations.prune(&self.model).await;
+            }
+        };
+
+        tokio::select! {
+            _ = mdns_receiver => {}
+            _ = http_server => {}
+            _ = garbage_collection => {}
+        };
+
+        Ok(())
+    }
+
+    pub fn start_blocking(self) -> Result<(), NmosError> {
+        let rt = Runtime::new().expect("Unable to create Tokio runtime");
+        rt.block_on(self.start())
+    }
+}
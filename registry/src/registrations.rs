@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use nmos_model::resource::ResourceType;
+use nmos_model::{version::APIVersion, Model};
+use tokio::sync::{RwLock, RwLockReadGuard};
+use uuid::Uuid;
+
+/// Time after the last heartbeat when a node is garbage collected.
+pub const HEALTH_TIMEOUT: Duration = Duration::from_secs(12);
+
+/// A resource held by the registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registration {
+    pub type_: ResourceType,
+    /// API version the resource was registered with.
+    pub api: APIVersion,
+}
+
+/// Registry bookkeeping kept alongside the model.
+#[derive(Debug, Default)]
+pub struct Registrations {
+    resources: RwLock<HashMap<Uuid, Registration>>,
+    // Time of the last heartbeat of each node
+    health: RwLock<HashMap<Uuid, Instant>>,
+}
+
+impl Registrations {
+    pub async fn get(&self, id: &Uuid) -> Option<Registration> {
+        self.resources.read().await.get(id).copied()
+    }
+
+    /// All registered resources.
+    pub async fn resources(&self) -> RwLockReadGuard<'_, HashMap<Uuid, Registration>> {
+        self.resources.read().await
+    }
+
+    pub async fn insert(&self, id: Uuid, registration: Registration) {
+        self.resources.write().await.insert(id, registration);
+
+        if registration.type_ == ResourceType::Node {
+            self.heartbeat(&id).await;
+        }
+    }
+
+    pub async fn remove(&self, id: &Uuid) {
+        self.resources.write().await.remove(id);
+        self.health.write().await.remove(id);
+    }
+
+    pub async fn heartbeat(&self, id: &Uuid) {
+        self.health.write().await.insert(*id, Instant::now());
+    }
+
+    /// Nodes which have not sent a heartbeat within the health timeout.
+    pub async fn expired(&self) -> Vec<Uuid> {
+        self.health
+            .read()
+            .await
+            .iter()
+            .filter(|(_, last)| last.elapsed() > HEALTH_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Forget resources which are no longer in the model.
+    pub async fn prune(&self, model: &Model) {
+        let nodes = model.nodes().await;
+        let devices = model.devices().await;
+        let sources = model.sources().await;
+        let flows = model.flows().await;
+        let senders = model.senders().await;
+        let receivers = model.receivers().await;
+
+        self.resources
+            .write()
+            .await
+            .retain(|id, registration| match registration.type_ {
+                ResourceType::Node => nodes.contains_key(id),
+                ResourceType::Device => devices.contains_key(id),
+                ResourceType::Source => sources.contains_key(id),
+                ResourceType::Flow => flows.contains_key(id),
+                ResourceType::Sender => senders.contains_key(id),
+                ResourceType::Receiver => receivers.contains_key(id),
+            });
+
+        self.health
+            .write()
+            .await
+            .retain(|id, _| nodes.contains_key(id));
+    }
+}
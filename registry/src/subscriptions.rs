@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use nmos_model::{
+    resource::ResourceType,
+    version::{is_04::V1_3, APIVersion},
+};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Time a non-persistent subscription is kept without any WebSocket clients.
+pub const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Rate limit of subscriptions which do not request one.
+pub const DEFAULT_MAX_UPDATE_RATE_MS: u64 = 100;
+
+/// A Query API subscription to changes in resources of one type.
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub id: Uuid,
+    pub api: APIVersion,
+    pub resource_type: ResourceType,
+    /// Basic query filters applied to the resources.
+    pub params: Vec<(String, String)>,
+    pub max_update_rate_ms: u64,
+    pub persist: bool,
+    pub secure: bool,
+    pub authorization: bool,
+    pub ws_href: String,
+}
+
+impl Subscription {
+    /// Topic of the grains sent to the subscription.
+    #[must_use]
+    pub fn resource_path(&self) -> String {
+        format!("/{}s", self.resource_type)
+    }
+
+    /// Whether a subscription request asks for the same updates.
+    #[must_use]
+    pub fn is_equivalent(&self, other: &Subscription) -> bool {
+        self.api == other.api
+            && self.resource_type == other.resource_type
+            && self.params == other.params
+            && self.max_update_rate_ms == other.max_update_rate_ms
+            && self.persist == other.persist
+            && self.secure == other.secure
+            && self.authorization == other.authorization
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        let params: serde_json::Map<String, Value> = self
+            .params
+            .iter()
+            .map(|(key, value)| (key.clone(), json!(value)))
+            .collect();
+
+        let mut json = json!({
+            "id": self.id.to_string(),
+            "ws_href": self.ws_href,
+            "max_update_rate_ms": self.max_update_rate_ms,
+            "persist": self.persist,
+            "secure": self.secure,
+            "resource_path": self.resource_path(),
+            "params": params,
+        });
+
+        if self.api == V1_3 {
+            json["authorization"] = json!(self.authorization);
+        }
+
+        json
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    subscription: Subscription,
+    connections: usize,
+    // Time the subscription last had no clients
+    idle_since: Instant,
+}
+
+/// Subscriptions held by a Query API.
+#[derive(Debug)]
+pub struct Subscriptions {
+    /// Identifies the Query API as the source of grains.
+    source_id: Uuid,
+    entries: RwLock<HashMap<Uuid, Entry>>,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self {
+            source_id: Uuid::new_v4(),
+            entries: RwLock::default(),
+        }
+    }
+}
+
+impl Subscriptions {
+    #[must_use]
+    pub fn source_id(&self) -> Uuid {
+        self.source_id
+    }
+
+    pub async fn get(&self, id: &Uuid) -> Option<Subscription> {
+        self.entries
+            .read()
+            .await
+            .get(id)
+            .map(|entry| entry.subscription.clone())
+    }
+
+    pub async fn list(&self) -> Vec<Subscription> {
+        self.entries
+            .read()
+            .await
+            .values()
+            .map(|entry| entry.subscription.clone())
+            .collect()
+    }
+
+    /// Insert a subscription unless an equivalent one exists, returning the
+    /// subscription in use and whether it was created.
+    pub async fn insert(&self, subscription: Subscription) -> (Subscription, bool) {
+        let mut entries = self.entries.write().await;
+
+        let existing = entries
+            .values()
+            .find(|entry| entry.subscription.is_equivalent(&subscription));
+        if let Some(entry) = existing {
+            return (entry.subscription.clone(), false);
+        }
+
+        entries.insert(
+            subscription.id,
+            Entry {
+                subscription: subscription.clone(),
+                connections: 0,
+                idle_since: Instant::now(),
+            },
+        );
+
+        (subscription, true)
+    }
+
+    pub async fn remove(&self, id: &Uuid) -> Option<Subscription> {
+        self.entries
+            .write()
+            .await
+            .remove(id)
+            .map(|entry| entry.subscription)
+    }
+
+    /// Record a WebSocket client connecting to a subscription.
+    pub async fn connect(&self, id: &Uuid) -> Option<()> {
+        let mut entries = self.entries.write().await;
+        let entry = entries.get_mut(id)?;
+        entry.connections += 1;
+
+        Some(())
+    }
+
+    /// Record a WebSocket client disconnecting from a subscription.
+    ///
+    /// A non-persistent subscription is removed with its last client.
+    pub async fn disconnect(&self, id: &Uuid) {
+        let mut entries = self.entries.write().await;
+
+        let remove = match entries.get_mut(id) {
+            Some(entry) => {
+                entry.connections = entry.connections.saturating_sub(1);
+                entry.idle_since = Instant::now();
+                entry.connections == 0 && !entry.subscription.persist
+            }
+            None => false,
+        };
+
+        if remove {
+            entries.remove(id);
+        }
+    }
+
+    /// Remove non-persistent subscriptions left without clients.
+    pub async fn prune(&self) {
+        self.entries.write().await.retain(|_, entry| {
+            entry.subscription.persist
+                || entry.connections > 0
+                || entry.idle_since.elapsed() < SUBSCRIPTION_TIMEOUT
+        });
+    }
+}